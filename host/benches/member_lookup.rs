@@ -0,0 +1,120 @@
+// Tap-to-decision cost with 5,000 members: the indexed lookup against re-parsing the list and
+// scanning it on every tap, which is what the firmware used to do. The hashed lookup adds the
+// HMAC of the presented UID.
+//
+//     cargo bench --bench member_lookup
+
+use chrono::NaiveDate;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use host::{
+    card_hash::{CardId, CardKey},
+    card_uid::CardUid,
+    members::{Member, MemberDb, MemberIndex, Role},
+};
+
+const MEMBERS: usize = 5_000;
+
+// Deterministic UIDs: 7 byte NXP style for most members, 4 byte for every third one
+fn make_db() -> MemberDb {
+    let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    let members = (0..MEMBERS)
+        .map(|i| {
+            let bytes = next().to_le_bytes();
+            let uid = match i % 3 {
+                0 => CardUid::from_bytes(&bytes[..4]),
+                _ => CardUid::from_bytes(&[&[0x04], &bytes[..6]].concat()),
+            };
+
+            Member {
+                name: format!("Member {}", i),
+                cards: vec![uid.unwrap().into()],
+                role: Role::Member,
+                valid_from: NaiveDate::from_ymd_opt(2024, 1, 1).map(Into::into),
+                valid_until: NaiveDate::from_ymd_opt(2030, 12, 31).map(Into::into),
+                remaining_uses: None,
+                notes: String::new(),
+                schedule: None,
+                pin: None,
+            }
+        })
+        .collect();
+
+    MemberDb {
+        members,
+        access: Default::default(),
+    }
+}
+
+fn linear_find<'a>(db: &'a MemberDb, uid: &CardUid) -> Option<&'a Member> {
+    let card = CardId::Uid(*uid);
+
+    db.members
+        .iter()
+        .find(|member| member.cards.iter().any(|entry| entry.card == card))
+}
+
+fn member_lookup(c: &mut Criterion) {
+    let db = make_db();
+    let json = db.to_json().unwrap();
+    let index = MemberIndex::new(db.clone(), None);
+    let key = CardKey::from_bytes(&[0x5A; 32]);
+    let hashed_index = MemberIndex::new(db.clone(), key.clone());
+    let now = NaiveDate::from_ymd_opt(2026, 6, 1).and_then(|date| date.and_hms_opt(12, 0, 0));
+
+    // Worst case for the scan: the last member, and a card nobody holds
+    let CardId::Uid(last) = db.members[MEMBERS - 1].cards[0].card else {
+        unreachable!()
+    };
+    let unknown = CardUid::from_bytes(&[0x04, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]).unwrap();
+
+    let mut group = c.benchmark_group("member_lookup_5000");
+
+    group.bench_function("indexed_hit", |b| {
+        b.iter(|| {
+            index
+                .find(black_box(&last))
+                .is_some_and(|member| member.is_valid_at(now))
+        })
+    });
+
+    group.bench_function("hashed_hit", |b| {
+        b.iter(|| {
+            hashed_index
+                .find(black_box(&last))
+                .is_some_and(|member| member.is_valid_at(now))
+        })
+    });
+
+    group.bench_function("indexed_miss", |b| {
+        b.iter(|| index.find(black_box(&unknown)).is_some())
+    });
+
+    group.bench_function("linear_hit", |b| {
+        b.iter(|| linear_find(&db, black_box(&last)).is_some_and(|member| member.is_valid_at(now)))
+    });
+
+    group.bench_function("reparse_and_scan", |b| {
+        b.iter(|| {
+            let db = MemberDb::from_json(black_box(&json)).unwrap();
+
+            linear_find(&db, &last).is_some_and(|member| member.is_valid_at(now))
+        })
+    });
+
+    // What a changed members.json costs once
+    group.bench_function("reload_index", |b| {
+        b.iter(|| MemberIndex::new(MemberDb::from_json(black_box(&json)).unwrap(), None))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, member_lookup);
+criterion_main!(benches);
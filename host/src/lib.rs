@@ -0,0 +1,33 @@
+// Firmware modules with no ESP-IDF dependencies. They are private to the firmware binary, so
+// lints that only apply to a public API are allowed here.
+#[path = "../../rfid-scanner-attempt-1/src/auth/backend.rs"]
+pub mod backend;
+#[path = "../../rfid-scanner-attempt-1/src/card_hash.rs"]
+pub mod card_hash;
+#[path = "../../rfid-scanner-attempt-1/src/card_uid.rs"]
+#[allow(clippy::len_without_is_empty)]
+pub mod card_uid;
+#[path = "../../rfid-scanner-attempt-1/src/credential.rs"]
+pub mod credential;
+#[path = "../../rfid-scanner-attempt-1/src/hex.rs"]
+pub mod hex;
+#[path = "../../rfid-scanner-attempt-1/src/limiter.rs"]
+pub mod limiter;
+#[path = "../../rfid-scanner-attempt-1/src/list_key.rs"]
+pub mod list_key;
+#[path = "../../rfid-scanner-attempt-1/src/members.rs"]
+pub mod members;
+#[path = "../../rfid-scanner-attempt-1/src/ndef.rs"]
+pub mod ndef;
+#[path = "../../rfid-scanner-attempt-1/src/pin.rs"]
+pub mod pin;
+#[allow(async_fn_in_trait)]
+pub mod rfid;
+#[path = "../../rfid-scanner-attempt-1/src/schedule.rs"]
+pub mod schedule;
+pub mod spiffs;
+#[path = "../../rfid-scanner-attempt-1/src/tz.rs"]
+pub mod tz;
+#[path = "../../rfid-scanner-attempt-1/src/uses.rs"]
+pub mod uses;
+pub mod wiegand;
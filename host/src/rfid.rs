@@ -0,0 +1,23 @@
+// The hardware-free half of rfid.rs, laid out the same so `super::` paths resolve.
+#[path = "../../rfid-scanner-attempt-1/src/rfid/debounce.rs"]
+pub mod debounce;
+#[path = "../../rfid-scanner-attempt-1/src/rfid/desfire.rs"]
+pub mod desfire;
+#[path = "../../rfid-scanner-attempt-1/src/rfid/health.rs"]
+pub mod health;
+#[path = "../../rfid-scanner-attempt-1/src/rfid/iso14443.rs"]
+pub mod iso14443;
+#[path = "../../rfid-scanner-attempt-1/src/rfid/iso14443_4.rs"]
+pub mod iso14443_4;
+#[path = "../../rfid-scanner-attempt-1/src/rfid/mifare.rs"]
+pub mod mifare;
+#[path = "../../rfid-scanner-attempt-1/src/rfid/mock.rs"]
+pub mod mock;
+#[path = "../../rfid-scanner-attempt-1/src/rfid/ntag.rs"]
+pub mod ntag;
+#[path = "../../rfid-scanner-attempt-1/src/rfid/presence.rs"]
+pub mod presence;
+#[path = "../../rfid-scanner-attempt-1/src/rfid/reader.rs"]
+pub mod reader;
+
+pub use reader::CardReader;
@@ -0,0 +1,52 @@
+// Stand-in for spiffs.rs, with the files in a directory on the host. Each thread has its own,
+// set with `Spiffs::use_dir`, so tests running side by side don't see each other's files.
+use anyhow::bail;
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+thread_local! {
+    static DIR: RefCell<PathBuf> = RefCell::new(std::env::temp_dir());
+}
+
+pub struct Spiffs {}
+
+impl Spiffs {
+    pub fn use_dir(dir: &Path) {
+        DIR.with(|root| *root.borrow_mut() = dir.to_path_buf());
+    }
+
+    fn path(path: &str) -> PathBuf {
+        DIR.with(|root| root.borrow().join(path))
+    }
+
+    pub fn read_string(path: String) -> anyhow::Result<String> {
+        Ok(fs::read_to_string(Spiffs::path(&path))?)
+    }
+
+    pub fn stamp(path: &str) -> Option<(Option<SystemTime>, u64)> {
+        let metadata = fs::metadata(Spiffs::path(path)).ok()?;
+
+        Some((metadata.modified().ok(), metadata.len()))
+    }
+
+    pub fn create(path: &str) -> anyhow::Result<fs::File> {
+        Ok(fs::File::create(Spiffs::path(path))?)
+    }
+
+    pub fn remove(path: &str) -> anyhow::Result<()> {
+        Ok(fs::remove_file(Spiffs::path(path))?)
+    }
+
+    // Like SPIFFS, refuses to rename over a file
+    pub fn rename(from: &str, to: &str) -> anyhow::Result<()> {
+        if Spiffs::path(to).exists() {
+            bail!("rename Error: {} exists", to);
+        }
+
+        Ok(fs::rename(Spiffs::path(from), Spiffs::path(to))?)
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::anyhow;
+use host::{
+    backend::{AuthBackend, AuthDecision, BackendChain, Outcome, Policy, Reason, StaticList},
+    card_uid::CardUid,
+};
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+const UNLOCK: Duration = Duration::from_secs(5);
+
+enum Answer {
+    Grant(&'static str),
+    Deny(Reason),
+    Unknown,
+    Fail,
+}
+
+// Gives the same answer for every card and counts how often it was asked.
+struct Mock {
+    name: &'static str,
+    answer: Answer,
+    online: bool,
+    asked: Rc<Cell<usize>>,
+}
+
+impl AuthBackend for Mock {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn is_online(&self) -> bool {
+        self.online
+    }
+
+    fn decide(&mut self, _code: &CardUid) -> anyhow::Result<Option<AuthDecision>> {
+        self.asked.set(self.asked.get() + 1);
+
+        match &self.answer {
+            Answer::Grant(name) => Ok(Some(AuthDecision::granted(name, UNLOCK))),
+            Answer::Deny(reason) => Ok(Some(AuthDecision::denied(reason.clone()))),
+            Answer::Unknown => Ok(None),
+            Answer::Fail => Err(anyhow!("unreachable")),
+        }
+    }
+}
+
+fn mock(name: &'static str, answer: Answer) -> (Mock, Rc<Cell<usize>>) {
+    let asked = Rc::new(Cell::new(0));
+
+    let mock = Mock {
+        name,
+        answer,
+        online: true,
+        asked: asked.clone(),
+    };
+
+    (mock, asked)
+}
+
+fn uid(s: &str) -> CardUid {
+    s.parse().unwrap()
+}
+
+fn card() -> CardUid {
+    uid("04:A2:3B:C1:5E:61:80")
+}
+
+#[test]
+fn first_answer_wins() {
+    let (server, _) = mock("server", Answer::Deny(Reason::Expired));
+    let (local, local_asked) = mock("local", Answer::Grant("Ada"));
+
+    let mut chain = BackendChain::new();
+    chain.push(Policy::FirstAnswer, server);
+    chain.push(Policy::FirstAnswer, local);
+
+    let decision = chain.decide(&card());
+
+    assert_eq!(decision.outcome, Outcome::Denied);
+    assert_eq!(decision.reason, Reason::Expired);
+    assert_eq!(decision.source, Some("server"));
+    assert_eq!(local_asked.get(), 0);
+}
+
+#[test]
+fn unknown_card_moves_on() {
+    let (allow, _) = mock("allow", Answer::Unknown);
+    let (local, _) = mock("local", Answer::Grant("Ada"));
+
+    let mut chain = BackendChain::new();
+    chain.push(Policy::FirstAnswer, allow);
+    chain.push(Policy::FirstAnswer, local);
+
+    let decision = chain.decide(&card());
+
+    assert!(decision.is_granted());
+    assert_eq!(decision.member, "Ada");
+    assert_eq!(decision.reason, Reason::Member);
+    assert_eq!(decision.source, Some("local"));
+}
+
+#[test]
+fn nobody_knows_the_card() {
+    let (a, _) = mock("a", Answer::Unknown);
+    let (b, _) = mock("b", Answer::Unknown);
+
+    let mut chain = BackendChain::new();
+    chain.push(Policy::FirstAnswer, a);
+    chain.push(Policy::IfOnline, b);
+
+    assert_eq!(
+        chain.decide(&card()),
+        AuthDecision::denied(Reason::UnknownCard)
+    );
+    assert_eq!(
+        BackendChain::new().decide(&card()),
+        AuthDecision::denied(Reason::UnknownCard)
+    );
+}
+
+#[test]
+fn failure_falls_through() {
+    let (server, server_asked) = mock("server", Answer::Fail);
+    let (local, _) = mock("local", Answer::Grant("Ada"));
+
+    let mut chain = BackendChain::new();
+    chain.push(Policy::IfOnline, server);
+    chain.push(Policy::FirstAnswer, local);
+
+    assert!(chain.decide(&card()).is_granted());
+    assert_eq!(server_asked.get(), 1);
+}
+
+#[test]
+fn failure_with_no_other_answer() {
+    let (server, _) = mock("server", Answer::Fail);
+    let (local, _) = mock("local", Answer::Unknown);
+
+    let mut chain = BackendChain::new();
+    chain.push(Policy::IfOnline, server);
+    chain.push(Policy::FirstAnswer, local);
+
+    let decision = chain.decide(&card());
+
+    assert_eq!(decision.reason, Reason::ServerError);
+    assert_eq!(decision.source, None);
+}
+
+#[test]
+fn offline_server_is_skipped() {
+    let (mut server, server_asked) = mock("server", Answer::Deny(Reason::Expired));
+    server.online = false;
+    let (local, _) = mock("local", Answer::Grant("Ada"));
+
+    let mut chain = BackendChain::new();
+    chain.push(Policy::IfOnline, server);
+    chain.push(Policy::FirstAnswer, local);
+
+    assert!(chain.decide(&card()).is_granted());
+    assert_eq!(server_asked.get(), 0);
+}
+
+#[test]
+fn offline_only_matters_to_if_online() {
+    let (mut server, server_asked) = mock("server", Answer::Deny(Reason::Expired));
+    server.online = false;
+
+    let mut chain = BackendChain::new();
+    chain.push(Policy::FirstAnswer, server);
+
+    assert_eq!(chain.decide(&card()).reason, Reason::Expired);
+    assert_eq!(server_asked.get(), 1);
+}
+
+#[test]
+fn deny_list_overrides_from_anywhere() {
+    let (server, server_asked) = mock("server", Answer::Grant("Ada"));
+    let (deny, _) = mock("deny", Answer::Deny(Reason::Blocked));
+
+    // Listed last, still asked first
+    let mut chain = BackendChain::new();
+    chain.push(Policy::IfOnline, server);
+    chain.push(Policy::DenyOverrides, deny);
+
+    let decision = chain.decide(&card());
+
+    assert_eq!(decision.reason, Reason::Blocked);
+    assert_eq!(decision.source, Some("deny"));
+    assert_eq!(server_asked.get(), 0);
+}
+
+#[test]
+fn deny_list_grants_are_ignored() {
+    let (deny, deny_asked) = mock("deny", Answer::Grant("Mallory"));
+    let (failing, _) = mock("failing", Answer::Fail);
+    let (local, _) = mock("local", Answer::Deny(Reason::OutsideSchedule));
+
+    let mut chain = BackendChain::new();
+    chain.push(Policy::DenyOverrides, deny);
+    chain.push(Policy::DenyOverrides, failing);
+    chain.push(Policy::FirstAnswer, local);
+
+    assert_eq!(chain.decide(&card()).reason, Reason::OutsideSchedule);
+
+    // Not asked a second time with the rest of the chain
+    assert_eq!(deny_asked.get(), 1);
+}
+
+#[test]
+fn static_lists() {
+    let lost = uid("04:11:22:33");
+    let visitor = uid("04:44:55:66");
+
+    let mut chain = BackendChain::new();
+    chain.push(
+        Policy::FirstAnswer,
+        StaticList::allow(vec![(visitor, "Visitor".to_string())], UNLOCK),
+    );
+    chain.push(
+        Policy::DenyOverrides,
+        StaticList::deny(vec![(lost, "Lost card".to_string())]),
+    );
+
+    let decision = chain.decide(&visitor);
+
+    assert!(decision.is_granted());
+    assert_eq!(decision.member, "Visitor");
+    assert_eq!(decision.unlock, UNLOCK);
+    assert_eq!(decision.source, Some("allow list"));
+
+    let decision = chain.decide(&lost);
+
+    assert_eq!(decision.outcome, Outcome::Denied);
+    assert_eq!(decision.member, "Lost card");
+    assert_eq!(decision.reason, Reason::Blocked);
+    assert_eq!(decision.source, Some("deny list"));
+
+    assert_eq!(chain.decide(&card()).reason, Reason::UnknownCard);
+}
+
+#[test]
+fn deny_list_without_a_reason() {
+    let (blocked, _) = mock("deny list", Answer::Deny(Reason::from_server(" ")));
+    let (local, local_asked) = mock("local", Answer::Grant("Ada"));
+
+    let mut chain = BackendChain::new();
+    chain.push(Policy::FirstAnswer, local);
+    chain.push(Policy::DenyOverrides, blocked);
+
+    let decision = chain.decide(&card());
+
+    assert_eq!(decision.outcome, Outcome::Denied);
+    assert_eq!(decision.reason, Reason::Denied);
+    assert_eq!(decision.reason.to_string(), "no reason given");
+    assert_eq!(decision.source, Some("deny list"));
+    assert_eq!(local_asked.get(), 0);
+}
+
+#[test]
+fn reasons_read_as_spoken() {
+    assert_eq!(Reason::OutsideSchedule.to_string(), "outside access times");
+    assert_eq!(Reason::from_server("membership expired"), Reason::Expired);
+    assert_eq!(Reason::from_server(" unknown card\n"), Reason::UnknownCard);
+    assert_eq!(Reason::from_server(""), Reason::Denied);
+    assert_eq!(Reason::from_server(" \t"), Reason::Denied);
+    assert_eq!(
+        Reason::from_server("board meeting").to_string(),
+        "board meeting"
+    );
+}
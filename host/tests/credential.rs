@@ -0,0 +1,229 @@
+use host::{
+    card_uid::CardUid,
+    credential::{MacKey, MemberCredential},
+    rfid::{
+        mifare::{self, AccessBits, KeyType, DEFAULT_KEY},
+        mock::{MockCard, MockReader},
+        CardReader,
+    },
+};
+
+// Where and how the firmware keeps the credential, see MIFARE_SECTOR in common.rs
+const SECTOR: u8 = 1;
+const KEY_TYPE: KeyType = KeyType::B;
+
+fn key() -> MacKey {
+    MacKey::from_bytes(b"0123456789abcdef").unwrap()
+}
+
+fn uid(s: &str) -> CardUid {
+    s.parse().unwrap()
+}
+
+fn card() -> CardUid {
+    uid("04:A2:3B:C1:5E:61:80")
+}
+
+#[test]
+fn mac_matches_hmac_sha256() {
+    // HMAC-SHA256(key, 04A23BC15E6180 0000002A), first 8 bytes
+    let credential = MemberCredential::issue(&key(), &card(), 42);
+
+    assert_eq!(
+        credential.mac,
+        [0x28, 0xb6, 0x6c, 0x89, 0x0b, 0xa4, 0x07, 0x21]
+    );
+}
+
+#[test]
+fn credential_verifies_on_its_own_card() {
+    let credential = MemberCredential::issue(&key(), &card(), 42);
+
+    assert!(credential.verify(&key(), &card()));
+
+    // Copied onto another card
+    assert!(!credential.verify(&key(), &uid("DE:AD:BE:EF")));
+
+    // Issued with another key
+    let other = MacKey::from_bytes(b"fedcba9876543210").unwrap();
+    assert!(!credential.verify(&other, &card()));
+
+    // Member id edited on the card
+    let edited = MemberCredential {
+        member_id: 43,
+        ..credential
+    };
+    assert!(!edited.verify(&key(), &card()));
+}
+
+#[test]
+fn credential_block_layout() {
+    let credential = MemberCredential::issue(&key(), &card(), 0x0102_0304);
+    let block = credential.to_block();
+
+    assert_eq!(block[0], 0x01);
+    assert_eq!(block[1..5], [0x01, 0x02, 0x03, 0x04]);
+    assert_eq!(block[5..13], credential.mac);
+    assert_eq!(block[13..], [0, 0, 0]);
+
+    assert_eq!(MemberCredential::from_block(&block), Some(credential));
+
+    // Blank or unknown version
+    assert_eq!(MemberCredential::from_block(&[0; 16]), None);
+    assert_eq!(MemberCredential::from_block(&[0x02; 16]), None);
+}
+
+#[test]
+fn mac_key_length() {
+    assert!(MacKey::from_bytes(&[0; 15]).is_none());
+    assert!(MacKey::from_bytes(&[0; 16]).is_some());
+    assert!(MacKey::from_bytes(&[0; 64]).is_some());
+    assert!(MacKey::from_bytes(&[0; 65]).is_none());
+
+    // Never printed
+    assert_eq!(format!("{:?}", key()), "MacKey(16 bytes)");
+}
+
+#[test]
+fn sector_layout() {
+    assert_eq!(mifare::first_block(SECTOR), 4);
+    assert_eq!(mifare::trailer_block(SECTOR), 7);
+    assert!(mifare::is_trailer(7));
+    assert!(!mifare::is_trailer(4));
+
+    // The large sectors of a 4K card
+    assert_eq!(mifare::first_block(32), 128);
+    assert_eq!(mifare::trailer_block(32), 143);
+    assert_eq!(mifare::sector_of(143), 32);
+    assert_eq!(mifare::sector_of(200), 36);
+    assert_eq!(mifare::trailer_block(39), 255);
+}
+
+#[test]
+fn transport_access_bits() {
+    let trailer = mifare::transport_trailer();
+    let groups = mifare::decode_access_bits(&trailer).unwrap();
+
+    let open = AccessBits {
+        c1: false,
+        c2: false,
+        c3: false,
+    };
+
+    assert_eq!(groups[..3], [open; 3]);
+    assert_eq!(
+        groups[3],
+        AccessBits {
+            c1: false,
+            c2: false,
+            c3: true,
+        }
+    );
+    assert_eq!(mifare::encode_access_bits(&groups), [0xFF, 0x07, 0x80]);
+
+    // The inverted copy no longer matches
+    let mut broken = trailer;
+    broken[7] ^= 0x10;
+    assert_eq!(mifare::decode_access_bits(&broken), None);
+}
+
+#[test]
+fn access_bits_round_trip() {
+    let groups = [
+        AccessBits {
+            c1: true,
+            c2: false,
+            c3: false,
+        },
+        AccessBits {
+            c1: false,
+            c2: true,
+            c3: true,
+        },
+        AccessBits {
+            c1: true,
+            c2: true,
+            c3: true,
+        },
+        AccessBits {
+            c1: false,
+            c2: true,
+            c3: true,
+        },
+    ];
+
+    let mut trailer = mifare::transport_trailer();
+    trailer[6..9].copy_from_slice(&mifare::encode_access_bits(&groups));
+
+    assert_eq!(mifare::decode_access_bits(&trailer), Some(groups));
+
+    // Readable with either key, written only with key B
+    assert!(mifare::can_read(groups[0], KeyType::A));
+    assert!(!mifare::can_write(groups[0], KeyType::A));
+    assert!(mifare::can_write(groups[0], KeyType::B));
+
+    // Key B only
+    assert!(!mifare::can_read(groups[1], KeyType::A));
+    assert!(mifare::can_read(groups[1], KeyType::B));
+
+    // Locked
+    assert!(!mifare::can_read(groups[2], KeyType::B));
+    assert!(!mifare::can_write(groups[2], KeyType::B));
+}
+
+#[test]
+fn credential_written_and_read_back() {
+    let (mut reader, field) = MockReader::new();
+    let block = mifare::first_block(SECTOR);
+
+    field.present(MockCard::new(card()));
+    reader.poll().unwrap();
+
+    let credential = MemberCredential::issue(&key(), &card(), 42);
+
+    reader.authenticate(block, KEY_TYPE, &DEFAULT_KEY).unwrap();
+    reader.write_block(block, &credential.to_block()).unwrap();
+    reader.stop_crypto().unwrap();
+
+    // Taken away and tapped again
+    let tapped = field.remove().unwrap();
+    assert_eq!(tapped.blocks[block as usize], credential.to_block());
+
+    field.present(tapped);
+    reader.poll().unwrap();
+
+    reader.authenticate(block, KEY_TYPE, &DEFAULT_KEY).unwrap();
+    let read = MemberCredential::from_block(&reader.read_block(block).unwrap()).unwrap();
+
+    assert_eq!(read, credential);
+    assert!(read.verify(&key(), &card()));
+}
+
+#[test]
+fn blocks_need_the_right_key() {
+    let (mut reader, field) = MockReader::new();
+    let block = mifare::first_block(SECTOR);
+
+    field.present(MockCard::new(card()));
+    reader.poll().unwrap();
+
+    assert!(reader
+        .authenticate(block, KEY_TYPE, &[0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5])
+        .is_err());
+    assert!(reader.read_block(block).is_err());
+
+    // Authenticated to another sector
+    reader.authenticate(0, KEY_TYPE, &DEFAULT_KEY).unwrap();
+    assert!(reader.read_block(block).is_err());
+
+    reader.authenticate(block, KEY_TYPE, &DEFAULT_KEY).unwrap();
+    assert!(reader.read_block(block).is_ok());
+
+    // Key A never reads back from the trailer
+    let trailer = reader.read_block(mifare::trailer_block(SECTOR)).unwrap();
+    assert_eq!(trailer[0..6], [0; 6]);
+    assert_eq!(trailer[6..9], [0xFF, 0x07, 0x80]);
+
+    reader.stop_crypto().unwrap();
+    assert!(reader.write_block(block, &[0; 16]).is_err());
+}
@@ -0,0 +1,322 @@
+use host::rfid::{
+    desfire::{self, Desfire, DesfireError, Session},
+    iso14443_4::{Ats, IsoDep, Transceive},
+};
+use std::collections::VecDeque;
+
+// Frames a card sent back, each for the frame the reader had to send. Anything else fails the
+// test, so the firmware's side of the exchange is checked byte for byte.
+struct Transcript {
+    steps: VecDeque<(Vec<u8>, Vec<u8>)>,
+    timeouts: Vec<u32>,
+}
+
+impl Transcript {
+    fn new(steps: &[(&str, &str)]) -> Transcript {
+        Transcript {
+            steps: steps
+                .iter()
+                .map(|(sent, reply)| (hex(sent), hex(reply)))
+                .collect(),
+            timeouts: Vec::new(),
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl Transceive for Transcript {
+    fn transceive(&mut self, frame: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let (expected, reply) = self.steps.pop_front().expect("frame after the transcript");
+
+        assert_eq!(
+            frame,
+            &expected[..],
+            "sent {:02X?}, expected {:02X?}",
+            frame,
+            expected
+        );
+
+        Ok(reply)
+    }
+
+    fn set_timeout_ms(&mut self, ms: u32) -> anyhow::Result<()> {
+        self.timeouts.push(ms);
+
+        Ok(())
+    }
+}
+
+fn hex(s: &str) -> Vec<u8> {
+    let s = s.replace(' ', "");
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn block(s: &str) -> [u8; 16] {
+    hex(s).try_into().unwrap()
+}
+
+// DESFire EV1 ATS: FSCI 5 (64 bytes), FWI 8
+const RATS: (&str, &str) = ("E0 50", "06 75 77 81 02 80");
+
+// Card key 000102..0F, RndA A0..AF, RndB B0..BF, TI 9D00C4DF. Card side computed with
+// Python's `cryptography` package.
+const KEY: &str = "000102030405060708090A0B0C0D0E0F";
+const RND_A: &str = "A0A1A2A3A4A5A6A7A8A9AAABACADAEAF";
+
+const SELECT: (&str, &str) = ("02 905A0000 03 01484C 00", "02 9100");
+const AUTH_FIRST: (&str, &str) = (
+    "03 90710000 02 0100 00",
+    "03 e315209ed0e7c94f74a65c99f6eadc1e 91AF",
+);
+const AUTH_SECOND: &str = "02 90AF0000 20 \
+     5e18d1fef61d087ec0a33ed734a7918f8c0b35f96f9640ab67d536c876626e70 00";
+const AUTH_ANSWER: &str = "02 \
+     d5f1fb830482df812ae7d25a3501c0d42d0c208e18d80ec14262f4db6289aa8c 9100";
+
+#[test]
+fn ev2_first_authentication() {
+    let mut link = Transcript::new(&[
+        RATS,
+        SELECT,
+        AUTH_FIRST,
+        (AUTH_SECOND, AUTH_ANSWER),
+        ("C2", "C2"),
+    ]);
+
+    let mut card = Desfire::new(IsoDep::activate(&mut link).unwrap());
+
+    card.select_application(0x4C4801).unwrap();
+
+    let session = card
+        .authenticate_ev2_first(0x01, &block(KEY), block(RND_A))
+        .unwrap();
+
+    assert_eq!(
+        session,
+        Session {
+            ti: [0x9D, 0x00, 0xC4, 0xDF],
+            enc_key: block("9d1f137e3127a91faf000eb89c0e02bb"),
+            mac_key: block("5e3386fa23ac3c5ff6706fc46ac1a1bc"),
+        }
+    );
+
+    card.into_inner().deselect().unwrap();
+
+    assert!(link.finished());
+
+    // FWT for FWI 8
+    assert_eq!(link.timeouts, vec![82]);
+}
+
+#[test]
+fn card_that_gets_rnd_a_wrong() {
+    // The answer holds RndA itself rather than RndA rotated
+    let mut link = Transcript::new(&[
+        RATS,
+        SELECT,
+        AUTH_FIRST,
+        (
+            AUTH_SECOND,
+            "02 b211f6d5ae4f529f883bcfc7fe14b71bf56aa35f185516eb026070cb7356f809 9100",
+        ),
+    ]);
+
+    let mut card = Desfire::new(IsoDep::activate(&mut link).unwrap());
+    card.select_application(0x4C4801).unwrap();
+
+    let err = card
+        .authenticate_ev2_first(0x01, &block(KEY), block(RND_A))
+        .unwrap_err();
+
+    assert_eq!(err.downcast_ref(), Some(&DesfireError(0xAE)));
+}
+
+#[test]
+fn card_with_wrong_key() {
+    // The card refuses the reader's token
+    let mut link = Transcript::new(&[RATS, SELECT, AUTH_FIRST, (AUTH_SECOND, "02 91AE")]);
+
+    let mut card = Desfire::new(IsoDep::activate(&mut link).unwrap());
+    card.select_application(0x4C4801).unwrap();
+
+    let err = card
+        .authenticate_ev2_first(0x01, &block(KEY), block(RND_A))
+        .unwrap_err();
+
+    assert_eq!(err.downcast_ref(), Some(&DesfireError(0xAE)));
+    assert_eq!(
+        err.to_string(),
+        "DESFire authentication error (AE)".to_string()
+    );
+}
+
+#[test]
+fn missing_application() {
+    let mut link = Transcript::new(&[RATS, (SELECT.0, "02 91A0")]);
+
+    let mut card = Desfire::new(IsoDep::activate(&mut link).unwrap());
+
+    let err = card.select_application(0x4C4801).unwrap_err();
+
+    assert_eq!(err.downcast_ref(), Some(&DesfireError(0xA0)));
+}
+
+#[test]
+fn not_a_desfire_card() {
+    // ISO 7816 "instruction not supported"
+    let mut link = Transcript::new(&[RATS, (SELECT.0, "02 6D00")]);
+
+    let mut card = Desfire::new(IsoDep::activate(&mut link).unwrap());
+
+    assert!(card.select_application(0x4C4801).is_err());
+}
+
+#[test]
+fn native_apdus() {
+    assert_eq!(
+        desfire::wrap_native(0x5A, &[0x01, 0x48, 0x4C]),
+        hex("905A0000 03 01484C 00")
+    );
+    assert_eq!(desfire::wrap_native(0x60, &[]), hex("90600000 00"));
+
+    assert_eq!(
+        desfire::unwrap_response(&hex("0102 91AF")).unwrap(),
+        (0xAF, &[0x01, 0x02][..])
+    );
+    assert!(desfire::unwrap_response(&hex("9000")).is_err());
+    assert!(desfire::unwrap_response(&hex("91")).is_err());
+}
+
+#[test]
+fn an10922_key_diversification() {
+    // The AES-128 example from NXP AN10922
+    let key = desfire::diversify_key(
+        &block("00112233445566778899AABBCCDDEEFF"),
+        &hex("04782E21801D80"),
+        0xF54230,
+        &hex("4E585020416275"),
+    );
+
+    assert_eq!(key, block("A8DD63A3B89D54B37CA802473FDA9175"));
+}
+
+#[test]
+fn nist_cmac() {
+    // NIST SP 800-38B, AES-128 examples 1 and 2
+    let key = block("2b7e151628aed2a6abf7158809cf4f3c");
+
+    assert_eq!(
+        desfire::cmac(&key, &[]),
+        block("bb1d6929e95937287fa37d129b756746")
+    );
+    assert_eq!(
+        desfire::cmac(&key, &hex("6bc1bee22e409f96e93d7e117393172a")),
+        block("070a16b46b4d4144f79bdd9dd04a287c")
+    );
+}
+
+#[test]
+fn aes_cbc_round_trip() {
+    let key = block(KEY);
+    let data = hex(&format!("{}{}", RND_A, KEY));
+
+    let encrypted = desfire::aes_cbc_encrypt(&key, &data);
+
+    assert_ne!(encrypted, data);
+    assert_eq!(desfire::aes_cbc_decrypt(&key, &encrypted), data);
+}
+
+#[test]
+fn ats() {
+    assert_eq!(
+        Ats::parse(&hex("06 75 77 81 02 80")).unwrap(),
+        Ats {
+            fsc: 64,
+            fwi: 8,
+            historical: vec![0x80],
+        }
+    );
+
+    // No T0: FSCI 2, FWI 4
+    assert_eq!(
+        Ats::parse(&hex("01")).unwrap(),
+        Ats {
+            fsc: 32,
+            fwi: 4,
+            historical: vec![],
+        }
+    );
+
+    // Only TB present
+    let ats = Ats::parse(&hex("03 28 E1")).unwrap();
+    assert_eq!((ats.fsc, ats.fwi), (256, 14));
+
+    assert!(Ats::parse(&[]).is_err());
+    assert!(Ats::parse(&hex("05 75 77")).is_err());
+    assert!(Ats::parse(&hex("03 75 77")).is_err());
+}
+
+#[test]
+fn chaining_both_ways() {
+    // FSCI 0: the card takes 16 byte frames, so 13 bytes of APDU per block
+    let apdu = (0..30).collect::<Vec<u8>>();
+    let hex_of = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<String>()
+    };
+
+    let mut link = Transcript::new(&[
+        ("E0 50", "02 00"),
+        (&format!("12 {}", hex_of(&apdu[..13])), "A2"),
+        (&format!("13 {}", hex_of(&apdu[13..26])), "A3"),
+        (&format!("02 {}", hex_of(&apdu[26..])), "12 0102"),
+        ("A3", "03 0304 9000"),
+    ]);
+
+    let mut iso = IsoDep::activate(&mut link).unwrap();
+
+    assert_eq!(iso.ats().fsc, 16);
+    assert_eq!(iso.exchange(&apdu).unwrap(), hex("01020304 9000"));
+    assert!(link.finished());
+}
+
+#[test]
+fn waiting_time_extension() {
+    let mut link = Transcript::new(&[
+        RATS,
+        ("02 90600000 00", "F2 02"),
+        ("F2 02", "F2 01"),
+        ("F2 01", "02 9100"),
+    ]);
+
+    let mut iso = IsoDep::activate(&mut link).unwrap();
+
+    assert_eq!(iso.exchange(&hex("90600000 00")).unwrap(), hex("9100"));
+
+    // Stretched for each request and put back afterwards
+    assert_eq!(link.timeouts, vec![82, 164, 82, 82, 82]);
+}
+
+#[test]
+fn unexpected_blocks() {
+    // R(NAK) where an answer was expected
+    let mut link = Transcript::new(&[RATS, ("02 9060000000", "B2")]);
+    let mut iso = IsoDep::activate(&mut link).unwrap();
+
+    assert!(iso.exchange(&hex("9060000000")).is_err());
+
+    // Answer with the wrong block number
+    let mut link = Transcript::new(&[RATS, ("02 9060000000", "03 9100")]);
+    let mut iso = IsoDep::activate(&mut link).unwrap();
+
+    assert!(iso.exchange(&hex("9060000000")).is_err());
+}
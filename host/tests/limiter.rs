@@ -0,0 +1,125 @@
+use host::limiter::{AttemptLimiter, LimiterConfig, LockoutScope};
+use std::time::{Duration, Instant};
+
+const CONFIG: LimiterConfig = LimiterConfig {
+    window: Duration::from_secs(60),
+    per_reader: 3,
+    global: 5,
+    lockout: Duration::from_secs(120),
+};
+
+fn secs(s: u64) -> Duration {
+    Duration::from_secs(s)
+}
+
+#[test]
+fn reader_locks_after_its_limit() {
+    let mut limiter = AttemptLimiter::new(CONFIG);
+    let start = Instant::now();
+
+    assert_eq!(limiter.deny("Outer", start), None);
+    assert_eq!(limiter.deny("Outer", start + secs(1)), None);
+    assert_eq!(
+        limiter.deny("Outer", start + secs(2)),
+        Some(LockoutScope::Reader("Outer"))
+    );
+
+    assert_eq!(
+        limiter.locked("Outer", start + secs(2)),
+        Some((LockoutScope::Reader("Outer"), secs(120)))
+    );
+    assert_eq!(limiter.locked("Inner", start + secs(2)), None);
+}
+
+#[test]
+fn window_slides() {
+    let mut limiter = AttemptLimiter::new(CONFIG);
+    let start = Instant::now();
+
+    limiter.deny("Outer", start);
+    limiter.deny("Outer", start + secs(30));
+
+    // The first denial has left the window by now
+    assert_eq!(limiter.deny("Outer", start + secs(60)), None);
+    assert_eq!(limiter.locked("Outer", start + secs(60)), None);
+
+    assert_eq!(
+        limiter.deny("Outer", start + secs(61)),
+        Some(LockoutScope::Reader("Outer"))
+    );
+}
+
+#[test]
+fn denials_spread_across_readers_lock_them_all() {
+    let mut limiter = AttemptLimiter::new(CONFIG);
+    let start = Instant::now();
+
+    for (i, reader) in ["Outer", "Inner", "Outer", "Inner"].into_iter().enumerate() {
+        assert_eq!(limiter.deny(reader, start + secs(i as u64)), None);
+    }
+
+    assert_eq!(
+        limiter.deny("Wiegand", start + secs(4)),
+        Some(LockoutScope::Global)
+    );
+
+    for reader in ["Outer", "Inner", "Wiegand", "Side"] {
+        assert_eq!(
+            limiter.locked(reader, start + secs(5)),
+            Some((LockoutScope::Global, secs(119)))
+        );
+    }
+}
+
+#[test]
+fn reader_counts_taps_that_trip_the_global_limit() {
+    // Short enough that the reader's denials are still in the window afterwards
+    let mut limiter = AttemptLimiter::new(LimiterConfig {
+        lockout: secs(10),
+        ..CONFIG
+    });
+    let start = Instant::now();
+
+    for (i, reader) in ["Inner", "Inner", "Inner", "Outer"].into_iter().enumerate() {
+        limiter.deny(reader, start + secs(i as u64));
+    }
+
+    // The third denial locked the inside reader and started its count again
+    assert_eq!(
+        limiter.locked("Inner", start + secs(3)),
+        Some((LockoutScope::Reader("Inner"), secs(9)))
+    );
+
+    assert_eq!(
+        limiter.deny("Outer", start + secs(4)),
+        Some(LockoutScope::Global)
+    );
+
+    // Once the global lockout is over, the outside reader is one denial from its own
+    let after = start + secs(14);
+
+    assert_eq!(limiter.locked("Outer", after), None);
+    assert_eq!(
+        limiter.deny("Outer", after),
+        Some(LockoutScope::Reader("Outer"))
+    );
+}
+
+#[test]
+fn lockout_expires() {
+    let mut limiter = AttemptLimiter::new(CONFIG);
+    let start = Instant::now();
+
+    for i in 0..3 {
+        limiter.deny("Outer", start + secs(i));
+    }
+
+    assert_eq!(
+        limiter.locked("Outer", start + secs(121)),
+        Some((LockoutScope::Reader("Outer"), secs(1)))
+    );
+    assert_eq!(limiter.locked("Outer", start + secs(122)), None);
+
+    // The count started again with the lockout
+    assert_eq!(limiter.deny("Outer", start + secs(122)), None);
+}
@@ -0,0 +1,167 @@
+use host::{
+    card_hash::{CardId, CardKey},
+    card_uid::CardUid,
+    members::{CardEntry, MemberDb, MemberIndex, Role},
+};
+
+fn uid(s: &str) -> CardUid {
+    s.parse().unwrap()
+}
+
+fn db() -> MemberDb {
+    MemberDb::from_json(
+        r#"{
+            "members": [{
+                "name": "Ada",
+                "cards": ["04:A2:3B:C1:5E:61:80", {"card": "04:11:22:33:44:55:66", "desfire": true}],
+                "role": "member"
+            }, {
+                "name": "Bob",
+                "cards": [{"card": "DE:AD:BE:EF"}],
+                "role": "admin"
+            }]
+        }"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn card_entries() {
+    let db = db();
+
+    assert_eq!(
+        db.members[0].cards,
+        vec![
+            CardEntry::from(uid("04:A2:3B:C1:5E:61:80")),
+            CardEntry {
+                card: CardId::Uid(uid("04:11:22:33:44:55:66")),
+                desfire: true,
+            },
+        ]
+    );
+
+    // Without the flag it is the same as a plain card
+    assert_eq!(db.members[1].cards, vec![uid("DE:AD:BE:EF").into()]);
+}
+
+#[test]
+fn card_entries_written_back() {
+    let json = db().to_json().unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(
+        value["members"][0]["cards"],
+        serde_json::json!([
+            "04:A2:3B:C1:5E:61:80",
+            {"card": "04:11:22:33:44:55:66", "desfire": true}
+        ])
+    );
+    assert_eq!(
+        value["members"][1]["cards"],
+        serde_json::json!(["DE:AD:BE:EF"])
+    );
+
+    assert_eq!(MemberDb::from_json(&json).unwrap(), db());
+}
+
+#[test]
+fn index_keeps_the_flag() {
+    let key = CardKey::from_bytes(&[0x5A; 32]);
+
+    for key in [None, key] {
+        let index = MemberIndex::new(db(), key);
+
+        let (entry, member) = index.find_entry(&uid("04:11:22:33:44:55:66")).unwrap();
+        assert!(entry.desfire);
+        assert_eq!(member.name, "Ada");
+
+        let (entry, member) = index.find_entry(&uid("04:A2:3B:C1:5E:61:80")).unwrap();
+        assert!(!entry.desfire);
+        assert_eq!(member.name, "Ada");
+
+        assert!(index.find_entry(&uid("01:02:03:04")).is_none());
+    }
+}
+
+#[test]
+fn codes_are_plain_cards() {
+    let db = MemberDb::from_codes("04:A2:3B:C1:5E:61:80\nDE:AD:BE:EF\n", None);
+
+    assert_eq!(db.members.len(), 2);
+    assert!(db.members.iter().all(|member| !member.cards[0].desfire));
+}
+
+#[test]
+fn enrolled_cards() {
+    let mut db = MemberDb::default();
+
+    let name = db.enroll(uid("04:11:22:33:44:55:66"), true, Role::Member, None);
+    assert_eq!(name.as_deref(), Some("04:11:22:33:44:55:66"));
+    assert!(db.members[0].cards[0].desfire);
+
+    db.enroll(uid("DE:AD:BE:EF"), false, Role::Member, None);
+    assert!(!db.members[1].cards[0].desfire);
+
+    // Listed already
+    assert_eq!(
+        db.enroll(uid("DE:AD:BE:EF"), true, Role::Member, None),
+        None
+    );
+
+    assert_eq!(
+        db.unenroll(uid("04:11:22:33:44:55:66"), None).as_deref(),
+        Some("04:11:22:33:44:55:66")
+    );
+    assert_eq!(db.members.len(), 1);
+}
+
+#[test]
+fn guest_pass_dates() {
+    let db = MemberDb::from_json(
+        r#"{
+            "members": [{
+                "name": "Guest",
+                "cards": ["04:A2:3B:C1"],
+                "role": "member",
+                "valid_from": "2026-10-18T19:30",
+                "valid_until": "2026-10-20",
+                "remaining_uses": 3
+            }]
+        }"#,
+    )
+    .unwrap();
+
+    let guest = &db.members[0];
+    let at = |s: &str| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M").unwrap();
+
+    // Written the way members.json has it, for use counts to be keyed by
+    assert_eq!(guest.valid_from.unwrap().to_string(), "2026-10-18T19:30");
+    assert_eq!(guest.valid_until.unwrap().to_string(), "2026-10-20");
+
+    // The end date is inclusive
+    assert!(!guest.has_expired(at("2026-10-20T23:59")));
+    assert!(guest.has_expired(at("2026-10-21T00:00")));
+
+    // Not started isn't expired
+    assert!(!guest.has_expired(at("2026-10-01T00:00")));
+}
+
+#[test]
+fn index_entries() {
+    let index = MemberIndex::new(db(), None);
+
+    let mut entries = index
+        .entries()
+        .map(|(entry, member)| (entry.card.to_string(), member.name.as_str()))
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    assert_eq!(
+        entries,
+        vec![
+            ("04:11:22:33:44:55:66".to_string(), "Ada"),
+            ("04:A2:3B:C1:5E:61:80".to_string(), "Ada"),
+            ("DE:AD:BE:EF".to_string(), "Bob"),
+        ]
+    );
+}
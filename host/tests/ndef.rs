@@ -0,0 +1,368 @@
+use anyhow::bail;
+use host::{
+    card_uid::CardUid,
+    ndef::{parse_message, NdefError, NdefRecord, TagPayload, HACKSPACE_MIME},
+    rfid::{
+        iso14443_4::Transceive,
+        mifare::{KeyType, MifareKey},
+        ntag::{self, TlvScan},
+        CardReader,
+    },
+};
+
+fn text(lang: &str, text: &str) -> NdefRecord {
+    NdefRecord::Text {
+        lang: lang.to_string(),
+        text: text.to_string(),
+    }
+}
+
+// Short record with the given header flags and TNF
+fn record(header: u8, record_type: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![header, record_type.len() as u8, payload.len() as u8];
+    bytes.extend_from_slice(record_type);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+// MB ME SR, well known type
+const ONLY: u8 = 0xD1;
+
+#[test]
+fn text_record() {
+    let message = record(ONLY, b"T", b"\x02enHello");
+
+    assert_eq!(parse_message(&message), Ok(vec![text("en", "Hello")]));
+}
+
+#[test]
+fn utf16_text() {
+    // With a little-endian BOM, and big-endian without one
+    let message = record(ONLY, b"T", b"\x82en\xFF\xFEH\0i\0");
+    assert_eq!(parse_message(&message), Ok(vec![text("en", "Hi")]));
+
+    let message = record(ONLY, b"T", b"\x82en\0H\0i");
+    assert_eq!(parse_message(&message), Ok(vec![text("en", "Hi")]));
+
+    let message = record(ONLY, b"T", b"\x82en\0H\0");
+    assert_eq!(parse_message(&message), Err(NdefError::Text));
+}
+
+#[test]
+fn uri_record() {
+    let message = record(ONLY, b"U", b"\x04example.com");
+    assert_eq!(
+        parse_message(&message),
+        Ok(vec![NdefRecord::Uri("https://example.com".to_string())])
+    );
+
+    // Unknown prefix codes are left off
+    let message = record(ONLY, b"U", b"\x7Fexample.com");
+    assert_eq!(
+        parse_message(&message),
+        Ok(vec![NdefRecord::Uri("example.com".to_string())])
+    );
+}
+
+#[test]
+fn hackspace_records() {
+    // MB ME SR, MIME type
+    let mime = HACKSPACE_MIME.as_bytes();
+
+    assert_eq!(
+        parse_message(&record(0xD2, mime, b"member:abc123")),
+        Ok(vec![NdefRecord::Hackspace(TagPayload::Member(
+            "abc123".to_string()
+        ))])
+    );
+    assert_eq!(
+        parse_message(&record(
+            0xD2,
+            b"Application/VND.LeighHack",
+            b"action: open "
+        )),
+        Ok(vec![NdefRecord::Hackspace(TagPayload::Action(
+            "open".to_string()
+        ))])
+    );
+    assert_eq!(
+        parse_message(&record(0xD2, mime, b"unknown")),
+        Ok(vec![NdefRecord::Mime {
+            mime_type: HACKSPACE_MIME.to_string(),
+            payload: b"unknown".to_vec(),
+        }])
+    );
+    assert_eq!(
+        parse_message(&record(0xD2, b"text/plain", b"hi")),
+        Ok(vec![NdefRecord::Mime {
+            mime_type: "text/plain".to_string(),
+            payload: b"hi".to_vec(),
+        }])
+    );
+}
+
+#[test]
+fn several_records() {
+    // MB on the first, ME on the last, and an external type in between
+    let mut message = record(0x91, b"T", b"\x02enA");
+    message.extend(record(0x14, b"example.com:x", b"\x01"));
+    message.extend(record(0x51, b"T", b"\x02deB"));
+
+    // Anything after ME is ignored
+    message.push(0xD1);
+
+    assert_eq!(
+        parse_message(&message),
+        Ok(vec![
+            text("en", "A"),
+            NdefRecord::Other {
+                tnf: 0x04,
+                record_type: b"example.com:x".to_vec(),
+                payload: vec![0x01],
+            },
+            text("de", "B"),
+        ])
+    );
+}
+
+#[test]
+fn long_record_with_id() {
+    // MB ME IL, 4 byte payload length
+    let message = b"\xC9\x01\x00\x00\x00\x05\x01TX\x02enHi";
+
+    assert_eq!(parse_message(message), Ok(vec![text("en", "Hi")]));
+}
+
+#[test]
+fn malformed_messages() {
+    assert_eq!(parse_message(&[]), Ok(vec![]));
+
+    assert_eq!(
+        parse_message(&record(0x51, b"T", b"\x02enA")),
+        Err(NdefError::MissingMessageBegin)
+    );
+    assert_eq!(
+        parse_message(&record(0xB1, b"T", b"\x02enA")),
+        Err(NdefError::Chunked)
+    );
+
+    let message = record(ONLY, b"T", b"\x02enHello");
+    assert_eq!(
+        parse_message(&message[..message.len() - 1]),
+        Err(NdefError::Truncated)
+    );
+    assert_eq!(parse_message(&[ONLY]), Err(NdefError::Truncated));
+
+    // Language code longer than the payload
+    assert_eq!(
+        parse_message(&record(ONLY, b"T", b"\x05en")),
+        Err(NdefError::Truncated)
+    );
+    assert_eq!(
+        parse_message(&record(ONLY, b"T", b"\x02en\xFF")),
+        Err(NdefError::Text)
+    );
+    assert_eq!(
+        parse_message(&record(ONLY, b"U", b"")),
+        Err(NdefError::Truncated)
+    );
+}
+
+#[test]
+fn huge_payload_length() {
+    // Lengths near the top of usize must not wrap around the read position
+    let message = b"\xC1\x01\xFF\xFF\xFF\xFFT\x02enHi";
+
+    assert_eq!(parse_message(message), Err(NdefError::Truncated));
+}
+
+#[test]
+fn capability_container() {
+    // NTAG213: 144 byte data area
+    assert_eq!(ntag::data_area_size(&[0xE1, 0x10, 0x12, 0x00]), Some(144));
+    assert_eq!(ntag::data_area_size(&[0x00, 0x00, 0x00, 0x00]), None);
+    assert_eq!(ntag::data_area_size(&[0xE1, 0x10]), None);
+}
+
+#[test]
+fn tlv_scanning() {
+    // NULL TLVs, then NDEF
+    let data = [0x00, 0x00, 0x03, 0x02, 0xAA, 0xBB, 0xFE];
+    assert_eq!(ntag::scan_tlvs(&data, false), TlvScan::Found(4..6));
+
+    // Lock control TLV first
+    let data = [0x01, 0x03, 0xA0, 0x0C, 0x34, 0x03, 0x01, 0xAA, 0xFE];
+    assert_eq!(ntag::scan_tlvs(&data, true), TlvScan::Found(7..8));
+
+    // Three byte length
+    let mut data = vec![0x03, 0xFF, 0x01, 0x00];
+    data.extend([0x55; 256]);
+    assert_eq!(ntag::scan_tlvs(&data, false), TlvScan::Found(4..260));
+
+    assert_eq!(ntag::scan_tlvs(&[0xFE, 0x03, 0x00], false), TlvScan::Absent);
+    assert_eq!(ntag::scan_tlvs(&[0x03, 0x00], true), TlvScan::Found(2..2));
+}
+
+#[test]
+fn tlv_needs_more_data() {
+    for data in [
+        &[][..],
+        &[0x00],
+        &[0x03],
+        &[0x03, 0xFF, 0x01],
+        &[0x03, 0x04, 0xAA],
+    ] {
+        assert_eq!(ntag::scan_tlvs(data, false), TlvScan::Incomplete);
+        assert_eq!(ntag::scan_tlvs(data, true), TlvScan::Absent);
+    }
+}
+
+// A Type 2 tag: READ returns four pages, wrapping around after the last one.
+struct Tag {
+    pages: Vec<[u8; 4]>,
+    reads: usize,
+}
+
+impl Tag {
+    // 45 pages like an NTAG213, with `data` from page 4
+    fn new(cc: [u8; 4], data: &[u8]) -> Tag {
+        let mut pages = vec![[0u8; 4]; 45];
+        pages[3] = cc;
+
+        for (page, chunk) in pages[4..].iter_mut().zip(data.chunks(4)) {
+            page[..chunk.len()].copy_from_slice(chunk);
+        }
+
+        Tag { pages, reads: 0 }
+    }
+}
+
+impl Transceive for Tag {
+    fn transceive(&mut self, _frame: &[u8]) -> anyhow::Result<Vec<u8>> {
+        bail!("Not an ISO 14443-4 tag");
+    }
+}
+
+impl CardReader for Tag {
+    fn name(&self) -> &'static str {
+        "Tag"
+    }
+
+    fn version(&mut self) -> anyhow::Result<u8> {
+        Ok(0)
+    }
+
+    fn poll(&mut self) -> anyhow::Result<Option<CardUid>> {
+        Ok(None)
+    }
+
+    fn wake(&mut self) -> anyhow::Result<Option<CardUid>> {
+        Ok(None)
+    }
+
+    fn sak(&self) -> Option<u8> {
+        Some(0x00)
+    }
+
+    fn self_test(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn reinit(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_antenna_gain(&mut self, _gain: u8) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_rf_field(&mut self, _on: bool) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn halt(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn authenticate(
+        &mut self,
+        _block: u8,
+        _key_type: KeyType,
+        _key: &MifareKey,
+    ) -> anyhow::Result<()> {
+        bail!("Type 2 tags have no MIFARE authentication");
+    }
+
+    fn read_block(&mut self, page: u8) -> anyhow::Result<[u8; 16]> {
+        self.reads += 1;
+
+        let mut block = [0u8; 16];
+
+        for (i, chunk) in block.chunks_mut(4).enumerate() {
+            chunk.copy_from_slice(&self.pages[(page as usize + i) % self.pages.len()]);
+        }
+
+        Ok(block)
+    }
+
+    fn write_block(&mut self, _page: u8, _data: &[u8; 16]) -> anyhow::Result<()> {
+        bail!("Read only");
+    }
+
+    fn stop_crypto(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+const NTAG213_CC: [u8; 4] = [0xE1, 0x10, 0x12, 0x00];
+
+#[test]
+fn ndef_read_across_pages() {
+    let message = record(ONLY, b"U", b"\x04hackspace.example/member/1234");
+
+    let mut data = vec![0x03, message.len() as u8];
+    data.extend(&message);
+    data.push(0xFE);
+
+    let mut tag = Tag::new(NTAG213_CC, &data);
+
+    assert_eq!(ntag::read_ndef(&mut tag).unwrap(), Some(message.clone()));
+
+    // CC, then only as many reads as the message needs
+    assert_eq!(tag.reads, 1 + data.len().div_ceil(16));
+
+    assert_eq!(
+        parse_message(&message),
+        Ok(vec![NdefRecord::Uri(
+            "https://hackspace.example/member/1234".to_string()
+        )])
+    );
+}
+
+#[test]
+fn tags_without_ndef() {
+    // Not formatted
+    let mut tag = Tag::new([0; 4], &[0x03, 0x00]);
+    assert_eq!(ntag::read_ndef(&mut tag).unwrap(), None);
+    assert_eq!(tag.reads, 1);
+
+    // Formatted but empty: read to the end of the data area
+    let mut tag = Tag::new(NTAG213_CC, &[]);
+    assert_eq!(ntag::read_ndef(&mut tag).unwrap(), None);
+    assert_eq!(tag.reads, 1 + 144 / 16);
+
+    let mut tag = Tag::new(NTAG213_CC, &[0xFE]);
+    assert_eq!(ntag::read_ndef(&mut tag).unwrap(), None);
+    assert_eq!(tag.reads, 2);
+}
+
+#[test]
+fn message_past_the_data_area() {
+    // The TLV claims more than the 144 bytes the CC allows
+    let mut tag = Tag::new(NTAG213_CC, &[0x03, 0xFF, 0x01, 0x00]);
+    assert_eq!(ntag::read_ndef(&mut tag).unwrap(), None);
+
+    // A CC claiming more pages than can be addressed
+    let mut tag = Tag::new([0xE1, 0x10, 0xFF, 0x00], &[]);
+    assert!(ntag::read_ndef(&mut tag).is_err());
+}
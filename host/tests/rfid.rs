@@ -0,0 +1,205 @@
+use host::{
+    card_uid::CardUid,
+    rfid::{
+        debounce::{Debounce, DebouncePolicy},
+        health::{Health, HealthEvent},
+        mock::{MockCard, MockField, MockReader},
+        presence::{Presence, PresenceEvent},
+        CardReader,
+    },
+};
+use std::time::{Duration, Instant};
+
+const MISSES: u8 = 3;
+const POLL: Duration = Duration::from_millis(100);
+
+fn uid(s: &str) -> CardUid {
+    s.parse().unwrap()
+}
+
+fn ada() -> CardUid {
+    uid("04:A2:3B:C1:5E:61:80")
+}
+
+fn bob() -> CardUid {
+    uid("DE:AD:BE:EF")
+}
+
+// A reader with a presence tracker, polled the way `RfidService` does it.
+struct Bench {
+    reader: MockReader,
+    field: MockField,
+    presence: Presence,
+    now: Instant,
+}
+
+impl Bench {
+    fn new() -> Bench {
+        let (reader, field) = MockReader::new();
+
+        Bench {
+            reader,
+            field,
+            presence: Presence::new(MISSES),
+            now: Instant::now(),
+        }
+    }
+
+    fn poll(&mut self) -> Vec<PresenceEvent> {
+        self.now += POLL;
+
+        let seen = self.presence.sense(&mut self.reader).unwrap();
+
+        self.presence.update(seen, self.now)
+    }
+}
+
+#[test]
+fn empty_field() {
+    let mut bench = Bench::new();
+
+    assert_eq!(bench.poll(), vec![]);
+    assert_eq!(bench.presence.card(), None);
+}
+
+#[test]
+fn card_held_then_removed() {
+    let mut bench = Bench::new();
+
+    bench.field.present(MockCard::new(ada()));
+
+    assert_eq!(bench.poll(), vec![PresenceEvent::Presented(ada())]);
+
+    // Halted after every poll and woken again, so it keeps answering
+    for _ in 0..10 {
+        assert_eq!(bench.poll(), vec![]);
+    }
+
+    assert_eq!(bench.presence.card(), Some(ada()));
+
+    bench.field.remove();
+
+    for _ in 1..MISSES {
+        assert_eq!(bench.poll(), vec![]);
+    }
+
+    assert_eq!(
+        bench.poll(),
+        vec![PresenceEvent::Removed(ada(), POLL * (10 + MISSES as u32))]
+    );
+    assert_eq!(bench.presence.card(), None);
+}
+
+#[test]
+fn missed_polls_are_forgiven() {
+    let mut bench = Bench::new();
+    let card = MockCard::new(ada());
+
+    bench.field.present(card.clone());
+    bench.poll();
+
+    bench.field.remove();
+
+    for _ in 1..MISSES {
+        assert_eq!(bench.poll(), vec![]);
+    }
+
+    bench.field.present(card);
+
+    assert_eq!(bench.poll(), vec![]);
+    assert_eq!(bench.presence.card(), Some(ada()));
+}
+
+#[test]
+fn other_card_replaces_at_once() {
+    let mut bench = Bench::new();
+
+    bench.field.present(MockCard::new(ada()));
+    bench.poll();
+    bench.poll();
+
+    bench.field.present(MockCard::new(bob()));
+
+    assert_eq!(
+        bench.poll(),
+        vec![
+            PresenceEvent::Removed(ada(), POLL * 2),
+            PresenceEvent::Presented(bob()),
+        ]
+    );
+}
+
+#[test]
+fn halted_card_stays_quiet_to_poll() {
+    let (mut reader, field) = MockReader::new();
+
+    field.present(MockCard::new(ada()));
+
+    assert_eq!(reader.poll().unwrap(), Some(ada()));
+
+    reader.halt().unwrap();
+
+    assert_eq!(reader.poll().unwrap(), None);
+    assert_eq!(reader.wake().unwrap(), Some(ada()));
+}
+
+#[test]
+fn cleared_card_is_presented_again() {
+    let mut bench = Bench::new();
+
+    bench.field.present(MockCard::new(ada()));
+    bench.poll();
+
+    // The reader went missing with the card on it
+    bench.presence.clear();
+
+    assert_eq!(bench.poll(), vec![PresenceEvent::Presented(ada())]);
+}
+
+#[test]
+fn debounced_taps() {
+    let mut debounce = Debounce::new(DebouncePolicy {
+        same_card: Duration::from_secs(2),
+        other_card: Duration::from_millis(500),
+    });
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+
+    assert!(debounce.accept(ada(), at(0)));
+
+    // Same card inside its window, other card inside the gap
+    assert!(!debounce.accept(ada(), at(1000)));
+    assert!(!debounce.accept(bob(), at(400)));
+
+    assert!(debounce.accept(bob(), at(600)));
+    assert!(!debounce.accept(bob(), at(2000)));
+    assert!(debounce.accept(ada(), at(2100)));
+}
+
+#[test]
+fn reader_goes_missing_and_comes_back() {
+    let start = Instant::now();
+    let interval = Duration::from_secs(10);
+    let mut health = Health::new(interval, 3, start);
+
+    assert!(health.due(start));
+    assert_eq!(health.record(true, start), None);
+    assert!(!health.due(start + interval / 2));
+
+    // A read error brings the next check forward
+    health.check_soon(start + Duration::from_secs(1));
+    assert!(health.due(start + Duration::from_secs(1)));
+
+    assert_eq!(health.record(false, start), None);
+    assert!(!health.should_reinit());
+    assert_eq!(health.record(false, start), None);
+    assert!(health.should_reinit());
+    assert_eq!(health.record(false, start), Some(HealthEvent::Missing));
+    assert!(health.is_missing());
+
+    // Reported once
+    assert_eq!(health.record(false, start), None);
+
+    assert_eq!(health.record(true, start), Some(HealthEvent::Restored));
+    assert!(!health.is_missing());
+}
@@ -0,0 +1,372 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use host::{
+    members::{Member, MemberDb, Role},
+    schedule::AccessRules,
+    tz::PosixTz,
+};
+
+const UK: &str = "GMT0BST,M3.5.0/1,M10.5.0";
+
+fn utc(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+}
+
+fn local(s: &str) -> NaiveDateTime {
+    s.parse().unwrap()
+}
+
+fn tz(s: &str) -> PosixTz {
+    s.parse().unwrap()
+}
+
+fn to_local(zone: &str, at: &str) -> NaiveDateTime {
+    tz(zone).to_local(utc(at))
+}
+
+fn member(role: Role, schedule: Option<&str>) -> Member {
+    Member {
+        name: "Ada".to_string(),
+        cards: vec![],
+        role,
+        valid_from: None,
+        valid_until: None,
+        remaining_uses: None,
+        notes: String::new(),
+        schedule: schedule.map(str::to_string),
+        pin: None,
+    }
+}
+
+fn rules() -> AccessRules {
+    MemberDb::from_json(
+        r#"{
+            "members": [],
+            "access": {
+                "weekly": {
+                    "evenings": [{ "days": ["Tue", "Thu"], "start": "18:00", "end": "22:00" }],
+                    "late": [{ "days": ["Sat"], "start": "22:00", "end": "03:00" }],
+                    "all-day": [{ "start": "00:00", "end": "24:00" }]
+                },
+                "roles": { "member": "evenings" },
+                "exceptions": [
+                    { "from": "2026-12-24", "until": "2026-12-26", "roles": ["member"] },
+                    {
+                        "from": "2026-12-31",
+                        "until": "2026-12-31",
+                        "windows": [{ "start": "20:00", "end": "02:00" }]
+                    }
+                ]
+            }
+        }"#,
+    )
+    .unwrap()
+    .access
+}
+
+// Whether `who` is let in at a UTC instant, evaluated in UK time.
+fn allowed_at(who: &Member, at: &str) -> bool {
+    rules().allows(who, Some(to_local(UK, at)))
+}
+
+#[test]
+fn parses_zones() {
+    for zone in [
+        "UTC0",
+        "GMT0BST,M3.5.0/1,M10.5.0",
+        "EST5EDT",
+        "EST5EDT,M3.2.0,M11.1.0",
+        "AEST-10AEDT,M10.1.0,M4.1.0/3",
+        "<+0330>-3:30",
+        "IST-1GMT0,M10.5.0,M3.5.0/1",
+        "CET-1CEST,J60/2,J300/3",
+        "XXX3YYY,59,300/-1",
+    ] {
+        assert!(zone.parse::<PosixTz>().is_ok(), "{zone}");
+    }
+
+    for zone in [
+        "",
+        "GM0",
+        "GMT",
+        "GMT0BST,M3.5.0",
+        "GMT0BST,M13.5.0,M10.5.0",
+        "GMT0BST,M3.6.0,M10.5.0",
+        "GMT25",
+        "<+0330-3:30",
+        "GMT0BST,M3.5.0/1,M10.5.0x",
+    ] {
+        assert!(zone.parse::<PosixTz>().is_err(), "{zone:?}");
+    }
+}
+
+#[test]
+fn fixed_offsets() {
+    assert_eq!(
+        to_local("UTC0", "2026-06-01T12:00:00Z"),
+        local("2026-06-01T12:00:00")
+    );
+    assert_eq!(
+        to_local("<+0330>-3:30", "2026-06-01T22:00:00Z"),
+        local("2026-06-02T01:30:00")
+    );
+    assert_eq!(
+        to_local("EST5", "2026-01-01T03:00:00Z"),
+        local("2025-12-31T22:00:00")
+    );
+}
+
+#[test]
+fn uk_spring_forward() {
+    // Last Sunday in March, 01:00 GMT becomes 02:00 BST
+    assert_eq!(
+        to_local(UK, "2026-03-29T00:59:59Z"),
+        local("2026-03-29T00:59:59")
+    );
+    assert_eq!(
+        to_local(UK, "2026-03-29T01:00:00Z"),
+        local("2026-03-29T02:00:00")
+    );
+    assert_eq!(
+        to_local(UK, "2026-07-01T12:00:00Z"),
+        local("2026-07-01T13:00:00")
+    );
+}
+
+#[test]
+fn uk_fall_back() {
+    // Last Sunday in October, 02:00 BST becomes 01:00 GMT, so 01:xx happens twice
+    assert_eq!(
+        to_local(UK, "2026-10-25T00:30:00Z"),
+        local("2026-10-25T01:30:00")
+    );
+    assert_eq!(
+        to_local(UK, "2026-10-25T00:59:59Z"),
+        local("2026-10-25T01:59:59")
+    );
+    assert_eq!(
+        to_local(UK, "2026-10-25T01:00:00Z"),
+        local("2026-10-25T01:00:00")
+    );
+    assert_eq!(
+        to_local(UK, "2026-10-25T01:30:00Z"),
+        local("2026-10-25T01:30:00")
+    );
+}
+
+#[test]
+fn us_transitions() {
+    let us = "EST5EDT,M3.2.0,M11.1.0";
+
+    // Second Sunday in March at 02:00 EST, first Sunday in November at 02:00 EDT
+    assert_eq!(
+        to_local(us, "2026-03-08T06:59:59Z"),
+        local("2026-03-08T01:59:59")
+    );
+    assert_eq!(
+        to_local(us, "2026-03-08T07:00:00Z"),
+        local("2026-03-08T03:00:00")
+    );
+    assert_eq!(
+        to_local(us, "2026-11-01T05:59:59Z"),
+        local("2026-11-01T01:59:59")
+    );
+    assert_eq!(
+        to_local(us, "2026-11-01T06:00:00Z"),
+        local("2026-11-01T01:00:00")
+    );
+
+    // Same rules when the DST part has no rules of its own
+    assert_eq!(tz("EST5EDT"), tz(us));
+}
+
+#[test]
+fn southern_hemisphere() {
+    let sydney = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+
+    // Daylight time over the new year
+    assert_eq!(
+        to_local(sydney, "2026-01-15T00:00:00Z"),
+        local("2026-01-15T11:00:00")
+    );
+
+    // First Sunday in April at 03:00 AEDT, first Sunday in October at 02:00 AEST
+    assert_eq!(
+        to_local(sydney, "2026-04-04T15:59:59Z"),
+        local("2026-04-05T02:59:59")
+    );
+    assert_eq!(
+        to_local(sydney, "2026-04-04T16:00:00Z"),
+        local("2026-04-05T02:00:00")
+    );
+    assert_eq!(
+        to_local(sydney, "2026-10-03T15:59:59Z"),
+        local("2026-10-04T01:59:59")
+    );
+    assert_eq!(
+        to_local(sydney, "2026-10-03T16:00:00Z"),
+        local("2026-10-04T03:00:00")
+    );
+}
+
+#[test]
+fn julian_days() {
+    // J60 is March 1st whether or not it is a leap year
+    let julian = tz("CET-1CEST,J60/2,J300/3");
+
+    assert_eq!(
+        julian.to_local(utc("2028-03-01T00:59:59Z")),
+        local("2028-03-01T01:59:59")
+    );
+    assert_eq!(
+        julian.to_local(utc("2028-03-01T01:00:00Z")),
+        local("2028-03-01T03:00:00")
+    );
+
+    // Zero-based day 59 is February 29th in a leap year and March 1st otherwise
+    let zero = tz("XXX0YYY,59/0,300/0");
+
+    assert_eq!(
+        zero.to_local(utc("2028-02-28T23:59:59Z")),
+        local("2028-02-28T23:59:59")
+    );
+    assert_eq!(
+        zero.to_local(utc("2028-02-29T00:00:00Z")),
+        local("2028-02-29T01:00:00")
+    );
+    assert_eq!(
+        zero.to_local(utc("2027-02-28T12:00:00Z")),
+        local("2027-02-28T12:00:00")
+    );
+    assert_eq!(
+        zero.to_local(utc("2027-03-01T00:00:00Z")),
+        local("2027-03-01T01:00:00")
+    );
+}
+
+#[test]
+fn weekly_windows_follow_local_time() {
+    let member = member(Role::Member, None);
+
+    // Tuesday 18:00-22:00 is 18:00-22:00 UTC in winter and 17:00-21:00 UTC in summer
+    assert!(!allowed_at(&member, "2026-01-13T17:59:00Z"));
+    assert!(allowed_at(&member, "2026-01-13T18:00:00Z"));
+    assert!(allowed_at(&member, "2026-01-13T21:59:00Z"));
+    assert!(!allowed_at(&member, "2026-01-13T22:00:00Z"));
+
+    assert!(allowed_at(&member, "2026-07-14T17:00:00Z"));
+    assert!(allowed_at(&member, "2026-07-14T20:59:00Z"));
+    assert!(!allowed_at(&member, "2026-07-14T21:00:00Z"));
+
+    // Wednesday
+    assert!(!allowed_at(&member, "2026-01-14T19:00:00Z"));
+}
+
+#[test]
+fn unrestricted_roles_and_member_schedules() {
+    let keyholder = member(Role::Keyholder, None);
+
+    assert!(allowed_at(&keyholder, "2026-01-14T03:00:00Z"));
+
+    // A member's own schedule wins over their role's
+    let all_day = member(Role::Member, Some("all-day"));
+
+    assert!(allowed_at(&all_day, "2026-01-14T03:00:00Z"));
+
+    let unknown = member(Role::Keyholder, Some("missing"));
+
+    assert!(!allowed_at(&unknown, "2026-01-14T03:00:00Z"));
+}
+
+#[test]
+fn overnight_window_across_dst() {
+    let late = member(Role::Member, Some("late"));
+
+    // Saturday 22:00 to Sunday 03:00 local. Spring forward skips 01:00-02:00, so the window
+    // is an hour shorter in UTC.
+    assert!(!allowed_at(&late, "2026-03-28T21:59:00Z"));
+    assert!(allowed_at(&late, "2026-03-28T22:00:00Z"));
+    assert!(allowed_at(&late, "2026-03-29T00:30:00Z"));
+    assert!(allowed_at(&late, "2026-03-29T01:30:00Z"));
+    assert!(!allowed_at(&late, "2026-03-29T02:00:00Z"));
+
+    // Fall back repeats 01:00-02:00, so it is an hour longer. 22:00 BST is 21:00 UTC.
+    assert!(!allowed_at(&late, "2026-10-24T20:59:00Z"));
+    assert!(allowed_at(&late, "2026-10-24T21:00:00Z"));
+    assert!(allowed_at(&late, "2026-10-25T00:30:00Z"));
+    assert!(allowed_at(&late, "2026-10-25T01:30:00Z"));
+    assert!(allowed_at(&late, "2026-10-25T02:59:00Z"));
+    assert!(!allowed_at(&late, "2026-10-25T03:00:00Z"));
+
+    // Not on Sunday night
+    assert!(!allowed_at(&late, "2026-03-29T22:30:00Z"));
+}
+
+#[test]
+fn exceptions() {
+    let regular = member(Role::Member, None);
+    let keyholder = member(Role::Keyholder, None);
+
+    // Christmas Eve is a Thursday, but closed to members
+    assert!(!allowed_at(&regular, "2026-12-24T19:00:00Z"));
+    assert!(allowed_at(&keyholder, "2026-12-24T19:00:00Z"));
+
+    // New Year's Eve is open to everyone 20:00-02:00, including keyholders
+    assert!(allowed_at(&regular, "2026-12-31T23:00:00Z"));
+    assert!(!allowed_at(&keyholder, "2026-12-31T12:00:00Z"));
+}
+
+#[test]
+fn no_clock() {
+    let rules = rules();
+
+    assert!(!rules.allows(&member(Role::Member, None), None));
+    assert!(rules.allows(&member(Role::Keyholder, None), None));
+}
+
+#[test]
+fn membership_dates() {
+    let mut member = member(Role::Member, None);
+    member.valid_until = NaiveDate::from_ymd_opt(2026, 12, 31).map(Into::into);
+
+    assert!(member.is_valid_at(Some(local("2026-12-31T23:59:59"))));
+    assert!(!member.is_valid_at(Some(local("2027-01-01T00:00:00"))));
+    assert!(!member.is_valid_at(None));
+
+    // The last day runs to local midnight: 23:30 UTC on 31st August is already September
+    // in BST
+    member.valid_until = NaiveDate::from_ymd_opt(2026, 8, 31).map(Into::into);
+
+    assert!(member.is_valid_at(Some(to_local(UK, "2026-08-31T22:59:59Z"))));
+    assert!(!member.is_valid_at(Some(to_local(UK, "2026-08-31T23:30:00Z"))));
+}
+
+#[test]
+fn guest_pass_times() {
+    let db = MemberDb::from_json(
+        r#"{
+            "members": [{
+                "name": "Open evening",
+                "cards": [],
+                "role": "member",
+                "valid_from": "2026-10-20T18:30",
+                "valid_until": "2026-10-20T22:00",
+                "remaining_uses": 2
+            }]
+        }"#,
+    )
+    .unwrap();
+    let guest = &db.members[0];
+
+    assert_eq!(guest.remaining_uses, Some(2));
+
+    assert!(!guest.is_valid_at(Some(local("2026-10-20T18:29:59"))));
+    assert!(guest.is_valid_at(Some(local("2026-10-20T18:30:00"))));
+    assert!(guest.is_valid_at(Some(local("2026-10-20T21:59:59"))));
+    assert!(!guest.is_valid_at(Some(local("2026-10-20T22:00:00"))));
+
+    // Written back the way it was given
+    let json = db.to_json().unwrap();
+
+    assert!(json.contains(r#""valid_from": "2026-10-20T18:30""#));
+    assert_eq!(MemberDb::from_json(&json).unwrap(), db);
+}
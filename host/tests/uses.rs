@@ -0,0 +1,201 @@
+use chrono::NaiveDateTime;
+use host::{
+    card_uid::CardUid,
+    members::{MemberDb, MemberIndex},
+    spiffs::Spiffs,
+    uses::UseCounts,
+};
+use std::{fs, path::PathBuf};
+
+// An empty directory of the test's own for the files to go in
+fn flash(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("host-uses-{}-{}", std::process::id(), test));
+
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    Spiffs::use_dir(&dir);
+
+    dir
+}
+
+fn uid(s: &str) -> CardUid {
+    s.parse().unwrap()
+}
+
+fn at(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M").unwrap()
+}
+
+const GUEST: &str = "04:A2:3B:C1";
+const OTHER_GUEST: &str = "DE:AD:BE:EF";
+
+// Two guests with three uses each, one of them from a start date
+fn index(valid_from: &str) -> MemberIndex {
+    let json = format!(
+        r#"{{
+            "members": [{{
+                "name": "Guest",
+                "cards": ["{GUEST}"],
+                "role": "member",
+                "valid_from": "{valid_from}",
+                "valid_until": "2026-10-20",
+                "remaining_uses": 3
+            }}, {{
+                "name": "Guest",
+                "cards": ["{OTHER_GUEST}"],
+                "role": "member",
+                "remaining_uses": 3
+            }}]
+        }}"#
+    );
+
+    MemberIndex::new(MemberDb::from_json(&json).unwrap(), None)
+}
+
+fn record(uses: &mut UseCounts, index: &MemberIndex, card: &str) -> Option<u32> {
+    let (entry, member) = index.find_entry(&uid(card)).unwrap();
+
+    uses.record(entry.card, member).unwrap()
+}
+
+fn remaining(uses: &UseCounts, index: &MemberIndex, card: &str) -> Option<u32> {
+    let (entry, member) = index.find_entry(&uid(card)).unwrap();
+
+    uses.remaining(entry.card, member)
+}
+
+#[test]
+fn passes_are_counted_apart() {
+    let dir = flash("apart");
+    let index = index("2026-10-18T19:30");
+    let mut uses = UseCounts::load();
+
+    assert_eq!(record(&mut uses, &index, GUEST), Some(2));
+    assert_eq!(record(&mut uses, &index, GUEST), Some(1));
+
+    // Same name, different card
+    assert_eq!(remaining(&uses, &index, OTHER_GUEST), Some(3));
+
+    // The same card handed out again from a later date
+    let again = self::index("2026-11-01");
+    assert_eq!(remaining(&uses, &again, GUEST), Some(3));
+
+    let saved = fs::read_to_string(dir.join("uses.json")).unwrap();
+    assert_eq!(saved, r#"{"04:A2:3B:C1 2026-10-18T19:30":2}"#);
+    assert!(!dir.join("uses.json.new").exists());
+}
+
+#[test]
+fn counts_survive_a_restart() {
+    flash("restart");
+    let index = index("2026-10-18T19:30");
+
+    let mut uses = UseCounts::load();
+    record(&mut uses, &index, GUEST);
+    record(&mut uses, &index, OTHER_GUEST);
+    record(&mut uses, &index, OTHER_GUEST);
+
+    let uses = UseCounts::load();
+    assert_eq!(remaining(&uses, &index, GUEST), Some(2));
+    assert_eq!(remaining(&uses, &index, OTHER_GUEST), Some(1));
+}
+
+#[test]
+fn used_up() {
+    flash("used-up");
+    let index = index("2026-10-18T19:30");
+    let mut uses = UseCounts::load();
+
+    for _ in 0..3 {
+        record(&mut uses, &index, GUEST);
+    }
+
+    assert_eq!(record(&mut uses, &index, GUEST), Some(0));
+    assert_eq!(remaining(&uses, &index, GUEST), Some(0));
+}
+
+#[test]
+fn prune_drops_removed_passes() {
+    let dir = flash("removed");
+    let index = index("2026-10-18T19:30");
+    let mut uses = UseCounts::load();
+
+    record(&mut uses, &index, GUEST);
+    record(&mut uses, &index, OTHER_GUEST);
+
+    // The first guest's pass handed out again from a later date
+    uses.prune(&self::index("2026-11-01"), None).unwrap();
+
+    let saved = fs::read_to_string(dir.join("uses.json")).unwrap();
+    assert_eq!(saved, r#"{"DE:AD:BE:EF":1}"#);
+}
+
+#[test]
+fn prune_drops_expired_passes() {
+    let dir = flash("expired");
+    let index = index("2026-10-18T19:30");
+    let mut uses = UseCounts::load();
+
+    record(&mut uses, &index, GUEST);
+    record(&mut uses, &index, OTHER_GUEST);
+
+    // Without the time nothing can be said to have expired
+    uses.prune(&index, None).unwrap();
+    uses.prune(&index, Some(at("2026-10-20T23:59"))).unwrap();
+    assert_eq!(remaining(&uses, &index, GUEST), Some(2));
+
+    uses.prune(&index, Some(at("2026-10-21T00:00"))).unwrap();
+    assert_eq!(remaining(&uses, &index, GUEST), Some(3));
+    assert_eq!(remaining(&uses, &index, OTHER_GUEST), Some(2));
+
+    let saved = fs::read_to_string(dir.join("uses.json")).unwrap();
+    assert_eq!(saved, r#"{"DE:AD:BE:EF":1}"#);
+}
+
+#[test]
+fn new_file_is_read_first() {
+    let dir = flash("new-first");
+    let index = index("2026-10-18T19:30");
+
+    // Power lost after the new file was written, before it replaced the old one
+    fs::write(dir.join("uses.json"), r#"{"DE:AD:BE:EF":1}"#).unwrap();
+    fs::write(dir.join("uses.json.new"), r#"{"DE:AD:BE:EF":2}"#).unwrap();
+
+    let mut uses = UseCounts::load();
+    assert_eq!(remaining(&uses, &index, OTHER_GUEST), Some(1));
+
+    // The next save finishes the swap
+    record(&mut uses, &index, OTHER_GUEST);
+
+    let saved = fs::read_to_string(dir.join("uses.json")).unwrap();
+    assert_eq!(saved, r#"{"DE:AD:BE:EF":3}"#);
+    assert!(!dir.join("uses.json.new").exists());
+}
+
+#[test]
+fn only_the_firmware_writes_counts() {
+    for name in [
+        "uses.json",
+        "uses.json.new",
+        "/uses.json",
+        "./uses.json.new",
+    ] {
+        assert!(UseCounts::is_uses_file(name), "{}", name);
+    }
+
+    assert!(!UseCounts::is_uses_file("members.json"));
+    assert!(!UseCounts::is_uses_file("uses.json.old"));
+}
+
+#[test]
+fn new_file_cut_short_is_ignored() {
+    let dir = flash("cut-short");
+    let index = index("2026-10-18T19:30");
+
+    fs::write(dir.join("uses.json"), r#"{"DE:AD:BE:EF":1}"#).unwrap();
+    fs::write(dir.join("uses.json.new"), r#"{"DE:AD:BE"#).unwrap();
+
+    let uses = UseCounts::load();
+    assert_eq!(remaining(&uses, &index, OTHER_GUEST), Some(2));
+}
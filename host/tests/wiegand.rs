@@ -0,0 +1,132 @@
+use host::{
+    card_uid::CardUid,
+    wiegand::frame::{decode_key, WiegandError, WiegandFormat, WiegandFrame},
+};
+
+fn bits(s: &str) -> Vec<bool> {
+    s.chars().map(|c| c == '1').collect()
+}
+
+fn frame(format: WiegandFormat, facility: u16, card: u16) -> WiegandFrame {
+    WiegandFrame::new(format, facility, card).unwrap()
+}
+
+// Facility 123, card 4567: 1 01111011 0001000111010111 0
+const W26: &str = "10111101100010001110101110";
+
+// Facility 0x1234, card 0xABCD
+const W34: &str = "1000100100011010010101011110011011";
+
+#[test]
+fn wiegand_26() {
+    let frame = frame(WiegandFormat::W26, 123, 4567);
+
+    assert_eq!(frame.encode(), bits(W26));
+    assert_eq!(WiegandFrame::decode(&bits(W26)), Ok(frame));
+    assert_eq!(frame.value(), (123 << 16) | 4567);
+}
+
+#[test]
+fn wiegand_34() {
+    let frame = frame(WiegandFormat::W34, 0x1234, 0xABCD);
+
+    assert_eq!(frame.encode(), bits(W34));
+    assert_eq!(WiegandFrame::decode(&bits(W34)), Ok(frame));
+}
+
+#[test]
+fn parity_bits() {
+    // All zeros: even parity 0, odd parity 1
+    assert_eq!(
+        frame(WiegandFormat::W26, 0, 0).encode(),
+        bits("00000000000000000000000001")
+    );
+
+    // One bit in each half sets the even parity and clears the odd one
+    assert_eq!(
+        frame(WiegandFormat::W26, 1, 1).encode(),
+        bits("10000000100000000000000010")
+    );
+}
+
+#[test]
+fn every_flipped_bit_is_caught() {
+    for format in [WiegandFormat::W26, WiegandFormat::W34] {
+        let encoded = frame(format, 0x5A, 0x3C3C).encode();
+
+        for i in 0..encoded.len() {
+            let mut damaged = encoded.clone();
+            damaged[i] = !damaged[i];
+
+            assert_eq!(
+                WiegandFrame::decode(&damaged),
+                Err(WiegandError::Parity),
+                "bit {} of {:?}",
+                i,
+                format
+            );
+        }
+    }
+}
+
+#[test]
+fn unsupported_lengths() {
+    assert_eq!(
+        WiegandFrame::decode(&bits("1010")),
+        Err(WiegandError::Length(4))
+    );
+    assert_eq!(WiegandFrame::decode(&[]), Err(WiegandError::Length(0)));
+    assert_eq!(
+        WiegandFrame::decode(&bits(&W26[1..])),
+        Err(WiegandError::Length(25))
+    );
+    assert_eq!(
+        WiegandError::Length(37).to_string(),
+        "Unsupported Wiegand frame of 37 bits"
+    );
+}
+
+#[test]
+fn facility_must_fit() {
+    assert_eq!(WiegandFrame::new(WiegandFormat::W26, 0x100, 1), None);
+    assert!(WiegandFrame::new(WiegandFormat::W26, 0xFF, 1).is_some());
+    assert!(WiegandFrame::new(WiegandFormat::W34, 0xFFFF, 1).is_some());
+}
+
+#[test]
+fn card_uids() {
+    let uid: CardUid = "04:A2:3B:C1".parse().unwrap();
+
+    // The first two UID bytes, least significant first
+    let frame = WiegandFrame::from_uid(WiegandFormat::W26, 1, &uid).unwrap();
+    assert_eq!((frame.facility, frame.card), (1, 0xA204));
+
+    assert_eq!(
+        WiegandFrame::from_uid(WiegandFormat::W26, 0x100, &uid),
+        None
+    );
+
+    // Read back as a 4 byte UID with the frame's value as its number
+    let frame = WiegandFrame::decode(&bits(W26)).unwrap();
+    assert_eq!(
+        frame.to_uid(),
+        CardUid::from_bytes(&frame.value().to_le_bytes()).unwrap()
+    );
+    assert_eq!(frame.to_uid(), "D7:11:7B:00".parse().unwrap());
+}
+
+#[test]
+fn keypad_keys() {
+    assert_eq!(decode_key(&bits("0101")), Some('5'));
+    assert_eq!(decode_key(&bits("1010")), Some('*'));
+    assert_eq!(decode_key(&bits("1011")), Some('#'));
+    assert_eq!(decode_key(&bits("1100")), None);
+
+    // 8 bits with the high nibble inverted
+    assert_eq!(decode_key(&bits("10100101")), Some('5'));
+    assert_eq!(decode_key(&bits("11110000")), Some('0'));
+    assert_eq!(decode_key(&bits("01010101")), None);
+
+    assert_eq!(decode_key(&bits("101")), None);
+    assert_eq!(decode_key(&bits(W26)), None);
+}
@@ -0,0 +1,135 @@
+// Card UIDs hashed with the site's card key, as HMAC-SHA256 over the UID's bytes. members.json
+// can list "hmac-sha256:<hex>" instead of a UID, so a leaked copy doesn't say what to clone.
+// DESFire cards are listed as {"card": ..., "desfire": true}, and only the card is hashed.
+//
+//   deno task card-key      makes card-key.txt and prints the key, for /card-key on each
+//                           controller
+//   deno task hash-cards    replaces the UIDs in members.json with their hashes, run
+//                           deno task sign after it if lists are signed
+//
+// The key lives in card-key.txt next to members.json.
+
+const KEY_FILE = "card-key.txt";
+
+// How a member's card is listed in members.json
+export type CardEntry = string | { card: string; desfire?: boolean };
+
+export function cardOf(entry: CardEntry): string {
+  return typeof entry === "string" ? entry : entry.card;
+}
+
+const PREFIX = "hmac-sha256:";
+
+function hex(bytes: Uint8Array): string {
+  return Array.from(bytes, (b) => b.toString(16).padStart(2, "0")).join("");
+}
+
+// "04:A2:3B:C1" as the controller writes them, or the decimal form of a 4 byte UID
+export function parseUid(uid: string): Uint8Array | null {
+  if (/^([0-9A-Fa-f]{2}:){3,9}[0-9A-Fa-f]{2}$/.test(uid)) {
+    return new Uint8Array(uid.split(":").map((b) => parseInt(b, 16)));
+  }
+
+  if (/^[0-9]+$/.test(uid) && Number(uid) <= 0xffffffff) {
+    const bytes = new Uint8Array(4);
+    new DataView(bytes.buffer).setUint32(0, Number(uid), true);
+    return bytes;
+  }
+
+  return null;
+}
+
+export function isHashed(card: string): boolean {
+  return card.startsWith(PREFIX);
+}
+
+// `null` when there is no card-key.txt
+export async function loadCardKey(): Promise<CryptoKey | null> {
+  let text;
+
+  try {
+    text = (await Deno.readTextFile(KEY_FILE)).trim();
+  } catch {
+    return null;
+  }
+
+  const bytes = new Uint8Array(text.match(/../g)!.map((b) => parseInt(b, 16)));
+
+  return await crypto.subtle.importKey(
+    "raw",
+    bytes,
+    { name: "HMAC", hash: "SHA-256" },
+    false,
+    ["sign"],
+  );
+}
+
+export async function hashUid(key: CryptoKey, uid: Uint8Array): Promise<string> {
+  const mac = await crypto.subtle.sign("HMAC", key, uid);
+
+  return PREFIX + hex(new Uint8Array(mac));
+}
+
+async function keygen() {
+  try {
+    await Deno.stat(KEY_FILE);
+    console.error(`${KEY_FILE} already exists, every hashed card depends on it`);
+    Deno.exit(1);
+  } catch {
+    // No key yet
+  }
+
+  const key = hex(crypto.getRandomValues(new Uint8Array(32)));
+
+  await Deno.writeTextFile(KEY_FILE, key, { mode: 0o600 });
+
+  console.log(key);
+}
+
+async function hashCards(file: string) {
+  const key = await loadCardKey();
+
+  if (!key) {
+    console.error(`No ${KEY_FILE}, run deno task card-key first`);
+    Deno.exit(1);
+  }
+
+  const db = JSON.parse(await Deno.readTextFile(file));
+  let hashed = 0;
+
+  for (const member of db.members ?? []) {
+    member.cards = await Promise.all(
+      member.cards.map(async (entry: CardEntry) => {
+        const card = cardOf(entry);
+        const uid = isHashed(card) ? null : parseUid(card);
+
+        if (!uid) {
+          return entry;
+        }
+
+        hashed++;
+        const hash = await hashUid(key, uid);
+
+        return typeof entry === "string" ? hash : { ...entry, card: hash };
+      }),
+    );
+  }
+
+  await Deno.writeTextFile(file, JSON.stringify(db, null, 2) + "\n");
+
+  console.log(`Hashed ${hashed} cards in ${file}`);
+}
+
+if (import.meta.main) {
+  switch (Deno.args[0]) {
+    case "keygen":
+      await keygen();
+      break;
+    case "hash":
+      await hashCards(Deno.args[1] ?? "members.json");
+      break;
+    default:
+      console.error("Usage: deno run cards.ts keygen|hash [members.json]");
+      Deno.exit(1);
+  }
+}
@@ -0,0 +1,36 @@
+// Prints the hash for a member's "pin" field in members.json:
+//
+//   deno task hash-pin 1234
+//
+// PBKDF2-HMAC-SHA256 with a random salt, in the form the controller reads.
+
+const ITERATIONS = 10000;
+
+const pin = Deno.args[0];
+
+if (!pin || !/^[0-9]{4,12}$/.test(pin)) {
+  console.error("Usage: deno task hash-pin <4 to 12 digits>");
+  Deno.exit(1);
+}
+
+const salt = crypto.getRandomValues(new Uint8Array(16));
+
+const key = await crypto.subtle.importKey(
+  "raw",
+  new TextEncoder().encode(pin),
+  "PBKDF2",
+  false,
+  ["deriveBits"],
+);
+
+const hash = await crypto.subtle.deriveBits(
+  { name: "PBKDF2", hash: "SHA-256", salt, iterations: ITERATIONS },
+  key,
+  256,
+);
+
+function hex(bytes: Uint8Array): string {
+  return Array.from(bytes, (b) => b.toString(16).padStart(2, "0")).join("");
+}
+
+console.log(`pbkdf2-sha256$${ITERATIONS}$${hex(salt)}$${hex(new Uint8Array(hash))}`);
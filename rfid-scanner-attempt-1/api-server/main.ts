@@ -1,3 +1,147 @@
-Deno.serve({ port: 8000, hostname: "0.0.0.0" }, (_req) => {
-  return new Response("Hello, World!");
+// Auth backend for the door controllers.
+//
+// POST /auth {"uid": "04:A2:3B:C1:5E:61:80", "device": "AA:BB:CC:DD:EE:FF"}
+//   -> {"granted": true, "name": "Ada", "reason": "member", "unlock_seconds": 5}
+//
+// GET /members
+//   -> members.json as it is, with an ETag. Controllers send it back in If-None-Match and get
+//      304 Not Modified until the file changes. With a signing key (see sign.ts) the list's
+//      signature goes in X-Signature.
+//
+// Members come from members.json in the working directory, in the same format as the copy on
+// the controller. It is read on every request so edits apply straight away. Cards listed by
+// hash need card-key.txt, see cards.ts. Guest pass uses (remaining_uses) are only counted by
+// the controllers, this server lets a pass in for as long as its dates allow.
+
+import { type CardEntry, cardOf, hashUid, loadCardKey, parseUid } from "./cards.ts";
+import { signList } from "./sign.ts";
+
+interface Member {
+  name: string;
+  cards: CardEntry[];
+  role: string;
+  valid_from?: string;
+  valid_until?: string;
+  remaining_uses?: number;
+}
+
+interface Decision {
+  granted: boolean;
+  name: string;
+  reason: string;
+  unlock_seconds?: number;
+}
+
+const UNLOCK_SECONDS = 5;
+
+async function loadMembers(): Promise<Member[]> {
+  try {
+    const db = JSON.parse(await Deno.readTextFile("members.json"));
+    return db.members ?? [];
+  } catch (err) {
+    console.error(`members.json: ${err}`);
+    return [];
+  }
+}
+
+// An end date covers the whole day, an end time ("2026-10-20T22:00") is when the pass stops
+// working. `now` is "YYYY-MM-DDTHH:MM:SS", so a prefix of it compares with either.
+function expired(until: string, now: string): boolean {
+  const cut = now.slice(0, until.length);
+  return until.includes("T") ? cut >= until : cut > until;
+}
+
+// Both sides write UIDs as colon separated hex, only the case may differ. A hashed card
+// matches the hash of the UID.
+function decide(members: Member[], uid: string, hash: string | null, now: string): Decision {
+  const member = members.find((m) =>
+    m.cards.map(cardOf).some((card) => card.toUpperCase() === uid.toUpperCase() || card === hash)
+  );
+
+  if (!member) {
+    return { granted: false, name: "", reason: "unknown card" };
+  }
+
+  if (
+    (member.valid_from && now < member.valid_from) ||
+    (member.valid_until && expired(member.valid_until, now))
+  ) {
+    return { granted: false, name: member.name, reason: "membership expired" };
+  }
+
+  return {
+    granted: true,
+    name: member.name,
+    reason: member.role,
+    unlock_seconds: UNLOCK_SECONDS,
+  };
+}
+
+// The ETag is a hash of the file, so it only changes when the contents do
+async function serveMembers(req: Request): Promise<Response> {
+  let file;
+
+  try {
+    file = await Deno.readFile("members.json");
+  } catch (err) {
+    console.error(`members.json: ${err}`);
+    return new Response("No member list", { status: 404 });
+  }
+
+  const hash = new Uint8Array(await crypto.subtle.digest("SHA-256", file));
+  const hex = Array.from(hash.slice(0, 16), (b) => b.toString(16).padStart(2, "0"));
+  const etag = `"${hex.join("")}"`;
+
+  if (req.headers.get("If-None-Match") === etag) {
+    return new Response(null, { status: 304, headers: { ETag: etag } });
+  }
+
+  console.log(`Sending member list ${etag}`);
+
+  const headers: Record<string, string> = { "Content-Type": "application/json", ETag: etag };
+  const signature = await signList(file);
+
+  if (signature) {
+    headers["X-Signature"] = signature;
+  }
+
+  return new Response(file, { headers });
+}
+
+async function auth(req: Request): Promise<Response> {
+  let body;
+
+  try {
+    body = await req.json();
+  } catch {
+    return new Response("Invalid JSON", { status: 400 });
+  }
+
+  if (typeof body?.uid !== "string") {
+    return new Response("Missing uid", { status: 400 });
+  }
+
+  const now = new Date().toISOString().slice(0, 19);
+  const key = await loadCardKey();
+  const bytes = parseUid(body.uid);
+  const hash = key && bytes ? await hashUid(key, bytes) : null;
+  const decision = decide(await loadMembers(), body.uid, hash, now);
+
+  console.log(`${body.device ?? "?"} ${body.uid}: ${decision.reason}`);
+
+  return Response.json(decision);
+}
+
+Deno.serve({ port: 8000, hostname: "0.0.0.0" }, (req) => {
+  const url = new URL(req.url);
+
+  if (req.method === "POST" && url.pathname === "/auth") {
+    return auth(req);
+  }
+
+  if (req.method === "GET" && url.pathname === "/members") {
+    return serveMembers(req);
+  }
+
+  return new Response("Not found", { status: 404 });
 });
@@ -0,0 +1,79 @@
+// Ed25519 signing for member lists. The private key lives in signing-key.json next to
+// members.json and never goes near a controller.
+//
+//   deno task keygen   makes signing-key.json and prints the public key, for /list-key or
+//                      the MEMBERS_PUBLIC_KEY build variable
+//   deno task sign     writes members.json.sig, to upload alongside members.json
+//
+// GET /members signs the list itself and sends the signature as X-Signature.
+
+const KEY_FILE = "signing-key.json";
+
+function hex(bytes: Uint8Array): string {
+  return Array.from(bytes, (b) => b.toString(16).padStart(2, "0")).join("");
+}
+
+// `null` when there is no key, the list then goes out unsigned
+export async function signList(list: Uint8Array): Promise<string | null> {
+  let jwk;
+
+  try {
+    jwk = JSON.parse(await Deno.readTextFile(KEY_FILE));
+  } catch {
+    return null;
+  }
+
+  const key = await crypto.subtle.importKey("jwk", jwk, { name: "Ed25519" }, false, ["sign"]);
+  const signature = await crypto.subtle.sign({ name: "Ed25519" }, key, list);
+
+  return hex(new Uint8Array(signature));
+}
+
+async function keygen() {
+  try {
+    await Deno.stat(KEY_FILE);
+    console.error(`${KEY_FILE} already exists, controllers only accept one key`);
+    Deno.exit(1);
+  } catch {
+    // No key yet
+  }
+
+  const pair = (await crypto.subtle.generateKey({ name: "Ed25519" }, true, [
+    "sign",
+    "verify",
+  ])) as CryptoKeyPair;
+
+  const jwk = await crypto.subtle.exportKey("jwk", pair.privateKey);
+  const raw = await crypto.subtle.exportKey("raw", pair.publicKey);
+
+  await Deno.writeTextFile(KEY_FILE, JSON.stringify(jwk), { mode: 0o600 });
+
+  console.log(hex(new Uint8Array(raw)));
+}
+
+async function sign() {
+  const signature = await signList(await Deno.readFile("members.json"));
+
+  if (!signature) {
+    console.error(`No ${KEY_FILE}, run deno task keygen first`);
+    Deno.exit(1);
+  }
+
+  await Deno.writeTextFile("members.json.sig", signature);
+
+  console.log("Wrote members.json.sig");
+}
+
+if (import.meta.main) {
+  switch (Deno.args[0]) {
+    case "keygen":
+      await keygen();
+      break;
+    case "sign":
+      await sign();
+      break;
+    default:
+      console.error("Usage: deno run sign.ts keygen|sign");
+      Deno.exit(1);
+  }
+}
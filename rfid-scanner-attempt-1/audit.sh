@@ -0,0 +1,21 @@
+#!/bin/bash
+set -Eeuo pipefail
+
+# ./audit.sh [csv|jsonl] [from] [until], times in Unix seconds. Follows X-Next-From until
+# every page has been fetched.
+
+FORMAT=${1:-jsonl}
+FROM=${2:-}
+UNTIL=${3:-}
+HEADERS=$(mktemp)
+trap 'rm -f $HEADERS' EXIT
+
+while true; do
+  curl -s -D $HEADERS "http://10.3.2.186/audit?format=$FORMAT&from=$FROM&until=$UNTIL"
+
+  NEXT=$(grep -i '^X-Next-From:' $HEADERS | tr -d '\r' | cut -d' ' -f2 || true)
+
+  [ -z "$NEXT" ] && break
+
+  FROM=$NEXT
+done
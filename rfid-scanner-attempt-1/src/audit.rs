@@ -0,0 +1,400 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{auth::backend::AuthDecision, card_uid::CardUid, common, spiffs::Spiffs, tz};
+
+const LOG_FILE: &str = "audit.log";
+
+// Appends come from the main loop and the HTTP server's thread
+static LOCK: Mutex<()> = Mutex::new(());
+
+// Times audit.log has moved aside, so a query reading between appends can follow its file
+static ROTATIONS: AtomicUsize = AtomicUsize::new(0);
+
+// Lines a query reads each time it takes the lock, so taps aren't held up while it runs
+const QUERY_CHUNK: usize = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditEvent {
+    // A card presented, or a PIN typed for one
+    Tap,
+    // The strike unlocking and locking again
+    Door,
+    // Something changed through the HTTP API or a sync
+    Admin,
+    // Something an admin should look into, such as a lockout after too many denied taps
+    Alert,
+}
+
+impl AuditEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuditEvent::Tap => "tap",
+            AuditEvent::Door => "door",
+            AuditEvent::Admin => "admin",
+            AuditEvent::Alert => "alert",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditDecision {
+    Granted,
+    Denied,
+}
+
+impl AuditDecision {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuditDecision::Granted => "granted",
+            AuditDecision::Denied => "denied",
+        }
+    }
+}
+
+// One line of audit.log, as JSON. `time` is Unix seconds, missing while the clock isn't set.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: Option<i64>,
+    pub event: AuditEvent,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<CardUid>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub member: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<AuditDecision>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reader: Option<String>,
+    // The auth backend that decided a tap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl AuditEntry {
+    pub const CSV_HEADER: &'static str = "time,event,uid,member,decision,reason,reader,source\n";
+
+    fn new(event: AuditEvent, reason: String) -> AuditEntry {
+        AuditEntry {
+            time: tz::utc_now().map(|now| now.timestamp()),
+            event,
+            uid: None,
+            member: String::new(),
+            decision: None,
+            reason,
+            reader: None,
+            source: None,
+        }
+    }
+
+    pub fn tap(uid: CardUid, decision: &AuthDecision, reader: &str) -> AuditEntry {
+        AuditEntry {
+            uid: Some(uid),
+            member: decision.member.clone(),
+            decision: Some(match decision.is_granted() {
+                true => AuditDecision::Granted,
+                false => AuditDecision::Denied,
+            }),
+            reader: Some(reader.to_string()),
+            source: decision.source.map(|source| source.to_string()),
+            ..AuditEntry::new(AuditEvent::Tap, decision.reason.to_string())
+        }
+    }
+
+    pub fn door(what: &str) -> AuditEntry {
+        AuditEntry::new(AuditEvent::Door, what.to_string())
+    }
+
+    pub fn admin(what: String) -> AuditEntry {
+        AuditEntry::new(AuditEvent::Admin, what)
+    }
+
+    pub fn alert(what: String, reader: Option<&str>) -> AuditEntry {
+        AuditEntry {
+            reader: reader.map(|reader| reader.to_string()),
+            ..AuditEntry::new(AuditEvent::Alert, what)
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let time = self
+            .time
+            .and_then(|time| chrono::DateTime::from_timestamp(time, 0))
+            .map(|time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            .unwrap_or_default();
+
+        let fields = [
+            time,
+            self.event.as_str().to_string(),
+            self.uid.map(|uid| uid.to_hex()).unwrap_or_default(),
+            self.member.clone(),
+            self.decision
+                .map(|decision| decision.as_str().to_string())
+                .unwrap_or_default(),
+            self.reason.clone(),
+            self.reader.clone().unwrap_or_default(),
+            self.source.clone().unwrap_or_default(),
+        ];
+
+        let mut line = fields.map(|field| quote(&field)).join(",");
+        line.push('\n');
+        line
+    }
+}
+
+fn quote(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+// Append-only log of taps, door events and admin actions in audit.log, one JSON object per
+// line. Once the file passes `AUDIT_MAX_LEN` it becomes audit.log.1, the one before that
+// audit.log.2 and so on, keeping `AUDIT_OLD_FILES` of them.
+pub struct AuditLog {}
+
+impl AuditLog {
+    // Failing to log never stops the door working, so errors are only printed.
+    pub fn record(entry: AuditEntry) {
+        let _lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        if let Err(err) = AuditLog::append(&entry) {
+            println!("Audit log write failed: {err:?}");
+        }
+    }
+
+    fn append(entry: &AuditEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = Spiffs::append(LOG_FILE)?;
+        file.write_all(line.as_bytes())?;
+
+        if file.metadata()?.len() > common::AUDIT_MAX_LEN {
+            drop(file);
+            AuditLog::rotate()?;
+        }
+
+        Ok(())
+    }
+
+    // SPIFFS can't rename over a file, so the oldest goes first
+    fn rotate() -> anyhow::Result<()> {
+        let oldest = AuditLog::file_name(common::AUDIT_OLD_FILES);
+
+        if Spiffs::stamp(&oldest).is_some() {
+            Spiffs::remove(&oldest)?;
+        }
+
+        for n in (0..common::AUDIT_OLD_FILES).rev() {
+            let from = AuditLog::file_name(n);
+
+            if Spiffs::stamp(&from).is_some() {
+                Spiffs::rename(&from, &AuditLog::file_name(n + 1))?;
+            }
+        }
+
+        ROTATIONS.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    // audit.log and its rotated copies, which only the log itself may write. Leading slashes
+    // and dots are ignored so another spelling of the same name doesn't get past.
+    pub fn is_log_file(name: &str) -> bool {
+        let name = name.trim_start_matches(['/', '.']);
+
+        name == LOG_FILE || name.starts_with(&format!("{}.", LOG_FILE))
+    }
+
+    fn file_name(n: usize) -> String {
+        match n {
+            0 => LOG_FILE.to_string(),
+            n => format!("{}.{}", LOG_FILE, n),
+        }
+    }
+
+    // Entries from `from` up to but not including `until` (Unix seconds), oldest first, at
+    // most `limit` of them. Entries logged before the clock was set only come back when no
+    // range is given.
+    //
+    // A page that is cut short comes with the cursor for the next one: the second of the first
+    // entry left out, and how many entries from that second have already been sent.
+    pub fn query(
+        from: Option<AuditCursor>,
+        until: Option<i64>,
+        limit: usize,
+    ) -> anyhow::Result<(Vec<AuditEntry>, Option<AuditCursor>)> {
+        let ranged = from.is_some() || until.is_some();
+        let mut entries = Vec::new();
+        let mut skipped = 0;
+        let mut reader = LogReader::new();
+
+        while let Some(line) = reader.next_line()? {
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) else {
+                continue;
+            };
+
+            let in_range = match entry.time {
+                Some(time) => {
+                    from.map_or(true, |from| time >= from.time)
+                        && until.map_or(true, |until| time < until)
+                }
+                None => !ranged,
+            };
+
+            if !in_range {
+                continue;
+            }
+
+            // Sent on an earlier page
+            if let Some(from) = from {
+                if entry.time == Some(from.time) && skipped < from.skip {
+                    skipped += 1;
+                    continue;
+                }
+            }
+
+            if entries.len() == limit {
+                let next = entry.time.map(|time| AuditCursor {
+                    time,
+                    skip: entries
+                        .iter()
+                        .filter(|entry| entry.time == Some(time))
+                        .count()
+                        + from
+                            .filter(|from| from.time == time)
+                            .map_or(0, |from| from.skip),
+                });
+
+                return Ok((entries, next));
+            }
+
+            entries.push(entry);
+        }
+
+        Ok((entries, None))
+    }
+}
+
+// Where a page of the audit log starts: entries from `time` on, less the first `skip` of those in
+// that second. Written "<time>" or "<time>.<skip>" in /audit's `from` and X-Next-From, so a
+// second with more entries than fit on a page still moves on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AuditCursor {
+    pub time: i64,
+    pub skip: usize,
+}
+
+impl FromStr for AuditCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (time, skip) = s.split_once('.').unwrap_or((s, "0"));
+
+        Ok(AuditCursor {
+            time: time.parse().map_err(|_| anyhow!("Bad time in {}", s))?,
+            skip: skip.parse().map_err(|_| anyhow!("Bad count in {}", s))?,
+        })
+    }
+}
+
+impl fmt::Display for AuditCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.skip {
+            0 => write!(f, "{}", self.time),
+            skip => write!(f, "{}.{}", self.time, skip),
+        }
+    }
+}
+
+// The log files' lines, oldest first, read a few at a time under the lock. A rotation in
+// between renumbers the files, so the one being read is followed to its new name.
+struct LogReader {
+    file: usize,
+    offset: u64,
+    rotations: usize,
+    lines: VecDeque<String>,
+    done: bool,
+}
+
+impl LogReader {
+    fn new() -> LogReader {
+        LogReader {
+            file: common::AUDIT_OLD_FILES,
+            offset: 0,
+            rotations: ROTATIONS.load(Ordering::Relaxed),
+            lines: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn next_line(&mut self) -> anyhow::Result<Option<String>> {
+        while self.lines.is_empty() && !self.done {
+            self.fill()?;
+        }
+
+        Ok(self.lines.pop_front())
+    }
+
+    fn fill(&mut self) -> anyhow::Result<()> {
+        let _lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        let rotations = ROTATIONS.load(Ordering::Relaxed);
+        self.file += rotations.wrapping_sub(self.rotations);
+        self.rotations = rotations;
+
+        // Rotated away before it was finished, the oldest file left is still to be read
+        if self.file > common::AUDIT_OLD_FILES {
+            self.file = common::AUDIT_OLD_FILES;
+            self.offset = 0;
+        }
+
+        let mut read = 0;
+
+        if let Ok(mut file) = Spiffs::open(&AuditLog::file_name(self.file)) {
+            file.seek(SeekFrom::Start(self.offset))?;
+
+            let mut file = BufReader::new(file);
+
+            while read < QUERY_CHUNK {
+                let mut line = String::new();
+                let len = file.read_line(&mut line)?;
+
+                if len == 0 {
+                    break;
+                }
+
+                self.offset += len as u64;
+                self.lines.push_back(line);
+                read += 1;
+            }
+        }
+
+        if read < QUERY_CHUNK {
+            match self.file {
+                0 => self.done = true,
+                _ => {
+                    self.file -= 1;
+                    self.offset = 0;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,168 +1,683 @@
-use anyhow::bail;
-use core::str;
-use embedded_svc::http::client::Client;
-use esp_idf_hal::io::Read;
-use esp_idf_svc::http::client::EspHttpConnection;
+pub mod backend;
+pub mod http;
+pub mod local;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 use tokio::sync::mpsc::Sender;
 
-use crate::{common::SystemMessage, spiffs::Spiffs};
+use crate::{
+    audit::{AuditEntry, AuditLog},
+    card_hash::CardKey,
+    card_uid::CardUid,
+    common::{self, AdminAction, SystemMessage},
+    credential::{MacKey, MemberCredential},
+    limiter::AttemptLimiter,
+    list_key::ListKey,
+    member_store::MemberStore,
+    members::Role,
+    pin::PinHash,
+    rfid::ReaderInfo,
+    tz::PosixTz,
+};
+use backend::{AuthDecision, BackendChain, Policy, Reason, StaticList};
+use http::HttpBackend;
+use local::LocalList;
+
+// Keys past this are ignored until '#' or '*'
+const MAX_PIN_LEN: usize = 12;
+
+// A card that was let in, waiting for its holder to type their PIN and '#'.
+struct PendingPin {
+    code: CardUid,
+    reader: ReaderInfo,
+    decision: AuthDecision,
+    pin: PinHash,
+    entered: String,
+    deadline: Instant,
+}
+
+// Wrong PINs for one card since its last right one. Kept in memory only, like the tap limiter,
+// so a restart forgets them along with any lockout.
+#[derive(Default)]
+struct PinFailures {
+    count: u8,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum EnrollMode {
+    Add,
+    Remove,
+}
+
+// The last admin let in, who can keep their card on the reader to confirm a change or start
+// enrolling.
+struct AdminTap {
+    code: CardUid,
+    admin: String,
+    reader: ReaderInfo,
+}
+
+// Armed by an admin's card, for the next card tapped on the same reader.
+struct Enrollment {
+    mode: EnrollMode,
+    admin: String,
+    reader: ReaderInfo,
+    deadline: Instant,
+}
+
+// Waiting for an admin's card to confirm it.
+struct PendingAdmin {
+    action: AdminAction,
+    deadline: Instant,
+}
 
 pub struct AuthService {
     tx: Sender<SystemMessage>,
+    admin_tap: Option<AdminTap>,
+    enrollment: Option<Enrollment>,
+    pending_admin: Option<PendingAdmin>,
+    members: Rc<RefCell<MemberStore>>,
+    backends: BackendChain,
+    pending_pin: Option<PendingPin>,
+    pin_failures: HashMap<CardUid, PinFailures>,
+    // Credentials are all rejected until the key has been set
+    mac_key: Option<MacKey>,
+    limiter: AttemptLimiter,
 }
 
 impl AuthService {
-    pub fn new(tx: Sender<SystemMessage>) -> AuthService {
-        AuthService { tx }
+    pub fn new(
+        tx: Sender<SystemMessage>,
+        device_id: String,
+        list_key: Option<ListKey>,
+        card_key: Option<CardKey>,
+        mac_key: Option<MacKey>,
+    ) -> AuthService {
+        let mut members = MemberStore::new(list_key, card_key);
+
+        // Also converts an old codes.txt on the first boot
+        if let Err(err) = members.refresh() {
+            println!("Member list unreadable: {err:?}");
+        }
+
+        let members = Rc::new(RefCell::new(members));
+
+        let timezone = common::TIMEZONE.parse().unwrap_or_else(|err| {
+            println!("{err}, using UTC");
+            PosixTz::utc()
+        });
+
+        // The server has the final say when there is one. The local list is used without it,
+        // or while it can't be reached.
+        let mut backends = BackendChain::new();
+
+        if !common::DENIED_CARDS.is_empty() {
+            backends.push(
+                Policy::DenyOverrides,
+                StaticList::deny(AuthService::static_cards(common::DENIED_CARDS)),
+            );
+        }
+
+        if !common::ALLOWED_CARDS.is_empty() {
+            backends.push(
+                Policy::FirstAnswer,
+                StaticList::allow(
+                    AuthService::static_cards(common::ALLOWED_CARDS),
+                    common::DOOR_UNLOCK,
+                ),
+            );
+        }
+
+        if let Some(url) = common::AUTH_SERVER_URL {
+            backends.push(Policy::IfOnline, HttpBackend::new(url, device_id));
+        }
+
+        backends.push(
+            Policy::FirstAnswer,
+            LocalList::new(members.clone(), timezone),
+        );
+
+        AuthService {
+            tx,
+            admin_tap: None,
+            enrollment: None,
+            pending_admin: None,
+            members,
+            backends,
+            pending_pin: None,
+            pin_failures: HashMap::new(),
+            mac_key,
+            limiter: AttemptLimiter::new(common::TAP_LIMITS),
+        }
     }
 
-    pub async fn check_text(&self, code: u32) -> anyhow::Result<bool> {
-        let codes =
-            Spiffs::read_string("codes.txt".to_string()).unwrap_or_else(|err| "".to_string());
+    fn static_cards(cards: &[(&str, &str)]) -> Vec<(CardUid, String)> {
+        cards
+            .iter()
+            .filter_map(|(uid, name)| match uid.parse() {
+                Ok(uid) => Some((uid, name.to_string())),
+                Err(err) => {
+                    println!("Skipping static card: {err}");
+                    None
+                }
+            })
+            .collect()
+    }
 
-        let lines = codes.split_whitespace();
+    // Admin status comes from the local list, the server doesn't deal in roles
+    fn is_admin(&self, code: &CardUid) -> bool {
+        self.members
+            .borrow()
+            .index()
+            .find(code)
+            .is_some_and(|member| member.role == Role::Admin)
+    }
 
-        for line in lines {
-            if code.to_string() == line {
-                self.tx
-                    .send(SystemMessage::OnAuth(code, code.to_string(), true))
-                    .await?;
+    // Whether the local list says the card must authenticate as a DESFire card
+    fn is_desfire(&self, code: &CardUid) -> bool {
+        self.members
+            .borrow()
+            .index()
+            .find_entry(code)
+            .is_some_and(|(entry, _)| entry.desfire)
+    }
+
+    // Bare UID with nothing to back it up. Good enough for most cards, but not for ones listed
+    // as DESFire cards: those have to authenticate, so their UID alone is turned away.
+    pub async fn check_text(&mut self, code: CardUid, reader: ReaderInfo) -> anyhow::Result<bool> {
+        if let Some(granted) = self.intercept(code, reader, false).await? {
+            return Ok(granted);
+        }
+
+        let mut decision = self.backends.decide(&code);
+
+        if decision.is_granted() && self.is_desfire(&code) {
+            println!(
+                "{} is a DESFire card, its UID alone isn't enough",
+                decision.member
+            );
+
+            decision = decision.overruled(Reason::UidOnly);
+        }
+
+        self.finish(code, reader, decision).await
+    }
+
+    // The card carried a credential block. It has to verify against this card's UID before
+    // the UID is looked up, so a copied UID without the matching block gets nowhere.
+    pub async fn check_credential(
+        &mut self,
+        code: CardUid,
+        credential: MemberCredential,
+        reader: ReaderInfo,
+    ) -> anyhow::Result<bool> {
+        if let Some(granted) = self.intercept(code, reader, false).await? {
+            return Ok(granted);
+        }
+
+        let verified = match &self.mac_key {
+            Some(key) => credential.verify(key, &code),
+            None => {
+                println!("No credential MAC key set");
+                false
+            }
+        };
+
+        let decision = match verified {
+            true => self.backends.decide(&code),
+            false => {
+                println!("Credential rejected for member {}", credential.member_id);
+
+                AuthDecision::denied(Reason::CredentialRejected)
+            }
+        };
+
+        self.finish(code, reader, decision).await
+    }
+
+    // The card passed DESFire mutual authentication with its diversified key, so the UID is
+    // genuine and only needs to be on the list.
+    pub async fn check_authenticated(
+        &mut self,
+        code: CardUid,
+        reader: ReaderInfo,
+    ) -> anyhow::Result<bool> {
+        if let Some(granted) = self.intercept(code, reader, true).await? {
+            return Ok(granted);
+        }
+
+        let decision = self.backends.decide(&code);
+
+        self.finish(code, reader, decision).await
+    }
+
+    // Taps that are dealt with before any decision: ones on a locked out reader, and ones an
+    // admin is enrolling cards with. `desfire` is for a card that has just authenticated.
+    async fn intercept(
+        &mut self,
+        code: CardUid,
+        reader: ReaderInfo,
+        desfire: bool,
+    ) -> anyhow::Result<Option<bool>> {
+        if let Some(decision) = self.locked_out(&reader) {
+            return self.send_result(code, reader, decision).await.map(Some);
+        }
+
+        self.enroll(code, reader, desfire).await
+    }
+
+    // The card tapped after an admin's is added, or removed once the admin has tapped a second
+    // time. A third tap stops without changing anything.
+    async fn enroll(
+        &mut self,
+        code: CardUid,
+        reader: ReaderInfo,
+        desfire: bool,
+    ) -> anyhow::Result<Option<bool>> {
+        let Some(enrollment) = self.enrollment.take() else {
+            return Ok(None);
+        };
+
+        if enrollment.reader != reader {
+            self.enrollment = Some(enrollment);
+
+            return Ok(None);
+        }
+
+        if Instant::now() > enrollment.deadline {
+            println!("Enrollment timed out");
+
+            return Ok(None);
+        }
 
-                return Ok(true);
+        if self.is_admin(&code) {
+            let prompt = match enrollment.mode {
+                EnrollMode::Add => {
+                    self.enrollment = Some(Enrollment {
+                        mode: EnrollMode::Remove,
+                        deadline: Instant::now() + common::ENROLL_TIMEOUT,
+                        ..enrollment
+                    });
+
+                    "Tap a card to remove."
+                }
+                EnrollMode::Remove => "Enrollment cancelled.",
             };
+
+            self.tx
+                .send(SystemMessage::Speak(prompt.to_string()))
+                .await?;
+
+            return Ok(Some(false));
         }
 
+        let (result, done) = match enrollment.mode {
+            EnrollMode::Add => (self.members.borrow_mut().enroll(code, desfire), "added"),
+            EnrollMode::Remove => (self.members.borrow_mut().unenroll(code), "removed"),
+        };
+
+        let prompt = match (result, enrollment.mode) {
+            (Ok(Some(name)), _) => {
+                println!("Card {:?} {} for {}", code, done, name);
+
+                AuditLog::record(AuditEntry {
+                    uid: Some(code),
+                    member: name,
+                    reader: Some(reader.name.to_string()),
+                    ..AuditEntry::admin(format!("card {} by {}", done, enrollment.admin))
+                });
+
+                format!("Card {}.", done)
+            }
+            (Ok(None), EnrollMode::Add) => "Card already enrolled.".to_string(),
+            (Ok(None), EnrollMode::Remove) => "Card not enrolled.".to_string(),
+            (Err(err), _) => {
+                println!("Enrollment failed: {err:?}");
+
+                "Enrollment failed.".to_string()
+            }
+        };
+
+        self.tx.send(SystemMessage::Speak(prompt)).await?;
+
+        Ok(Some(false))
+    }
+
+    // Taps on a locked out reader are turned away before the card is looked up, and aren't
+    // counted again so a lockout doesn't keep extending itself.
+    fn locked_out(&self, reader: &ReaderInfo) -> Option<AuthDecision> {
+        let (scope, left) = self.limiter.locked(reader.name, Instant::now())?;
+
+        println!(
+            "Tap on {} refused, {:?} locked out for {:?}",
+            reader.name, scope, left
+        );
+
+        Some(AuthDecision::denied(Reason::LockedOut))
+    }
+
+    // A denied card counts towards the limits, and the one that goes over them starts a
+    // lockout the main loop warns about.
+    async fn count_denied(&mut self, reader: &ReaderInfo) -> anyhow::Result<()> {
+        let Some(scope) = self.limiter.deny(reader.name, Instant::now()) else {
+            return Ok(());
+        };
+
+        println!("Too many denied taps, {:?} locked out", scope);
+
         self.tx
-            .send(SystemMessage::OnAuth(code, "".to_string(), false))
+            .send(SystemMessage::TapsLocked(scope, common::TAP_LIMITS.lockout))
+            .await?;
+
+        Ok(())
+    }
+
+    // Doors that need a PIN hold on to a granted card until the PIN has been typed.
+    async fn finish(
+        &mut self,
+        code: CardUid,
+        reader: ReaderInfo,
+        decision: AuthDecision,
+    ) -> anyhow::Result<bool> {
+        if !decision.is_granted() {
+            self.count_denied(&reader).await?;
+        }
+
+        if !common::REQUIRE_PIN || !decision.is_granted() {
+            return self.send_result(code, reader, decision).await;
+        }
+
+        let locked_until = self
+            .pin_failures
+            .get(&code)
+            .and_then(|failures| failures.locked_until)
+            .filter(|until| *until > Instant::now());
+
+        if let Some(until) = locked_until {
+            println!(
+                "{} is locked out for {:?} after wrong PINs",
+                decision.member,
+                until - Instant::now()
+            );
+
+            let decision = decision.overruled(Reason::PinLockedOut);
+
+            return self.send_result(code, reader, decision).await;
+        }
+
+        // The server doesn't deal in PINs, so they always come from the local list
+        let pin = self
+            .members
+            .borrow()
+            .index()
+            .find(&code)
+            .and_then(|member| member.pin.clone());
+
+        let Some(pin) = pin else {
+            println!("{} has no PIN set", decision.member);
+
+            let decision = decision.overruled(Reason::NoPin);
+
+            return self.send_result(code, reader, decision).await;
+        };
+
+        // Only one card waits for a PIN at a time, one left waiting is turned away
+        if let Some(abandoned) = self.pending_pin.take() {
+            println!("PIN entry abandoned for another card");
+
+            let decision = abandoned.decision.overruled(Reason::PinTimedOut);
+
+            self.send_result(abandoned.code, abandoned.reader, decision)
+                .await?;
+        }
+
+        self.pending_pin = Some(PendingPin {
+            code,
+            reader,
+            decision,
+            pin,
+            entered: String::new(),
+            deadline: Instant::now() + common::PIN_TIMEOUT,
+        });
+
+        self.tx
+            .send(SystemMessage::Speak("Enter PIN.".to_string()))
             .await?;
 
         Ok(false)
     }
 
-    pub async fn check_server(&self, code: u32) -> anyhow::Result<()> {
-        // HTTP Configuration
-        // Create HTTPS Connection Handle
-        let httpconnection = EspHttpConnection::new(&esp_idf_svc::http::client::Configuration {
-            use_global_ca_store: false,
-            crt_bundle_attach: Some(esp_idf_sys::esp_crt_bundle_attach),
-            ..Default::default()
-        })?;
+    // Turns away a card whose PIN wasn't finished in time, without waiting for another key.
+    pub async fn tick(&mut self) -> anyhow::Result<()> {
+        let expired = self
+            .pending_pin
+            .as_ref()
+            .is_some_and(|pending| Instant::now() > pending.deadline);
 
-        // Create HTTPS Client
-        let mut httpclient = Client::wrap(httpconnection);
+        if !expired {
+            return Ok(());
+        }
 
-        // HTTP Request Submission
-        // Define URL
-        let url = "http://10.3.2.151:8000";
+        println!("PIN entry timed out");
 
-        // Prepare request
-        let request = httpclient.get(url)?;
+        let pending = self.pending_pin.take().unwrap();
 
-        // Log URL and type of request
-        println!("-> GET {}", url);
+        let decision = pending.decision.overruled(Reason::PinTimedOut);
 
-        // Submit Request and Store Response
-        let response = request.submit()?;
+        self.send_result(pending.code, pending.reader, decision)
+            .await?;
+
+        Ok(())
+    }
 
-        // HTTP Response Processing
-        let status = response.status();
-        println!("<- {}", status);
+    // Keys make up the PIN for the last card presented, '*' starts it again and '#' ends it.
+    pub async fn check_key(&mut self, key: char) -> anyhow::Result<bool> {
+        // A key that comes in after the deadline doesn't count towards the PIN
+        self.tick().await?;
 
-        match response.header("Content-Length") {
-            Some(data) => {
-                println!("Content-Length: {}", data);
-            }
-            None => {
-                println!("No Content-Length Header");
+        let Some(pending) = self.pending_pin.as_mut() else {
+            return Ok(false);
+        };
+
+        match key {
+            '*' => pending.entered.clear(),
+            '#' => {
+                let pending = self.pending_pin.take().unwrap();
+
+                return self.check_pin(pending).await;
             }
+            _ if pending.entered.len() < MAX_PIN_LEN => pending.entered.push(key),
+            _ => {}
         }
 
-        match response.header("Date") {
-            Some(data) => {
-                println!("Date: {}", data);
-            }
-            None => {
-                println!("No Date Header");
-            }
+        Ok(false)
+    }
+
+    async fn check_pin(&mut self, mut pending: PendingPin) -> anyhow::Result<bool> {
+        let pin = pending.pin.clone();
+        let entered = std::mem::take(&mut pending.entered);
+
+        // PBKDF2 takes a while, the other services carry on in the meantime
+        let verified = tokio::task::spawn_blocking(move || pin.verify(&entered)).await?;
+
+        if verified {
+            self.pin_failures.remove(&pending.code);
+
+            return self
+                .send_result(pending.code, pending.reader, pending.decision)
+                .await;
         }
 
-        match status {
-            200..=299 => {
-                // 5. If the status is OK, read response data chunk by chunk into a buffer and print it until done.
-                //
-                // NB. There is no guarantee that chunks will be split at the boundaries of valid UTF-8
-                // sequences (in fact it is likely that they are not) so this edge case needs to be handled.
-                // However, for the purposes of clarity and brevity(?), the additional case of completely invalid
-                // UTF-8 sequences will not be handled here and is left as an exercise for the reader.
-                let mut buf = [0_u8; 256];
-                // Offset into the buffer to indicate that there may still be
-                // bytes at the beginning that have not been decoded yet
-                let mut offset = 0;
-                // Keep track of the total number of bytes read to print later
-                let mut total = 0;
-                let mut reader = response;
-
-                loop {
-                    // read into the buffer starting at the offset to not overwrite
-                    // the incomplete UTF-8 sequence we put there earlier
-                    if let Ok(size) = Read::read(&mut reader, &mut buf[offset..]) {
-                        if size == 0 {
-                            // It might be nice to check if we have any left over bytes here (ie. the offset > 0)
-                            // as this would mean that the response ended with an invalid UTF-8 sequence, but for the
-                            // purposes of this training we are assuming that the full response will be valid UTF-8
-                            break;
-                        }
-                        // Update the total number of bytes read
-                        total += size;
-                        // 6. Try converting the bytes into a Rust (UTF-8) string and print it.
-                        // Remember that we read into an offset and recalculate the real length
-                        // of the bytes to decode.
-                        let size_plus_offset = size + offset;
-                        match str::from_utf8(&buf[..size_plus_offset]) {
-                            Ok(text) => {
-                                // buffer contains fully valid UTF-8 data,
-                                // print it and reset the offset to 0.
-                                print!("{}", text);
-                                offset = 0;
-                            }
-                            Err(error) => {
-                                // The buffer contains incomplete UTF-8 data, we will
-                                // print the valid part, copy the invalid sequence to
-                                // the beginning of the buffer and set an offset for the
-                                // next read.
-                                //
-                                // NB. There is actually an additional case here that should be
-                                // handled in a real implementation. The Utf8Error may also contain
-                                // an error_len field indicating that there is actually an invalid UTF-8
-                                // sequence in the middle of the buffer. Such an error would not be
-                                // recoverable through our offset and copy mechanism. The result will be
-                                // that the invalid sequence will be copied to the front of the buffer and
-                                // eventually the buffer will be filled until no more bytes can be read when
-                                // the offset == buf.len(). At this point the loop will exit without reading
-                                // any more of the response.
-                                let valid_up_to = error.valid_up_to();
-                                unsafe {
-                                    // It's ok to use unsafe here as the error code already told us that
-                                    // the UTF-8 data up to this point is valid, so we can tell the compiler
-                                    // it's fine.
-                                    print!("{}", str::from_utf8_unchecked(&buf[..valid_up_to]));
-                                }
-                                buf.copy_within(valid_up_to.., 0);
-                                offset = size_plus_offset - valid_up_to;
-                            }
-                        }
-                    }
-                }
+        let failures = self.pin_failures.entry(pending.code).or_default();
+        failures.count += 1;
+
+        println!(
+            "Wrong PIN for {} ({} of {})",
+            pending.decision.member,
+            failures.count,
+            common::PIN_MAX_ATTEMPTS
+        );
 
-                println!("\nTotal: {} bytes", total);
+        if failures.count >= common::PIN_MAX_ATTEMPTS {
+            failures.count = 0;
+            failures.locked_until = Some(Instant::now() + common::PIN_LOCKOUT);
+        }
 
+        let decision = pending.decision.overruled(Reason::WrongPin);
+
+        self.send_result(pending.code, pending.reader, decision)
+            .await
+    }
+
+    // An admin who was just let in and kept their card on the reader for `ENROLL_HOLD` confirms
+    // the change waiting on them, or starts enrolling there when there is none.
+    pub async fn card_removed(&mut self, code: CardUid, held: Duration) -> anyhow::Result<()> {
+        if !self.admin_tap.as_ref().is_some_and(|tap| tap.code == code) {
+            return Ok(());
+        }
+
+        let tap = self.admin_tap.take().unwrap();
+
+        if held < common::ENROLL_HOLD {
+            return Ok(());
+        }
+
+        if let Some(pending) = self.pending_admin.take() {
+            if Instant::now() <= pending.deadline {
                 self.tx
-                    .send(SystemMessage::OnAuth(code, "Insert name here".to_string(), true))
+                    .send(SystemMessage::AdminConfirmed(pending.action, tap.admin))
                     .await?;
+
+                return Ok(());
             }
-            _ => bail!("Unexpected response code: {}", status),
+
+            println!("Admin confirmation timed out: {}", pending.action);
+        }
+
+        if let Some(why) = self.members.borrow().read_only() {
+            println!("Not enrolling, {}", why);
+
+            self.tx
+                .send(SystemMessage::Speak(format!(
+                    "Cards can't be enrolled here, {}.",
+                    why
+                )))
+                .await?;
+
+            return Ok(());
         }
 
+        self.enrollment = Some(Enrollment {
+            mode: EnrollMode::Add,
+            admin: tap.admin,
+            reader: tap.reader,
+            deadline: Instant::now() + common::ENROLL_TIMEOUT,
+        });
+
+        self.tx
+            .send(SystemMessage::Speak("Tap a card to add.".to_string()))
+            .await?;
+
+        Ok(())
+    }
+
+    // Holds on to a change asked for over HTTP until an admin is let in and holds their card on
+    // the reader. A newer one replaces it.
+    pub async fn confirm_admin(&mut self, action: AdminAction) -> anyhow::Result<()> {
+        println!("Waiting for an admin to confirm: {}", action);
+
+        self.pending_admin = Some(PendingAdmin {
+            action,
+            deadline: Instant::now() + common::ADMIN_CONFIRM_TIMEOUT,
+        });
+
+        self.tx
+            .send(SystemMessage::Speak(
+                "Hold an admin card on the reader to confirm.".to_string(),
+            ))
+            .await?;
+
         Ok(())
     }
+
+    async fn send_result(
+        &mut self,
+        code: CardUid,
+        reader: ReaderInfo,
+        decision: AuthDecision,
+    ) -> anyhow::Result<bool> {
+        AuditLog::record(AuditEntry::tap(code, &decision, reader.name));
+
+        let granted = decision.is_granted();
+        let guest = decision.guest;
+        let admin = (granted && self.is_admin(&code)).then(|| decision.member.clone());
+
+        self.tx.send(SystemMessage::OnAuth(code, decision)).await?;
+
+        if granted && guest {
+            let left = self.members.borrow_mut().record_use(&code);
+
+            match left {
+                Ok(Some(left)) => {
+                    println!("{} uses left", left);
+
+                    self.tx
+                        .send(SystemMessage::Speak(match left {
+                            1 => "1 use left.".to_string(),
+                            left => format!("{} uses left.", left),
+                        }))
+                        .await?;
+                }
+                Ok(None) => {}
+                Err(err) => println!("Guest pass use not saved: {err:?}"),
+            }
+        }
+
+        // Being let in never confirms a change by itself, the admin is told what holding their
+        // card there would confirm
+        let pending = self
+            .pending_admin
+            .as_ref()
+            .filter(|pending| Instant::now() <= pending.deadline);
+
+        if let (Some(pending), Some(_)) = (pending, &admin) {
+            self.tx
+                .send(SystemMessage::Speak(format!(
+                    "Hold your card to {}.",
+                    pending.action.spoken()
+                )))
+                .await?;
+        }
+
+        // Entry as usual, confirming or enrolling only happens once the card has been held there
+        self.admin_tap = admin.map(|admin| AdminTap {
+            code,
+            admin,
+            reader,
+        });
+
+        Ok(granted)
+    }
+
+    // The card talks ISO 14443-4 but failed DESFire authentication, so whatever its UID says
+    // it isn't one of ours.
+    pub async fn check_rejected(
+        &mut self,
+        code: CardUid,
+        reader: ReaderInfo,
+    ) -> anyhow::Result<bool> {
+        if let Some(granted) = self.intercept(code, reader, false).await? {
+            return Ok(granted);
+        }
+
+        let decision = AuthDecision::denied(Reason::CredentialRejected);
+
+        self.finish(code, reader, decision).await
+    }
 }
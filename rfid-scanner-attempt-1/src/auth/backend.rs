@@ -0,0 +1,274 @@
+use std::{fmt, time::Duration};
+
+use crate::card_uid::CardUid;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Granted,
+    Denied,
+}
+
+// Why a card was let in or turned away, written the way it is logged and spoken.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reason {
+    // Listed, and within their dates, access times and uses
+    Member,
+    UnknownCard,
+    // Outside the membership dates
+    Expired,
+    // Outside the access times
+    OutsideSchedule,
+    NoUsesLeft,
+    // On the deny list
+    Blocked,
+    // Too many denied taps on the reader
+    LockedOut,
+    // A backend failed and none of the others knew the card
+    ServerError,
+    // A bare UID for a card listed as a DESFire card
+    UidOnly,
+    CredentialRejected,
+    NoPin,
+    WrongPin,
+    PinTimedOut,
+    PinLockedOut,
+    // Turned away by the auth server without saying why
+    Denied,
+    // What the auth server said, when it isn't one of the above
+    Server(String),
+}
+
+impl Reason {
+    // The auth server's reasons for a denial, see api-server/main.ts
+    pub fn from_server(reason: &str) -> Reason {
+        match reason.trim() {
+            "" => Reason::Denied,
+            "unknown card" => Reason::UnknownCard,
+            "membership expired" => Reason::Expired,
+            reason => Reason::Server(reason.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Reason::Member => "member",
+            Reason::UnknownCard => "unknown card",
+            Reason::Expired => "membership expired",
+            Reason::OutsideSchedule => "outside access times",
+            Reason::NoUsesLeft => "no uses left",
+            Reason::Blocked => "card blocked",
+            Reason::LockedOut => "too many attempts",
+            Reason::ServerError => "server unavailable",
+            Reason::UidOnly => "card not secure",
+            Reason::CredentialRejected => "credential rejected",
+            Reason::NoPin => "no PIN set",
+            Reason::WrongPin => "wrong PIN",
+            Reason::PinTimedOut => "PIN timed out",
+            Reason::PinLockedOut => "PIN locked",
+            Reason::Denied => "no reason given",
+            Reason::Server(reason) => reason,
+        };
+
+        write!(f, "{}", reason)
+    }
+}
+
+// The outcome for one card and why, carried to the main loop in `OnAuth`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthDecision {
+    // Who holds the card, empty when nobody is known to
+    pub member: String,
+    pub outcome: Outcome,
+    pub reason: Reason,
+    pub unlock: Duration,
+    // The backend that answered, none for cards turned away before or after asking
+    pub source: Option<&'static str>,
+    // A guest pass from the local list, one use of which goes when the door opens
+    pub guest: bool,
+}
+
+impl AuthDecision {
+    pub fn granted(member: &str, unlock: Duration) -> AuthDecision {
+        AuthDecision {
+            member: member.to_string(),
+            outcome: Outcome::Granted,
+            reason: Reason::Member,
+            unlock,
+            source: None,
+            guest: false,
+        }
+    }
+
+    pub fn denied(reason: Reason) -> AuthDecision {
+        AuthDecision {
+            member: "".to_string(),
+            outcome: Outcome::Denied,
+            reason,
+            unlock: Duration::ZERO,
+            source: None,
+            guest: false,
+        }
+    }
+
+    // Turns the card away after all, keeping who it was for and where that came from.
+    pub fn overruled(self, reason: Reason) -> AuthDecision {
+        AuthDecision {
+            member: self.member,
+            source: self.source,
+            ..AuthDecision::denied(reason)
+        }
+    }
+
+    pub fn is_granted(&self) -> bool {
+        self.outcome == Outcome::Granted
+    }
+}
+
+// Somewhere a card can be looked up: the member list on flash, the auth server or a fixed list
+// of cards.
+pub trait AuthBackend {
+    // Short name for logs, e.g. "server"
+    fn name(&self) -> &'static str;
+
+    // Whether it is worth asking. Backends that have just failed to answer can say no, so taps
+    // don't each wait for them to fail again.
+    fn is_online(&self) -> bool {
+        true
+    }
+
+    // `Ok(None)` means the card isn't one this backend knows about.
+    fn decide(&mut self, code: &CardUid) -> anyhow::Result<Option<AuthDecision>>;
+}
+
+// How a backend's answer counts towards the chain's decision.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Policy {
+    // Its answer is final. Without one, or when it fails, the next backend is asked.
+    FirstAnswer,
+    // As `FirstAnswer`, but not asked at all while it says it is offline
+    IfOnline,
+    // Only its denials count, and they win over every other backend's answer. These are asked
+    // before the rest, wherever they are in the chain.
+    DenyOverrides,
+}
+
+// Backends asked in order until one gives an answer. A card nobody knows is denied, and the
+// answer carries the name of the backend that gave it.
+#[derive(Default)]
+pub struct BackendChain {
+    steps: Vec<(Policy, Box<dyn AuthBackend>)>,
+}
+
+impl BackendChain {
+    pub fn new() -> BackendChain {
+        BackendChain::default()
+    }
+
+    pub fn push(&mut self, policy: Policy, backend: impl AuthBackend + 'static) {
+        self.steps.push((policy, Box::new(backend)));
+    }
+
+    pub fn decide(&mut self, code: &CardUid) -> AuthDecision {
+        let mut failed = false;
+
+        for (_, backend) in self
+            .steps
+            .iter_mut()
+            .filter(|(policy, _)| *policy == Policy::DenyOverrides)
+        {
+            match backend.decide(code) {
+                Ok(Some(decision)) if !decision.is_granted() => {
+                    return AuthDecision {
+                        source: Some(backend.name()),
+                        ..decision
+                    };
+                }
+                Ok(_) => {}
+                Err(err) => println!("{} backend failed: {err:?}", backend.name()),
+            }
+        }
+
+        for (policy, backend) in self.steps.iter_mut() {
+            match policy {
+                Policy::DenyOverrides => continue,
+                Policy::IfOnline if !backend.is_online() => {
+                    println!("{} backend offline, skipped", backend.name());
+                    continue;
+                }
+                _ => {}
+            }
+
+            match backend.decide(code) {
+                Ok(Some(decision)) => {
+                    return AuthDecision {
+                        source: Some(backend.name()),
+                        ..decision
+                    };
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    println!("{} backend failed: {err:?}", backend.name());
+                    failed = true;
+                }
+            }
+        }
+
+        // Whoever failed might have known the card
+        AuthDecision::denied(match failed {
+            true => Reason::ServerError,
+            false => Reason::UnknownCard,
+        })
+    }
+}
+
+// A fixed set of cards, each with a name, that are all let in or all turned away.
+pub struct StaticList {
+    cards: Vec<(CardUid, String)>,
+    granted: bool,
+    unlock: Duration,
+}
+
+impl StaticList {
+    pub fn allow(cards: Vec<(CardUid, String)>, unlock: Duration) -> StaticList {
+        StaticList {
+            cards,
+            granted: true,
+            unlock,
+        }
+    }
+
+    pub fn deny(cards: Vec<(CardUid, String)>) -> StaticList {
+        StaticList {
+            cards,
+            granted: false,
+            unlock: Duration::ZERO,
+        }
+    }
+}
+
+impl AuthBackend for StaticList {
+    fn name(&self) -> &'static str {
+        match self.granted {
+            true => "allow list",
+            false => "deny list",
+        }
+    }
+
+    fn decide(&mut self, code: &CardUid) -> anyhow::Result<Option<AuthDecision>> {
+        let Some((_, name)) = self.cards.iter().find(|(card, _)| card == code) else {
+            return Ok(None);
+        };
+
+        let decision = match self.granted {
+            true => AuthDecision::granted(name, self.unlock),
+            false => AuthDecision {
+                member: name.clone(),
+                ..AuthDecision::denied(Reason::Blocked)
+            },
+        };
+
+        Ok(Some(decision))
+    }
+}
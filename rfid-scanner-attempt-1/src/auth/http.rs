@@ -0,0 +1,154 @@
+use anyhow::bail;
+use embedded_svc::http::client::Client;
+use esp_idf_hal::io::{Read, Write};
+use esp_idf_svc::http::client::{Configuration, EspHttpConnection};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use super::backend::{AuthBackend, AuthDecision, Reason};
+use crate::{card_uid::CardUid, common};
+
+// Responses are a small JSON object, anything bigger is a misbehaving server
+const MAX_RESPONSE_LEN: usize = 1024;
+
+// Longest unlock the server may ask for
+const MAX_SERVER_UNLOCK: Duration = Duration::from_secs(60);
+
+#[derive(Serialize)]
+struct ServerRequest<'a> {
+    uid: String,
+    device: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ServerResponse {
+    granted: bool,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    unlock_seconds: Option<u64>,
+}
+
+// The auth server, which has an answer for every card. After it fails it counts as offline
+// for `AUTH_SERVER_RETRY`.
+pub struct HttpBackend {
+    url: &'static str,
+    // Sent to the auth server so it knows which door is asking
+    device_id: String,
+    offline_until: Option<Instant>,
+}
+
+impl HttpBackend {
+    pub fn new(url: &'static str, device_id: String) -> HttpBackend {
+        HttpBackend {
+            url,
+            device_id,
+            offline_until: None,
+        }
+    }
+
+    // POSTs {"uid", "device"} and expects {"granted", "name", "reason", "unlock_seconds"}.
+    fn ask_server(&self, code: &CardUid) -> anyhow::Result<AuthDecision> {
+        let connection = EspHttpConnection::new(&Configuration {
+            timeout: Some(common::AUTH_SERVER_TIMEOUT),
+            use_global_ca_store: false,
+            crt_bundle_attach: Some(esp_idf_sys::esp_crt_bundle_attach),
+            ..Default::default()
+        })?;
+
+        let mut client = Client::wrap(connection);
+
+        let body = serde_json::to_vec(&ServerRequest {
+            uid: code.to_hex(),
+            device: &self.device_id,
+        })?;
+        let content_length = body.len().to_string();
+
+        let headers = [
+            ("Content-Type", "application/json"),
+            ("Content-Length", content_length.as_str()),
+        ];
+
+        println!("-> POST {}", self.url);
+
+        let mut request = client.post(self.url, &headers)?;
+        request.write_all(&body)?;
+
+        let mut response = request.submit()?;
+        let status = response.status();
+
+        println!("<- {}", status);
+
+        if !(200..=299).contains(&status) {
+            bail!("Unexpected response code: {}", status);
+        }
+
+        let mut body = Vec::new();
+        let mut buf = [0u8; 256];
+
+        loop {
+            let size = response.read(&mut buf)?;
+
+            if size == 0 {
+                break;
+            }
+
+            body.extend_from_slice(&buf[..size]);
+
+            if body.len() > MAX_RESPONSE_LEN {
+                bail!("Response longer than {} bytes", MAX_RESPONSE_LEN);
+            }
+        }
+
+        let response: ServerResponse = serde_json::from_slice(&body)?;
+
+        if let Some(reason) = &response.reason {
+            println!("Auth server: {}", reason);
+        }
+
+        let unlock = match response.unlock_seconds {
+            Some(seconds) => Duration::from_secs(seconds).min(MAX_SERVER_UNLOCK),
+            None => common::DOOR_UNLOCK,
+        };
+
+        let decision = match response.granted {
+            true => AuthDecision::granted(&response.name, unlock),
+            false => AuthDecision {
+                member: response.name,
+                ..AuthDecision::denied(Reason::from_server(
+                    response.reason.as_deref().unwrap_or_default(),
+                ))
+            },
+        };
+
+        Ok(decision)
+    }
+}
+
+impl AuthBackend for HttpBackend {
+    fn name(&self) -> &'static str {
+        "server"
+    }
+
+    fn is_online(&self) -> bool {
+        self.offline_until
+            .map_or(true, |until| Instant::now() >= until)
+    }
+
+    fn decide(&mut self, code: &CardUid) -> anyhow::Result<Option<AuthDecision>> {
+        match self.ask_server(code) {
+            Ok(decision) => {
+                self.offline_until = None;
+
+                Ok(Some(decision))
+            }
+            Err(err) => {
+                self.offline_until = Some(Instant::now() + common::AUTH_SERVER_RETRY);
+
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::backend::{AuthBackend, AuthDecision, Reason};
+use crate::{
+    card_uid::CardUid,
+    common,
+    member_store::MemberStore,
+    tz::{self, PosixTz},
+};
+
+// members.json on flash. The store is shared with `AuthService`, which also needs it for PINs,
+// enrollment and guest pass uses.
+pub struct LocalList {
+    members: Rc<RefCell<MemberStore>>,
+    timezone: PosixTz,
+}
+
+impl LocalList {
+    pub fn new(members: Rc<RefCell<MemberStore>>, timezone: PosixTz) -> LocalList {
+        LocalList { members, timezone }
+    }
+}
+
+impl AuthBackend for LocalList {
+    fn name(&self) -> &'static str {
+        "member list"
+    }
+
+    // Members are let in as long as they are within their membership dates and access times
+    // and have uses left.
+    fn decide(&mut self, code: &CardUid) -> anyhow::Result<Option<AuthDecision>> {
+        let mut members = self.members.borrow_mut();
+
+        if let Err(err) = members.refresh() {
+            println!("Member list unreadable: {err:?}");
+        }
+
+        let now = tz::utc_now().map(|now| self.timezone.to_local(now));
+
+        if let Err(err) = members.prune_uses(now) {
+            println!("Guest pass uses not pruned: {err:?}");
+        }
+
+        let Some(member) = members.index().find(code) else {
+            return Ok(None);
+        };
+
+        let denied = if !member.is_valid_at(now) {
+            Some(Reason::Expired)
+        } else if !members.access().allows(member, now) {
+            Some(Reason::OutsideSchedule)
+        } else if members.remaining_uses(code) == Some(0) {
+            Some(Reason::NoUsesLeft)
+        } else {
+            None
+        };
+
+        if let Some(reason) = denied {
+            println!("{}: {}", member.name, reason);
+
+            return Ok(Some(AuthDecision {
+                member: member.name.clone(),
+                ..AuthDecision::denied(reason)
+            }));
+        }
+
+        Ok(Some(AuthDecision {
+            guest: member.remaining_uses.is_some(),
+            ..AuthDecision::granted(&member.name, common::DOOR_UNLOCK)
+        }))
+    }
+}
@@ -0,0 +1,115 @@
+use hmac::{Hmac, Mac};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha256;
+use std::{fmt, str::FromStr};
+
+use crate::{card_uid::CardUid, hex};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const PREFIX: &str = "hmac-sha256:";
+
+// Secret the site's cards are hashed with, kept in NVS and in card-key.txt for the tools in
+// api-server.
+#[derive(Clone)]
+pub struct CardKey(Vec<u8>);
+
+impl CardKey {
+    // 16 to 64 bytes, the most NVS is read back into
+    pub fn from_bytes(bytes: &[u8]) -> Option<CardKey> {
+        (16..=64)
+            .contains(&bytes.len())
+            .then(|| CardKey(bytes.to_vec()))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    // HMAC-SHA256 over the UID's bytes
+    pub fn hash(&self, uid: &CardUid) -> CardHash {
+        let mut mac = HmacSha256::new_from_slice(&self.0).unwrap();
+        mac.update(uid.as_bytes());
+
+        CardHash(mac.finalize().into_bytes().into())
+    }
+}
+
+impl fmt::Debug for CardKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CardKey({} bytes)", self.0.len())
+    }
+}
+
+// A card's UID hashed with the card key, written "hmac-sha256:<hex>".
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CardHash([u8; 32]);
+
+impl fmt::Display for CardHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", PREFIX, hex::encode(&self.0))
+    }
+}
+
+// How members.json lists a card: by UID, or by its hash so a leaked copy of the file doesn't
+// give away what to clone.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CardId {
+    Uid(CardUid),
+    Hash(CardHash),
+}
+
+impl CardId {
+    // What this card is looked up by: its hash when there is a key, its UID otherwise.
+    // Without a key a hashed card can't be matched.
+    pub fn resolve(&self, key: Option<&CardKey>) -> CardId {
+        match (self, key) {
+            (CardId::Uid(uid), Some(key)) => CardId::Hash(key.hash(uid)),
+            _ => *self,
+        }
+    }
+}
+
+impl From<CardUid> for CardId {
+    fn from(uid: CardUid) -> Self {
+        CardId::Uid(uid)
+    }
+}
+
+impl FromStr for CardId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(hash) = s.strip_prefix(PREFIX) else {
+            return Ok(CardId::Uid(s.parse()?));
+        };
+
+        hex::decode(hash)
+            .and_then(|hash| hash.try_into().ok())
+            .map(|hash| CardId::Hash(CardHash(hash)))
+            .ok_or_else(|| anyhow::anyhow!("Invalid card hash: {:?}", s))
+    }
+}
+
+impl fmt::Display for CardId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardId::Uid(uid) => write!(f, "{}", uid.to_hex()),
+            CardId::Hash(hash) => write!(f, "{}", hash),
+        }
+    }
+}
+
+impl Serialize for CardId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CardId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
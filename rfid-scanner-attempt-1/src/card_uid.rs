@@ -0,0 +1,174 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+const MAX_UID_LEN: usize = 10;
+
+// ISO 14443-3 cascade levels. A card answers with 4, 7 or 10 UID bytes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum UidSize {
+    Single,
+    Double,
+    Triple,
+}
+
+impl UidSize {
+    pub fn from_len(len: usize) -> Option<UidSize> {
+        match len {
+            4 => Some(UidSize::Single),
+            7 => Some(UidSize::Double),
+            10 => Some(UidSize::Triple),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            UidSize::Single => 4,
+            UidSize::Double => 7,
+            UidSize::Triple => 10,
+        }
+    }
+}
+
+// The full UID as read during anticollision, in the order the card sent it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CardUid {
+    len: u8,
+    bytes: [u8; MAX_UID_LEN],
+}
+
+impl CardUid {
+    pub fn from_bytes(bytes: &[u8]) -> Option<CardUid> {
+        UidSize::from_len(bytes.len())?;
+
+        let mut buf = [0u8; MAX_UID_LEN];
+        buf[..bytes.len()].copy_from_slice(bytes);
+
+        Some(CardUid {
+            len: bytes.len() as u8,
+            bytes: buf,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    pub fn size(&self) -> UidSize {
+        UidSize::from_len(self.len as usize).unwrap()
+    }
+
+    // Colon separated, e.g. "04:A2:3B:C1:5E:61:80".
+    pub fn to_hex(&self) -> String {
+        self.as_bytes()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    // Little-endian, so a single size UID gives the same number the old `to_u32` did
+    // and existing codes.txt entries keep working.
+    pub fn to_decimal(&self) -> String {
+        let mut value: u128 = 0;
+
+        for (i, b) in self.as_bytes().iter().enumerate() {
+            value |= (*b as u128) << (8 * i);
+        }
+
+        value.to_string()
+    }
+
+    fn parse_hex(s: &str) -> Option<CardUid> {
+        let bytes = s
+            .split(':')
+            .map(|part| match part.len() {
+                2 => u8::from_str_radix(part, 16).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()?;
+
+        CardUid::from_bytes(&bytes)
+    }
+
+    // A decimal UID carries no length, so pick the smallest size the value fits in.
+    // Double and triple size UIDs should be written in hex to avoid any ambiguity.
+    fn parse_decimal(s: &str) -> Option<CardUid> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let value = s.parse::<u128>().ok()?;
+
+        let size = if value <= u32::MAX as u128 {
+            UidSize::Single
+        } else if value < 1 << 56 {
+            UidSize::Double
+        } else if value < 1 << 80 {
+            UidSize::Triple
+        } else {
+            return None;
+        };
+
+        CardUid::from_bytes(&value.to_le_bytes()[..size.len()])
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseCardUidError(String);
+
+impl fmt::Display for ParseCardUidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid card UID: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseCardUidError {}
+
+impl FromStr for CardUid {
+    type Err = ParseCardUidError;
+
+    // Accepts either the hex form ("04:A2:3B:C1:5E:61:80") or the decimal form ("2741061529").
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let uid = if s.contains(':') {
+            CardUid::parse_hex(s)
+        } else {
+            CardUid::parse_decimal(s)
+        };
+
+        uid.ok_or_else(|| ParseCardUidError(s.to_string()))
+    }
+}
+
+// Single size UIDs render as decimal to match codes.txt, anything longer renders as hex.
+impl fmt::Display for CardUid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.size() {
+            UidSize::Single => write!(f, "{}", self.to_decimal()),
+            _ => write!(f, "{}", self.to_hex()),
+        }
+    }
+}
+
+impl fmt::Debug for CardUid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CardUid({})", self.to_hex())
+    }
+}
+
+// Stored as hex, which keeps the length, but either form is read back.
+impl Serialize for CardUid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for CardUid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
@@ -1,4 +1,17 @@
-use std::sync::Arc;
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{
+    auth::backend::AuthDecision,
+    card_hash::CardKey,
+    card_uid::CardUid,
+    credential::{MacKey, MemberCredential},
+    limiter::{LimiterConfig, LockoutScope},
+    list_key::ListKey,
+    members::Role,
+    ndef::NdefRecord,
+    rfid::{desfire::MasterKey, mifare::KeyType, Direction, ReaderInfo},
+    wiegand::frame::WiegandFormat,
+};
 
 pub const MAX_DELAY: u32 = 0xffffffff;
 // pub const WLAN_SSID: &str = "The Lab 2.4GHz";
@@ -8,12 +21,194 @@ pub const MAX_DELAY: u32 = 0xffffffff;
 pub const WLAN_SSID: &str = "Leigh Hackspace - Main Space";
 pub const WLAN_PASS: &str = "caffeine1234";
 
+// MIFARE Classic sector holding the member credential. Sector 0 is left alone for the MAD.
+pub const MIFARE_SECTOR: u8 = 1;
+pub const MIFARE_KEY_TYPE: KeyType = KeyType::B;
+pub const MIFARE_KEY: [u8; 6] = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+// DESFire application holding the member key. Each card's key is diversified from the master
+// key with its UID (AN10922), so one cracked card doesn't give away the others. The master key
+// is kept in NVS, set through /desfire-key.
+pub const DESFIRE_AID: u32 = 0x4C4801;
+pub const DESFIRE_KEY_NO: u8 = 0x01;
+pub const DESFIRE_SYSTEM_ID: &[u8] = b"LeighHack";
+
+// Reader polling. With RFID_LPCD the MFRC522 sleeps with its field off while no card is
+// present and wakes every LPCD_INTERVAL for a short detection pulse, signalled on its IRQ pin
+// (GPIO 4). The thresholds only apply to that pulse: lower MinLevel and higher gain detect
+// cards further away but wake up on more noise.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Receiver gain, 0 (18 dB) to 7 (48 dB) on the MFRC522
+pub const RFID_ANTENNA_GAIN: u8 = 4;
+pub const RFID_LPCD: bool = false;
+pub const LPCD_INTERVAL: Duration = Duration::from_millis(300);
+pub const LPCD_PULSE: Duration = Duration::from_millis(30);
+pub const LPCD_MIN_LEVEL: u8 = 8;
+pub const LPCD_RX_GAIN: u8 = 4;
+
+// Readers on the door, each on its own SPI chip select: GPIO 44 outside, GPIO 14 inside. With
+// an inside reader fitted low-power detection is not used, both readers are polled in turn.
+pub const OUTER_READER: ReaderInfo = ReaderInfo {
+    name: "Outer",
+    direction: Direction::In,
+};
+pub const INNER_READER: Option<ReaderInfo> = None;
+
+// Wiegand to the alarm panel: every card read here is repeated in this format with this
+// facility code. Wiegand input takes cards from an external reader, which only ever sends a
+// number, so cards listed as DESFire cards are turned away when read there.
+pub const WIEGAND_OUTPUT: Option<WiegandFormat> = None;
+pub const WIEGAND_FACILITY: u16 = 1;
+pub const WIEGAND_INPUT: bool = false;
+pub const WIEGAND_READER: ReaderInfo = ReaderInfo {
+    name: "Wiegand",
+    direction: Direction::In,
+};
+
+// Local time for access schedules and membership dates, as a POSIX TZ string
+pub const TIMEZONE: &str = "GMT0BST,M3.5.0/1,M10.5.0";
+
+// Auth server asked about every card, see api-server/main.ts. The local member list is used
+// when it is unset or doesn't answer within the timeout.
+pub const AUTH_SERVER_URL: Option<&str> = None; // Some("http://10.3.2.151:8000/auth")
+pub const AUTH_SERVER_TIMEOUT: Duration = Duration::from_secs(3);
+// After the server fails to answer, taps go straight to the local list for this long
+pub const AUTH_SERVER_RETRY: Duration = Duration::from_secs(30);
+
+// Cards decided before the server and the member list, as (UID, name). A denied card stays
+// out whatever anything else says, an allowed one gets in. UIDs are hex, e.g. "04:A2:3B:C1".
+pub const DENIED_CARDS: &[(&str, &str)] = &[];
+pub const ALLOWED_CARDS: &[(&str, &str)] = &[];
+
+// Public key member lists must be signed with, as hex, built in with
+// `MEMBERS_PUBLIC_KEY=<hex> cargo build`. Without one the key is set through /list-key and an
+// admin's card, and until then lists are used unsigned.
+pub const MEMBERS_PUBLIC_KEY: Option<&str> = option_env!("MEMBERS_PUBLIC_KEY");
+
+// Where the member list is downloaded from into members.json, and how often. The server
+// answers 304 when the list hasn't changed since the last download.
+pub const MEMBER_SYNC_URL: Option<&str> = None; // Some("http://10.3.2.151:8000/members")
+pub const MEMBER_SYNC_INTERVAL: Duration = Duration::from_secs(5 * 60);
+pub const MEMBER_SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Doors that need a PIN typed after the card: on a 3x4 keypad wired to GPIO (keypad.rs) or a
+// Wiegand keypad on the Wiegand input. Members without a PIN are turned away from these.
+pub const REQUIRE_PIN: bool = false;
+pub const KEYPAD_MATRIX: bool = false;
+pub const PIN_TIMEOUT: Duration = Duration::from_secs(10);
+// How often the main loop checks deadlines like the PIN timeout
+pub const TICK_INTERVAL: Duration = Duration::from_secs(1);
+// Wrong PINs in a row before the card is locked out for a while
+pub const PIN_MAX_ATTEMPTS: u8 = 3;
+pub const PIN_LOCKOUT: Duration = Duration::from_secs(5 * 60);
+
+// Denied taps within a minute that lock out one reader, or all of them when spread across
+// readers, for a while. Taps during a lockout are denied without being looked up.
+pub const TAP_LIMITS: LimiterConfig = LimiterConfig {
+    window: Duration::from_secs(60),
+    per_reader: 5,
+    global: 10,
+    lockout: Duration::from_secs(2 * 60),
+};
+
+// A member with the admin role holding their card on a reader for `ENROLL_HOLD` can enroll the
+// next card tapped on that reader within `ENROLL_TIMEOUT`, or tap again to remove one instead.
+// A quick tap just lets them in. Enrolled cards get `ENROLL_ROLE`. Lists that are signed or
+// synced from a server can't be enrolled into, the admin is told so instead.
+pub const ENROLL_HOLD: Duration = Duration::from_secs(3);
+pub const ENROLL_TIMEOUT: Duration = Duration::from_secs(15);
+pub const ENROLL_ROLE: Role = Role::Member;
+
+// Changes asked for over HTTP wait this long for an admin to confirm them by holding their card
+// on a reader for `ENROLL_HOLD` after being let in, which then doesn't start enrolling. The
+// first admin has to come from a members.json flashed with flash-spiffs.sh.
+pub const ADMIN_CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How long the door stays unlocked after access is granted, unless the auth server asks for
+// another time
+pub const DOOR_UNLOCK: Duration = Duration::from_secs(5);
+
+// audit.log moves aside once it passes this size, and this many old ones are kept
+pub const AUDIT_MAX_LEN: u64 = 256 * 1024;
+pub const AUDIT_OLD_FILES: usize = 3;
+
+// Debounce: the same card is only identified once in this window, a different card only after
+// this gap. Set the gap to zero so a member right behind another isn't ignored.
+pub const REPEAT_WINDOW: Duration = Duration::from_secs(3);
+pub const OTHER_CARD_GAP: Duration = Duration::ZERO;
+
+// Something asked for over HTTP that only happens once an admin confirms it with their card.
+#[derive(Clone, Debug)]
+pub enum AdminAction {
+    // Write a member credential onto the next card presented
+    WriteCredential(u32),
+    ProvisionListKey(ListKey),
+    ProvisionCardKey(CardKey),
+    ProvisionMacKey(MacKey),
+    ProvisionDesfireKey(MasterKey),
+}
+
+impl fmt::Display for AdminAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminAction::WriteCredential(member_id) => {
+                write!(f, "write credential for member {}", member_id)
+            }
+            AdminAction::ProvisionListKey(key) => write!(f, "set member list key to {}", key),
+            AdminAction::ProvisionCardKey(_) => write!(f, "set card key"),
+            AdminAction::ProvisionMacKey(_) => write!(f, "set credential MAC key"),
+            AdminAction::ProvisionDesfireKey(_) => write!(f, "set DESFire master key"),
+        }
+    }
+}
+
+impl AdminAction {
+    // What an admin is asked to confirm out loud. Keys stay out of it.
+    pub fn spoken(&self) -> &'static str {
+        match self {
+            AdminAction::WriteCredential(_) => "confirm writing a credential",
+            AdminAction::ProvisionListKey(_) => "confirm the member list key change",
+            AdminAction::ProvisionCardKey(_) => "confirm the card key change",
+            AdminAction::ProvisionMacKey(_) => "confirm the credential MAC key change",
+            AdminAction::ProvisionDesfireKey(_) => "confirm the DESFire master key change",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum SystemMessage {
     Speak(String),
     WifiConnected(),
-    OnCard(u32),
-    OnAuth(u32, String, bool),
+    // A card arrived on the reader, sent before it is identified
+    OnCardPresented(CardUid),
+    // The card left the field after being held there for the given time
+    OnCardRemoved(CardUid, Duration),
+    // Identified cards carry the reader they were read on
+    OnCard(CardUid, ReaderInfo),
+    // NDEF records read from an Ultralight/NTAG tag, sent before its `OnCard`
+    OnNdef(CardUid, Vec<NdefRecord>),
+    // The reader stopped answering or failed its checks, and later came back
+    ReaderMissing(&'static str),
+    ReaderRestored(&'static str),
+    OnCredential(CardUid, MemberCredential, ReaderInfo),
+    // DESFire card that proved it holds its diversified key
+    OnAuthenticatedCard(CardUid, ReaderInfo),
+    // ISO 14443-4 card that failed DESFire authentication
+    OnRejectedCard(CardUid, ReaderInfo),
+    // Key pressed on a keypad: '0'-'9', '*' or '#'
+    OnKey(char),
+    // Sent every `TICK_INTERVAL` for deadlines nothing else would notice
+    Tick,
+    // Decision for a card, with who it was for, why and how long to unlock for
+    OnAuth(CardUid, AuthDecision),
+    // Too many denied taps, nothing is let in through the reader, or any reader, for a while
+    TapsLocked(LockoutScope, Duration),
+    // Asked for over HTTP, held until an admin holds their card on a reader
+    ConfirmAdmin(AdminAction),
+    // Confirmed by the named admin, for the main loop to carry out
+    AdminConfirmed(AdminAction, String),
+    SetAntennaGain(u8),
+    SetRfField(bool),
 
     OnOtaBuffer(Arc<Vec<u8>>),
 }
@@ -0,0 +1,93 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+
+use crate::card_uid::CardUid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const CREDENTIAL_VERSION: u8 = 0x01;
+const MAC_LEN: usize = 8;
+
+// Secret for the credential MAC, kept in NVS and set through /mac-key. Must match whatever
+// issued the cards.
+#[derive(Clone)]
+pub struct MacKey(Vec<u8>);
+
+impl MacKey {
+    // 16 to 64 bytes, like the card key
+    pub fn from_bytes(bytes: &[u8]) -> Option<MacKey> {
+        (16..=64)
+            .contains(&bytes.len())
+            .then(|| MacKey(bytes.to_vec()))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for MacKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MacKey({} bytes)", self.0.len())
+    }
+}
+
+// Member credential stored in one 16 byte card block:
+// version (1) | member id, big-endian (4) | MAC (8) | padding (3)
+//
+// The MAC is HMAC-SHA256(key, UID || member id) cut to 8 bytes, so a block copied onto a card
+// with a different UID, or an edited member id, no longer verifies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemberCredential {
+    pub member_id: u32,
+    pub mac: [u8; MAC_LEN],
+}
+
+impl MemberCredential {
+    pub fn issue(key: &MacKey, uid: &CardUid, member_id: u32) -> MemberCredential {
+        let tag = MemberCredential::hmac(key, uid, member_id)
+            .finalize()
+            .into_bytes();
+
+        let mut mac = [0u8; MAC_LEN];
+        mac.copy_from_slice(&tag[..MAC_LEN]);
+
+        MemberCredential { member_id, mac }
+    }
+
+    pub fn verify(&self, key: &MacKey, uid: &CardUid) -> bool {
+        MemberCredential::hmac(key, uid, self.member_id)
+            .verify_truncated_left(&self.mac)
+            .is_ok()
+    }
+
+    pub fn from_block(block: &[u8; 16]) -> Option<MemberCredential> {
+        if block[0] != CREDENTIAL_VERSION {
+            return None;
+        }
+
+        let mut mac = [0u8; MAC_LEN];
+        mac.copy_from_slice(&block[5..5 + MAC_LEN]);
+
+        Some(MemberCredential {
+            member_id: u32::from_be_bytes([block[1], block[2], block[3], block[4]]),
+            mac,
+        })
+    }
+
+    pub fn to_block(&self) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block[0] = CREDENTIAL_VERSION;
+        block[1..5].copy_from_slice(&self.member_id.to_be_bytes());
+        block[5..5 + MAC_LEN].copy_from_slice(&self.mac);
+        block
+    }
+
+    fn hmac(key: &MacKey, uid: &CardUid, member_id: u32) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&key.0).expect("HMAC accepts any key length");
+        mac.update(uid.as_bytes());
+        mac.update(&member_id.to_be_bytes());
+        mac
+    }
+}
@@ -0,0 +1,60 @@
+use esp_idf_hal::gpio::{Gpio43, Output, PinDriver};
+use std::time::Duration;
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::{sleep_until, Instant},
+};
+
+use crate::audit::{AuditEntry, AuditLog};
+
+// Drives the door strike. Unlocks arrive over a channel so whoever grants access can carry on
+// straight away; another unlock while the door is open extends the time instead of queueing.
+pub struct DoorService {
+    pin: PinDriver<'static, Gpio43, Output>,
+    unlock_tx: Sender<Duration>,
+    unlock_rx: Receiver<Duration>,
+}
+
+impl DoorService {
+    pub fn new() -> anyhow::Result<DoorService> {
+        let mut pin = PinDriver::output(unsafe { Gpio43::new() })?;
+
+        // Active low, start locked
+        pin.set_high()?;
+
+        let (unlock_tx, unlock_rx) = mpsc::channel(4);
+
+        Ok(DoorService {
+            pin,
+            unlock_tx,
+            unlock_rx,
+        })
+    }
+
+    pub fn unlocks(&self) -> Sender<Duration> {
+        self.unlock_tx.clone()
+    }
+
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        while let Some(duration) = self.unlock_rx.recv().await {
+            let mut until = Instant::now() + duration;
+
+            self.pin.set_low()?;
+            AuditLog::record(AuditEntry::door("unlocked"));
+
+            loop {
+                tokio::select! {
+                    Some(duration) = self.unlock_rx.recv() => {
+                        until = until.max(Instant::now() + duration);
+                    }
+                    _ = sleep_until(until) => break,
+                }
+            }
+
+            self.pin.set_high()?;
+            AuditLog::record(AuditEntry::door("locked"));
+        }
+
+        Ok(())
+    }
+}
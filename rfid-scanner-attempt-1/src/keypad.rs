@@ -0,0 +1,110 @@
+use esp_idf_hal::{
+    delay::Ets,
+    gpio::{
+        AnyIOPin, Gpio15, Gpio16, Gpio21, Gpio38, Gpio39, Gpio40, Gpio41, IOPin, Input, Output,
+        PinDriver, Pull,
+    },
+};
+use std::time::Duration;
+use tokio::{sync::mpsc::Sender, time::sleep};
+
+use crate::common::SystemMessage;
+
+const KEYS: [[char; 3]; 4] = [
+    ['1', '2', '3'],
+    ['4', '5', '6'],
+    ['7', '8', '9'],
+    ['*', '0', '#'],
+];
+
+const SCAN_INTERVAL: Duration = Duration::from_millis(20);
+
+// Scans a key has to be seen down for, to ride out contact bounce
+const DEBOUNCE_SCANS: u8 = 2;
+
+// 3x4 membrane keypad: rows on GPIO 15/16/21/38 pulled up, columns on GPIO 39/40/41 driven low
+// one at a time. Each key goes out as `OnKey` when pressed, holding it doesn't repeat.
+pub struct KeypadService {
+    tx: Sender<SystemMessage>,
+    rows: Vec<PinDriver<'static, AnyIOPin, Input>>,
+    columns: Vec<PinDriver<'static, AnyIOPin, Output>>,
+}
+
+impl KeypadService {
+    pub fn new(tx: Sender<SystemMessage>) -> anyhow::Result<KeypadService> {
+        let rows = [
+            unsafe { Gpio15::new() }.downgrade(),
+            unsafe { Gpio16::new() }.downgrade(),
+            unsafe { Gpio21::new() }.downgrade(),
+            unsafe { Gpio38::new() }.downgrade(),
+        ]
+        .into_iter()
+        .map(|pin| {
+            let mut row = PinDriver::input(pin)?;
+            row.set_pull(Pull::Up)?;
+            Ok(row)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let columns = [
+            unsafe { Gpio39::new() }.downgrade(),
+            unsafe { Gpio40::new() }.downgrade(),
+            unsafe { Gpio41::new() }.downgrade(),
+        ]
+        .into_iter()
+        .map(|pin| {
+            let mut column = PinDriver::output(pin)?;
+            column.set_high()?;
+            Ok(column)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(KeypadService { tx, rows, columns })
+    }
+
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        let mut down: Option<char> = None;
+        let mut scans = 0u8;
+
+        loop {
+            let key = self.scan()?;
+
+            if key.is_some() && key == down {
+                scans = scans.saturating_add(1);
+
+                if scans == DEBOUNCE_SCANS {
+                    self.tx.send(SystemMessage::OnKey(key.unwrap())).await?;
+                }
+            } else {
+                down = key;
+                scans = 1;
+            }
+
+            sleep(SCAN_INTERVAL).await;
+        }
+    }
+
+    // The first key found down, if any.
+    fn scan(&mut self) -> anyhow::Result<Option<char>> {
+        let mut found = None;
+
+        for (c, column) in self.columns.iter_mut().enumerate() {
+            column.set_low()?;
+
+            // Give the row lines a moment to settle through the pull-ups
+            Ets::delay_us(10);
+
+            if found.is_none() {
+                found = self
+                    .rows
+                    .iter()
+                    .position(|row| row.is_low())
+                    .map(|r| KEYS[r][c]);
+            }
+
+            column.set_high()?;
+        }
+
+        Ok(found)
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::bail;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+
+use crate::{
+    card_hash::CardKey, common, credential::MacKey, list_key::ListKey, rfid::desfire::MasterKey,
+};
+
+const NAMESPACE: &str = "keys";
+const LIST_KEY: &str = "list_key";
+const CARD_KEY: &str = "card_key";
+const MAC_KEY: &str = "mac_key";
+const DESFIRE_KEY: &str = "desfire_key";
+
+// Keys kept in NVS, where the file upload endpoints can't reach them.
+pub struct KeyStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl KeyStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> anyhow::Result<KeyStore> {
+        Ok(KeyStore {
+            nvs: EspNvs::new(partition, NAMESPACE, true)?,
+        })
+    }
+
+    // `MEMBERS_PUBLIC_KEY` when it is built in, otherwise the one set through /list-key.
+    pub fn list_key(&self) -> anyhow::Result<Option<ListKey>> {
+        if let Some(key) = common::MEMBERS_PUBLIC_KEY {
+            return Ok(Some(key.parse()?));
+        }
+
+        let mut buf = [0u8; 32];
+
+        match self.nvs.get_raw(LIST_KEY, &mut buf)? {
+            Some(bytes) => Ok(Some(ListKey::from_bytes(bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Only called once an admin has confirmed it at the door, so it may replace an old key.
+    // A key built into the firmware can't be replaced.
+    pub fn set_list_key(&mut self, key: ListKey) -> anyhow::Result<()> {
+        if common::MEMBERS_PUBLIC_KEY.is_some() {
+            bail!("Member list key is built into the firmware");
+        }
+
+        self.nvs.set_raw(LIST_KEY, &key.to_bytes())?;
+
+        Ok(())
+    }
+
+    // The key card UIDs are hashed with, set through /card-key. It is secret, so unlike the
+    // list key it is never built into the firmware.
+    pub fn card_key(&self) -> anyhow::Result<Option<CardKey>> {
+        let mut buf = [0u8; 64];
+
+        match self.nvs.get_raw(CARD_KEY, &mut buf)? {
+            Some(bytes) => Ok(CardKey::from_bytes(bytes)),
+            None => Ok(None),
+        }
+    }
+
+    // Also confirmed by an admin. Hashed cards in the list stop matching until it has been
+    // hashed again with the new key.
+    pub fn set_card_key(&mut self, key: CardKey) -> anyhow::Result<()> {
+        self.nvs.set_raw(CARD_KEY, key.as_bytes())?;
+
+        Ok(())
+    }
+
+    // The credential MAC key. Without it credential blocks are neither written nor trusted.
+    pub fn mac_key(&self) -> anyhow::Result<Option<MacKey>> {
+        let mut buf = [0u8; 64];
+
+        match self.nvs.get_raw(MAC_KEY, &mut buf)? {
+            Some(bytes) => Ok(MacKey::from_bytes(bytes)),
+            None => Ok(None),
+        }
+    }
+
+    // Only called once an admin has confirmed it at the door, so it may replace an old key.
+    // Cards written with the old one have to be written again.
+    pub fn set_mac_key(&mut self, key: MacKey) -> anyhow::Result<()> {
+        self.nvs.set_raw(MAC_KEY, key.as_bytes())?;
+
+        Ok(())
+    }
+
+    // The DESFire master key. Without it no DESFire card can authenticate.
+    pub fn desfire_key(&self) -> anyhow::Result<Option<MasterKey>> {
+        let mut buf = [0u8; 16];
+
+        match self.nvs.get_raw(DESFIRE_KEY, &mut buf)? {
+            Some(bytes) => Ok(MasterKey::from_bytes(bytes)),
+            None => Ok(None),
+        }
+    }
+
+    // Likewise confirmed by an admin. Cards keyed from the old master key stop authenticating.
+    pub fn set_desfire_key(&mut self, key: MasterKey) -> anyhow::Result<()> {
+        self.nvs.set_raw(DESFIRE_KEY, key.as_bytes())?;
+
+        Ok(())
+    }
+}
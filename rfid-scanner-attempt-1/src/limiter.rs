@@ -0,0 +1,105 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LockoutScope {
+    // One reader, by name
+    Reader(&'static str),
+    // Every reader, from denials spread across them
+    Global,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LimiterConfig {
+    pub window: Duration,
+    // Denied taps within the window that start a lockout
+    pub per_reader: usize,
+    pub global: usize,
+    pub lockout: Duration,
+}
+
+// Denied taps in the last `window`, and the lockout they led to.
+#[derive(Default)]
+struct Counter {
+    denied: VecDeque<Instant>,
+    locked_until: Option<Instant>,
+}
+
+impl Counter {
+    fn locked(&self, now: Instant) -> Option<Duration> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    // Whether this denial started a lockout
+    fn deny(&mut self, now: Instant, config: &LimiterConfig, limit: usize) -> bool {
+        while self
+            .denied
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= config.window)
+        {
+            self.denied.pop_front();
+        }
+
+        self.denied.push_back(now);
+
+        if self.denied.len() < limit {
+            return false;
+        }
+
+        self.denied.clear();
+        self.locked_until = Some(now + config.lockout);
+
+        true
+    }
+}
+
+// Slows down someone trying a stack of cards: too many denied taps in a sliding window lock a
+// reader, or every reader when they are spread around, for a while.
+pub struct AttemptLimiter {
+    config: LimiterConfig,
+    readers: HashMap<&'static str, Counter>,
+    global: Counter,
+}
+
+impl AttemptLimiter {
+    pub fn new(config: LimiterConfig) -> AttemptLimiter {
+        AttemptLimiter {
+            config,
+            readers: HashMap::new(),
+            global: Counter::default(),
+        }
+    }
+
+    // How much longer taps on `reader` are refused, if they are.
+    pub fn locked(&self, reader: &'static str, now: Instant) -> Option<(LockoutScope, Duration)> {
+        if let Some(left) = self.global.locked(now) {
+            return Some((LockoutScope::Global, left));
+        }
+
+        let left = self.readers.get(reader)?.locked(now)?;
+
+        Some((LockoutScope::Reader(reader), left))
+    }
+
+    // Counts a denied tap against the reader and every reader. Returns the lockout it started,
+    // if any, the global one when both start at once.
+    pub fn deny(&mut self, reader: &'static str, now: Instant) -> Option<LockoutScope> {
+        let config = self.config;
+
+        let reader_locked =
+            self.readers
+                .entry(reader)
+                .or_default()
+                .deny(now, &config, config.per_reader);
+
+        if self.global.deny(now, &config, config.global) {
+            return Some(LockoutScope::Global);
+        }
+
+        reader_locked.then_some(LockoutScope::Reader(reader))
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::{anyhow, bail};
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::{fmt, str::FromStr};
+
+use crate::hex;
+
+// Public half of the Ed25519 key member lists are signed with. A list comes with the
+// signature of its exact bytes, in hex, in a file of its own (members.json.sig).
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct ListKey(VerifyingKey);
+
+impl ListKey {
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<ListKey> {
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("Public key must be 32 bytes, not {}", bytes.len()))?;
+
+        Ok(ListKey(VerifyingKey::from_bytes(&bytes)?))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn verify(&self, list: &[u8], signature: &str) -> anyhow::Result<()> {
+        let Some(signature) = hex::decode(signature.trim()) else {
+            bail!("Signature is not hex");
+        };
+
+        let signature = Signature::from_slice(&signature)?;
+
+        // Strict rejects the malleable forms plain verify lets through
+        self.0.verify_strict(list, &signature)?;
+
+        Ok(())
+    }
+}
+
+impl FromStr for ListKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match hex::decode(s.trim()) {
+            Some(bytes) => ListKey::from_bytes(&bytes),
+            None => bail!("Public key is not hex"),
+        }
+    }
+}
+
+impl fmt::Display for ListKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.to_bytes()))
+    }
+}
+
+impl fmt::Debug for ListKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ListKey({})", self)
+    }
+}
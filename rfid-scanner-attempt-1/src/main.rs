@@ -1,29 +1,63 @@
 #![feature(async_closure)]
 #![feature(raw_ref_op)]
 mod audio;
+mod audit;
 mod auth;
+mod card_hash;
+mod card_uid;
 mod common;
+mod credential;
+mod door;
+mod hex;
+mod keypad;
+mod keystore;
+mod limiter;
+mod list_key;
+mod member_store;
+mod member_sync;
+mod members;
+mod ndef;
+mod pin;
 mod rfid;
+mod schedule;
 mod server;
 mod speech;
 mod spiffs;
+mod tz;
+mod uses;
+mod wiegand;
 mod wifi;
 
 use audio::AudioService;
+use audit::{AuditEntry, AuditLog};
 use auth::AuthService;
-use common::SystemMessage;
-use esp_idf_hal::{cpu::Core, delay, gpio::PinDriver, peripherals};
-use esp_idf_svc::{eventloop::EspSystemEventLoop, nvs, ota::EspOta, timer::EspTaskTimerService};
+use common::{AdminAction, SystemMessage};
+use door::DoorService;
+use esp_idf_hal::{cpu::Core, delay, peripherals};
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop, nvs, ota::EspOta, sntp::EspSntp, timer::EspTaskTimerService,
+};
 use esp_idf_sys::{printf, sys_delay_ms};
+use keypad::KeypadService;
+use keystore::KeyStore;
+use limiter::LockoutScope;
 use log::{error, info, warn};
-use rfid::RfidService;
+use member_sync::MemberSyncService;
+use rfid::{
+    mfrc522::{LpcdConfig, Mfrc522Spi},
+    CardReader, CardSecrets, RfidCommand, RfidService,
+};
 use server::HttpServer;
 use speech::SpeechService;
 use spiffs::Spiffs;
-use std::{error::Error, ffi::CString, time::Duration};
-use tokio::{sync::mpsc, time::sleep};
+use std::{error::Error, ffi::CString};
+use tokio::sync::mpsc;
+use wiegand::WiegandService;
 use wifi::WifiConnection;
 
+// Threads for blocking work, such as member list downloads, need room for TLS
+const BLOCKING_STACK_SIZE: usize = 16 * 1024;
+
 fn main() {
     // It is necessary to call this function once. Otherwise some patches to the runtime
     // implemented by esp-idf-sys might not link properly. See https://github.com/esp-rs/esp-idf-template/issues/71
@@ -36,6 +70,7 @@ fn main() {
 
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
+        .thread_stack_size(BLOCKING_STACK_SIZE)
         .build()
         .expect("Failed to build Tokio runtime");
 
@@ -67,8 +102,8 @@ async fn async_main() -> Result<(), Box<dyn Error>> {
 
     let (message_bus_tx, mut message_bus_rx) = mpsc::channel::<SystemMessage>(10);
 
-    let mut door = unsafe { PinDriver::output(esp_idf_hal::gpio::Gpio43::new()).unwrap() };
-    door.set_high().unwrap();
+    let mut door_service = DoorService::new()?;
+    let door_unlocks = door_service.unlocks();
 
     Spiffs::init()?;
 
@@ -83,12 +118,86 @@ async fn async_main() -> Result<(), Box<dyn Error>> {
 
     speech_service.speak(ready_msg);
 
-    let rfid_service = RfidService::new(message_bus_tx.clone());
+    let nvs_default_partition = nvs::EspDefaultNvsPartition::take().unwrap();
+
+    let mut key_store = KeyStore::new(nvs_default_partition.clone())?;
+    let list_key = key_store.list_key()?;
+    let card_key = key_store.card_key()?;
+    let mac_key = key_store.mac_key()?;
+    let desfire_key = key_store.desfire_key()?;
+
+    match list_key {
+        Some(key) => info!("Member lists must be signed by {}", key),
+        None => warn!("No member list key, lists are used unsigned"),
+    }
+
+    if card_key.is_none() {
+        warn!("No card key, only cards listed by UID can be matched");
+    }
+
+    if mac_key.is_none() {
+        warn!("No credential MAC key, MIFARE Classic credentials are rejected");
+    }
+
+    if desfire_key.is_none() {
+        warn!("No DESFire master key, DESFire cards are rejected");
+    }
+
+    // Swap for `Mfrc522I2c::i2c()`, `Pn532Reader::new(Pn532I2c::new()?)` or
+    // `Pn532Reader::new(Pn532Uart::new()?)` depending on the module fitted.
+    let (mut reader, mut inner_reader) = match common::INNER_READER {
+        Some(_) => {
+            let (outer, inner) = Mfrc522Spi::spi_pair()?;
+            (outer, Some(inner))
+        }
+        None => (Mfrc522Spi::spi()?, None),
+    };
+
+    for reader in std::iter::once(&mut reader).chain(inner_reader.as_mut()) {
+        if let Err(err) = reader.set_antenna_gain(common::RFID_ANTENNA_GAIN) {
+            warn!("Antenna gain not set: {err:?}");
+        }
+    }
+
+    if common::RFID_LPCD && inner_reader.is_none() {
+        reader = reader.with_lpcd(LpcdConfig {
+            interval: common::LPCD_INTERVAL,
+            pulse: common::LPCD_PULSE,
+            min_level: common::LPCD_MIN_LEVEL,
+            rx_gain: common::LPCD_RX_GAIN,
+        })?;
+    }
+
+    let mut rfid_service = RfidService::new(
+        message_bus_tx.clone(),
+        reader,
+        common::OUTER_READER,
+        CardSecrets {
+            mac_key: mac_key.clone(),
+            desfire_key,
+        },
+    );
+
+    if let (Some(inner), Some(info)) = (inner_reader, common::INNER_READER) {
+        rfid_service = rfid_service.with_reader(inner, info);
+    }
+
+    let mut wiegand_service = WiegandService::new(
+        message_bus_tx.clone(),
+        common::WIEGAND_OUTPUT.map(|format| (format, common::WIEGAND_FACILITY)),
+        common::WIEGAND_INPUT,
+    )?;
+    let wiegand_cards = wiegand_service.cards();
+
+    let mut keypad_service = match common::KEYPAD_MATRIX {
+        true => Some(KeypadService::new(message_bus_tx.clone())?),
+        false => None,
+    };
+    let rfid_commands = rfid_service.commands();
 
     let event_loop = EspSystemEventLoop::take().unwrap();
     let timer = EspTaskTimerService::new()?;
     let peripherals = peripherals::Peripherals::take().unwrap();
-    let nvs_default_partition = nvs::EspDefaultNvsPartition::take().unwrap();
 
     match esp_idf_hal::cpu::core() {
         Core::Core0 => info!("running on core 0"),
@@ -105,7 +214,19 @@ async fn async_main() -> Result<(), Box<dyn Error>> {
     )
     .await?;
 
-    let auth_service = AuthService::new(message_bus_tx.clone());
+    let mut auth_service = AuthService::new(
+        message_bus_tx.clone(),
+        wifi_connection.state.mac_address.clone(),
+        list_key,
+        card_key,
+        mac_key,
+    );
+
+    let mut member_sync_service = MemberSyncService::new(list_key);
+    let sync_connected = member_sync_service.connected();
+
+    // Membership dates and schedules need the time, kept alive once the network is up
+    let mut sntp = None;
 
     let mut app_loop = async || -> anyhow::Result<()> {
         loop {
@@ -117,30 +238,198 @@ async fn async_main() -> Result<(), Box<dyn Error>> {
                     SystemMessage::WifiConnected() => {
                         http_server.start().unwrap();
 
+                        if sntp.is_none() {
+                            sntp = Some(EspSntp::new_default()?);
+                        }
+
+                        // Only full when a sync is already due
+                        let _ = sync_connected.try_send(());
+
                         speech_service.speak("Connected".to_string());
                     }
-                    SystemMessage::OnCard(code) => {
-                        println!("==== Code: {:?}", code);
+                    SystemMessage::OnCardPresented(code) => {
+                        println!("==== Presented: {:?}", code);
+
+                        wiegand_cards.send(code).await?;
+                    }
+                    SystemMessage::OnCardRemoved(code, held) => {
+                        println!("==== Removed: {:?} held {:?}", code, held);
 
-                        if let Err(err) = auth_service.check_text(code).await {
+                        if let Err(err) = auth_service.card_removed(code, held).await {
                             warn!("Auth failed: {err:?}");
                         }
                     }
-                    SystemMessage::OnAuth(code, name, granted) => {
-                        println!("==== Name: {:?}", name);
+                    SystemMessage::ReaderMissing(name) => {
+                        warn!("{} reader missing", name);
 
-                        if granted {
-                            speech_service.speak(format!("Access granted {}.", name));
+                        speech_service.speak("Card reader fault.".to_string());
+                    }
+                    SystemMessage::ReaderRestored(name) => {
+                        info!("{} reader restored", name);
 
-                            door.set_low().unwrap();
+                        speech_service.speak("Card reader restored.".to_string());
+                    }
+                    SystemMessage::OnNdef(code, records) => {
+                        for record in records {
+                            println!("==== NDEF {:?}: {:?}", code, record);
+                        }
+                    }
+                    SystemMessage::OnCard(code, reader) => {
+                        println!("==== Code: {:?} on {:?}", code, reader);
 
-                            sleep(Duration::from_millis(5000)).await;
+                        if let Err(err) = auth_service.check_text(code, reader).await {
+                            warn!("Auth failed: {err:?}");
+                        }
+                    }
+                    SystemMessage::OnCredential(code, credential, reader) => {
+                        println!(
+                            "==== Code: {:?} Member: {} on {:?}",
+                            code, credential.member_id, reader
+                        );
+
+                        if let Err(err) = auth_service
+                            .check_credential(code, credential, reader)
+                            .await
+                        {
+                            warn!("Auth failed: {err:?}");
+                        }
+                    }
+                    SystemMessage::OnAuthenticatedCard(code, reader) => {
+                        println!("==== Code: {:?} (DESFire) on {:?}", code, reader);
 
-                            door.set_high().unwrap();
+                        if let Err(err) = auth_service.check_authenticated(code, reader).await {
+                            warn!("Auth failed: {err:?}");
+                        }
+                    }
+                    SystemMessage::OnRejectedCard(code, reader) => {
+                        println!("==== Code: {:?} (DESFire, rejected) on {:?}", code, reader);
+
+                        if let Err(err) = auth_service.check_rejected(code, reader).await {
+                            warn!("Auth failed: {err:?}");
+                        }
+                    }
+                    SystemMessage::OnKey(key) => {
+                        if let Err(err) = auth_service.check_key(key).await {
+                            warn!("Auth failed: {err:?}");
+                        }
+                    }
+                    SystemMessage::Tick => {
+                        if let Err(err) = auth_service.tick().await {
+                            warn!("Auth failed: {err:?}");
+                        }
+                    }
+                    SystemMessage::ConfirmAdmin(action) => {
+                        if let Err(err) = auth_service.confirm_admin(action).await {
+                            warn!("Confirmation not started: {err:?}");
+                        }
+                    }
+                    SystemMessage::AdminConfirmed(action, admin) => {
+                        println!("==== {} confirmed by {}", action, admin);
+
+                        let entry = AuditEntry {
+                            member: admin,
+                            ..AuditEntry::admin(action.to_string())
+                        };
+
+                        match action {
+                            AdminAction::WriteCredential(member_id) => {
+                                AuditLog::record(entry);
+
+                                rfid_commands
+                                    .send(RfidCommand::WriteCredential(member_id))
+                                    .await?;
+
+                                speech_service.speak("Present card to write.".to_string());
+                            }
+                            AdminAction::ProvisionListKey(key) => {
+                                match key_store.set_list_key(key) {
+                                    Ok(()) => {
+                                        AuditLog::record(entry);
+
+                                        // Everything holding the key picks it up from the start
+                                        esp_idf_svc::hal::reset::restart();
+                                    }
+                                    Err(err) => warn!("Member list key not set: {err:?}"),
+                                }
+                            }
+                            AdminAction::ProvisionCardKey(key) => {
+                                match key_store.set_card_key(key) {
+                                    Ok(()) => {
+                                        AuditLog::record(entry);
+
+                                        esp_idf_svc::hal::reset::restart();
+                                    }
+                                    Err(err) => warn!("Card key not set: {err:?}"),
+                                }
+                            }
+                            AdminAction::ProvisionMacKey(key) => match key_store.set_mac_key(key) {
+                                Ok(()) => {
+                                    AuditLog::record(entry);
+
+                                    esp_idf_svc::hal::reset::restart();
+                                }
+                                Err(err) => warn!("Credential MAC key not set: {err:?}"),
+                            },
+                            AdminAction::ProvisionDesfireKey(key) => {
+                                match key_store.set_desfire_key(key) {
+                                    Ok(()) => {
+                                        AuditLog::record(entry);
+
+                                        esp_idf_svc::hal::reset::restart();
+                                    }
+                                    Err(err) => warn!("DESFire master key not set: {err:?}"),
+                                }
+                            }
+                        }
+                    }
+                    SystemMessage::SetAntennaGain(gain) => {
+                        rfid_commands
+                            .send(RfidCommand::SetAntennaGain(gain))
+                            .await?;
+                    }
+                    SystemMessage::SetRfField(on) => {
+                        rfid_commands.send(RfidCommand::SetRfField(on)).await?;
+                    }
+                    SystemMessage::OnAuth(code, decision) => {
+                        println!(
+                            "==== {:?}: {:?} {:?}, {} (from {})",
+                            code,
+                            decision.outcome,
+                            decision.member,
+                            decision.reason,
+                            decision.source.unwrap_or("controller")
+                        );
+
+                        if decision.is_granted() {
+                            speech_service.speak(format!("Access granted {}.", decision.member));
+
+                            door_unlocks.send(decision.unlock).await?;
                         } else {
-                            speech_service.speak(format!("Access denied {}.", code));
+                            speech_service.speak(format!("Access denied, {}.", decision.reason));
                         }
                     }
+                    SystemMessage::TapsLocked(scope, lockout) => {
+                        let reader = match scope {
+                            LockoutScope::Reader(name) => Some(name),
+                            LockoutScope::Global => None,
+                        };
+
+                        warn!(
+                            "Too many denied taps on {}, locked out for {:?}",
+                            reader.unwrap_or("all readers"),
+                            lockout
+                        );
+
+                        AuditLog::record(AuditEntry::alert(
+                            format!(
+                                "too many denied taps, locked out for {}s",
+                                lockout.as_secs()
+                            ),
+                            reader,
+                        ));
+
+                        speech_service.speak("Too many attempts. Try again later.".to_string());
+                    }
                     SystemMessage::OnOtaBuffer(arc) => {
                         let mut ota = EspOta::new().expect("obtain OTA instance");
 
@@ -157,7 +446,30 @@ async fn async_main() -> Result<(), Box<dyn Error>> {
         }
     };
 
-    tokio::try_join!(wifi_connection.connect(), rfid_service.run(), app_loop(),)?;
+    tokio::try_join!(
+        wifi_connection.connect(),
+        rfid_service.run(),
+        door_service.run(),
+        wiegand_service.run(),
+        member_sync_service.run(),
+        async {
+            match keypad_service.as_mut() {
+                Some(keypad_service) => keypad_service.run().await,
+                None => Ok(()),
+            }
+        },
+        tick(message_bus_tx.clone()),
+        app_loop(),
+    )?;
 
     Ok(())
 }
+
+async fn tick(tx: mpsc::Sender<SystemMessage>) -> anyhow::Result<()> {
+    let mut ticks = tokio::time::interval(common::TICK_INTERVAL);
+
+    loop {
+        ticks.tick().await;
+        tx.send(SystemMessage::Tick).await?;
+    }
+}
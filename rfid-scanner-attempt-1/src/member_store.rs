@@ -0,0 +1,291 @@
+use anyhow::bail;
+use chrono::NaiveDateTime;
+use std::time::SystemTime;
+
+use crate::{
+    card_hash::CardKey,
+    card_uid::CardUid,
+    common,
+    list_key::ListKey,
+    members::{MemberDb, MemberIndex},
+    schedule::AccessRules,
+    spiffs::Spiffs,
+    uses::UseCounts,
+};
+
+const MEMBERS_FILE: &str = "members.json";
+
+// Signature of members.json, needed once there is a list key
+const SIGNATURE_FILE: &str = "members.json.sig";
+
+// Where a downloaded list and its signature wait until they have been checked, see
+// `install_download`
+pub const DOWNLOAD_FILE: &str = "members.json.new";
+pub const DOWNLOAD_SIGNATURE_FILE: &str = "members.json.new.sig";
+
+// The last list whose signature checked out, used while members.json doesn't
+const LAST_GOOD_FILE: &str = "members.good.json";
+const LAST_GOOD_SIGNATURE_FILE: &str = "members.good.json.sig";
+
+// The old list of bare UIDs, renamed once it has been migrated
+const CODES_FILE: &str = "codes.txt";
+const MIGRATED_CODES_FILE: &str = "codes.txt.old";
+
+// Modification time and size of members.json and its signature when they were last read
+type FileStamp = (Option<SystemTime>, u64);
+type ListStamp = (Option<FileStamp>, Option<FileStamp>);
+
+// members.json held in memory as an index, read again only when the file changes.
+//
+// With a list key, members.json is only used when its signature checks out. Otherwise the
+// last list that did stays in use, so uploading an edited file through /write-file gets
+// nobody in.
+pub struct MemberStore {
+    key: Option<ListKey>,
+    card_key: Option<CardKey>,
+    index: MemberIndex,
+    access: AccessRules,
+    stamp: ListStamp,
+    uses: UseCounts,
+}
+
+impl MemberStore {
+    pub fn new(key: Option<ListKey>, card_key: Option<CardKey>) -> MemberStore {
+        MemberStore {
+            key,
+            card_key,
+            index: MemberIndex::default(),
+            access: AccessRules::default(),
+            stamp: (None, None),
+            uses: UseCounts::load(),
+        }
+    }
+
+    pub fn index(&self) -> &MemberIndex {
+        &self.index
+    }
+
+    pub fn access(&self) -> &AccessRules {
+        &self.access
+    }
+
+    // Uses left on the card's guest pass, None when it doesn't run out
+    pub fn remaining_uses(&self, code: &CardUid) -> Option<u32> {
+        let (entry, member) = self.index.find_entry(code)?;
+
+        self.uses.remaining(entry.card, member)
+    }
+
+    // Counts a use of the card's pass if it has a limited number, returning how many are left.
+    pub fn record_use(&mut self, code: &CardUid) -> anyhow::Result<Option<u32>> {
+        match self.index.find_entry(code) {
+            Some((entry, member)) => self.uses.record(entry.card, member),
+            None => Ok(None),
+        }
+    }
+
+    // Drops the use counts of passes that have gone from the list or expired.
+    pub fn prune_uses(&mut self, now: Option<NaiveDateTime>) -> anyhow::Result<()> {
+        self.uses.prune(&self.index, now)
+    }
+
+    fn stamp() -> ListStamp {
+        (Spiffs::stamp(MEMBERS_FILE), Spiffs::stamp(SIGNATURE_FILE))
+    }
+
+    // Reloads members.json if it or its signature has changed since the last read. A file
+    // that doesn't parse leaves the previous index in place.
+    pub fn refresh(&mut self) -> anyhow::Result<()> {
+        let stamp = MemberStore::stamp();
+
+        if stamp.0.is_some() && stamp == self.stamp {
+            return Ok(());
+        }
+
+        let db = self.load();
+
+        // Taken again as loading may have just created the file
+        self.stamp = MemberStore::stamp();
+
+        let mut db = db?;
+
+        self.access = std::mem::take(&mut db.access);
+        self.index = MemberIndex::new(db, self.card_key.clone());
+
+        println!("Loaded {} members", self.index.len());
+
+        Ok(())
+    }
+
+    // Why cards can't be enrolled on the device, when they can't: a signed list can't be changed
+    // here, and a synced one would replace them with the server's next copy.
+    pub fn read_only(&self) -> Option<&'static str> {
+        if self.key.is_some() {
+            return Some("the member list is signed");
+        }
+
+        if common::MEMBER_SYNC_URL.is_some() {
+            return Some("the member list comes from the server");
+        }
+
+        None
+    }
+
+    // Cards enrolled on the device are written straight into members.json.
+    pub fn enroll(&mut self, uid: CardUid, desfire: bool) -> anyhow::Result<Option<String>> {
+        let card_key = self.card_key.clone();
+
+        self.change(|db| db.enroll(uid, desfire, common::ENROLL_ROLE, card_key.as_ref()))
+    }
+
+    pub fn unenroll(&mut self, uid: CardUid) -> anyhow::Result<Option<String>> {
+        let card_key = self.card_key.clone();
+
+        self.change(|db| db.unenroll(uid, card_key.as_ref()))
+    }
+
+    // Edits members.json as it is on flash, and reloads it when something changed.
+    fn change(
+        &mut self,
+        edit: impl FnOnce(&mut MemberDb) -> Option<String>,
+    ) -> anyhow::Result<Option<String>> {
+        if let Some(why) = self.read_only() {
+            bail!("Cards can only be changed on the server, {}", why);
+        }
+
+        let mut db = match Spiffs::read_string(MEMBERS_FILE.to_string()) {
+            Ok(json) => MemberDb::from_json(&json)?,
+            Err(_) => MemberDb::default(),
+        };
+
+        let Some(name) = edit(&mut db) else {
+            return Ok(None);
+        };
+
+        Spiffs::write_string(MEMBERS_FILE.to_string(), db.to_json()?);
+        self.refresh()?;
+
+        Ok(Some(name))
+    }
+
+    // Replaces members.json with the download once it has parsed, and its signature has been
+    // checked when there is a key. SPIFFS can't rename over a file, so members.json is removed
+    // first and `load` finishes the swap if power is lost in between. Returns how many
+    // members the new list has.
+    pub fn install_download(key: Option<&ListKey>) -> anyhow::Result<usize> {
+        let list = Spiffs::read_binary(&DOWNLOAD_FILE.to_string())?;
+
+        if let Some(key) = key {
+            key.verify(
+                &list,
+                &Spiffs::read_string(DOWNLOAD_SIGNATURE_FILE.to_string())?,
+            )?;
+        }
+
+        let db = MemberDb::from_json(std::str::from_utf8(&list)?)?;
+
+        if Spiffs::stamp(MEMBERS_FILE).is_some() {
+            Spiffs::remove(MEMBERS_FILE)?;
+        }
+
+        if Spiffs::stamp(DOWNLOAD_SIGNATURE_FILE).is_some() {
+            MemberStore::replace(DOWNLOAD_SIGNATURE_FILE, SIGNATURE_FILE)?;
+        }
+
+        Spiffs::rename(DOWNLOAD_FILE, MEMBERS_FILE)?;
+
+        Ok(db.members.len())
+    }
+
+    fn replace(from: &str, to: &str) -> anyhow::Result<()> {
+        if Spiffs::stamp(to).is_some() {
+            Spiffs::remove(to)?;
+        }
+
+        Spiffs::rename(from, to)
+    }
+
+    // Reads members.json, or the last good list when it has been tampered with. The first
+    // time, codes.txt is converted into it and set aside.
+    fn load(&self) -> anyhow::Result<MemberDb> {
+        if Spiffs::stamp(MEMBERS_FILE).is_none() {
+            // A swap cut short by a power loss, the download is checked again before it is used
+            if Spiffs::stamp(DOWNLOAD_FILE).is_some()
+                && MemberStore::install_download(self.key.as_ref()).is_ok()
+            {
+                println!("Finished installing the downloaded member list");
+            } else if self.key.is_none() {
+                // codes.txt was never signed, so it only counts on controllers without a key
+                MemberStore::migrate_codes(self.card_key.as_ref())?;
+            }
+        }
+
+        let Some(key) = &self.key else {
+            return match Spiffs::read_string(MEMBERS_FILE.to_string()) {
+                Ok(json) => MemberDb::from_json(&json),
+                Err(_) => Ok(MemberDb::default()),
+            };
+        };
+
+        match MemberStore::read_signed(key, MEMBERS_FILE, SIGNATURE_FILE) {
+            Ok((db, list, signature)) => {
+                MemberStore::keep_good_copy(list, signature);
+
+                Ok(db)
+            }
+            Err(err) => {
+                println!(
+                    "{} rejected, using the last good list: {err:?}",
+                    MEMBERS_FILE
+                );
+
+                MemberStore::read_signed(key, LAST_GOOD_FILE, LAST_GOOD_SIGNATURE_FILE)
+                    .map(|(db, _, _)| db)
+            }
+        }
+    }
+
+    // The list, along with its bytes and signature so they can be kept.
+    fn read_signed(
+        key: &ListKey,
+        path: &str,
+        signature_path: &str,
+    ) -> anyhow::Result<(MemberDb, Vec<u8>, String)> {
+        let list = Spiffs::read_binary(&path.to_string())?;
+        let signature = Spiffs::read_string(signature_path.to_string())?;
+
+        key.verify(&list, &signature)?;
+
+        let db = MemberDb::from_json(std::str::from_utf8(&list)?)?;
+
+        Ok((db, list, signature))
+    }
+
+    // Only written when the list has changed, to spare the flash
+    fn keep_good_copy(list: Vec<u8>, signature: String) {
+        let kept = Spiffs::read_binary(&LAST_GOOD_FILE.to_string()).ok();
+        let kept_signature = Spiffs::read_string(LAST_GOOD_SIGNATURE_FILE.to_string()).ok();
+
+        if kept.as_ref() == Some(&list) && kept_signature.as_ref() == Some(&signature) {
+            return;
+        }
+
+        Spiffs::write_binary(LAST_GOOD_FILE.to_string(), list);
+        Spiffs::write_string(LAST_GOOD_SIGNATURE_FILE.to_string(), signature);
+    }
+
+    fn migrate_codes(card_key: Option<&CardKey>) -> anyhow::Result<()> {
+        let Ok(codes) = Spiffs::read_string(CODES_FILE.to_string()) else {
+            return Ok(());
+        };
+
+        let db = MemberDb::from_codes(&codes, card_key);
+
+        Spiffs::write_string(MEMBERS_FILE.to_string(), db.to_json()?);
+        Spiffs::rename(CODES_FILE, MIGRATED_CODES_FILE)?;
+
+        println!("Migrated {} cards from {}", db.members.len(), CODES_FILE);
+
+        Ok(())
+    }
+}
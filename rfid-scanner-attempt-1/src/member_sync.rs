@@ -0,0 +1,203 @@
+use anyhow::bail;
+use embedded_svc::http::client::Client;
+use esp_idf_hal::io::Read;
+use esp_idf_svc::http::client::{Configuration, EspHttpConnection};
+use serde::{Deserialize, Serialize};
+use std::io::Write as _;
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::sleep,
+};
+
+use crate::{
+    audit::{AuditEntry, AuditLog},
+    common,
+    list_key::ListKey,
+    member_store::{self, MemberStore},
+    spiffs::Spiffs,
+    tz,
+};
+
+const STATE_FILE: &str = "sync.json";
+
+// Stops a runaway response from filling the flash
+const MAX_LIST_LEN: usize = 256 * 1024;
+
+// What the last successful sync got, kept on flash so a reboot doesn't download it again.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct SyncState {
+    // ETag of the list in members.json
+    #[serde(default)]
+    etag: Option<String>,
+    // Unix time the server was last asked, whether or not the list had changed
+    #[serde(default)]
+    synced_at: Option<i64>,
+}
+
+enum Download {
+    Unchanged,
+    // The list is in `DOWNLOAD_FILE`, with the ETag it came with
+    Saved(Option<String>),
+}
+
+enum SyncResult {
+    Unchanged,
+    Installed(usize),
+}
+
+// Pulls the member list from `MEMBER_SYNC_URL` so the door keeps working from a recent copy
+// while the network is down. The ETag of the current list is sent along, and the server answers
+// 304 when nothing has changed.
+//
+// The first sync waits for the network to come up, and each reconnect brings the next one
+// forward. Downloads run on a thread of their own so taps aren't held up behind them.
+pub struct MemberSyncService {
+    state: SyncState,
+    // Lists are signed, the signature comes in an X-Signature header
+    key: Option<ListKey>,
+    connected_tx: Sender<()>,
+    connected_rx: Receiver<()>,
+}
+
+impl MemberSyncService {
+    pub fn new(key: Option<ListKey>) -> MemberSyncService {
+        let state = Spiffs::read_string(STATE_FILE.to_string())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        let (connected_tx, connected_rx) = mpsc::channel(1);
+
+        MemberSyncService {
+            state,
+            key,
+            connected_tx,
+            connected_rx,
+        }
+    }
+
+    // For the main loop to say the network is up
+    pub fn connected(&self) -> Sender<()> {
+        self.connected_tx.clone()
+    }
+
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        let Some(url) = common::MEMBER_SYNC_URL else {
+            return Ok(());
+        };
+
+        self.connected_rx.recv().await;
+
+        loop {
+            match self.sync(url).await {
+                Ok(SyncResult::Unchanged) => println!("Member list unchanged"),
+                Ok(SyncResult::Installed(count)) => {
+                    println!("Member list synced: {} members", count);
+
+                    AuditLog::record(AuditEntry::admin(format!(
+                        "member list synced, {} members",
+                        count
+                    )));
+                }
+                Err(err) => println!("Member sync failed: {err:?}"),
+            }
+
+            tokio::select! {
+                _ = sleep(common::MEMBER_SYNC_INTERVAL) => {}
+                _ = self.connected_rx.recv() => {}
+            }
+        }
+    }
+
+    async fn sync(&mut self, url: &'static str) -> anyhow::Result<SyncResult> {
+        let etag = self.state.etag.clone();
+
+        let download =
+            tokio::task::spawn_blocking(move || MemberSyncService::download(url, etag)).await??;
+
+        let result = match download {
+            Download::Unchanged => SyncResult::Unchanged,
+            Download::Saved(etag) => {
+                let count = MemberStore::install_download(self.key.as_ref())?;
+
+                self.state.etag = etag;
+
+                SyncResult::Installed(count)
+            }
+        };
+
+        self.state.synced_at = tz::utc_now().map(|now| now.timestamp());
+
+        Spiffs::write_string(STATE_FILE.to_string(), serde_json::to_string(&self.state)?);
+
+        Ok(result)
+    }
+
+    // Blocks for as long as the server takes, up to `MEMBER_SYNC_TIMEOUT` at each step. The list
+    // is only installed afterwards, on the runtime, where it is read from.
+    fn download(url: &str, etag: Option<String>) -> anyhow::Result<Download> {
+        let connection = EspHttpConnection::new(&Configuration {
+            timeout: Some(common::MEMBER_SYNC_TIMEOUT),
+            use_global_ca_store: false,
+            crt_bundle_attach: Some(esp_idf_sys::esp_crt_bundle_attach),
+            ..Default::default()
+        })?;
+
+        let mut client = Client::wrap(connection);
+
+        let mut headers = vec![("Accept", "application/json")];
+
+        if let Some(etag) = &etag {
+            headers.push(("If-None-Match", etag.as_str()));
+        }
+
+        println!("-> GET {}", url);
+
+        let mut response = client.get(url, &headers)?.submit()?;
+        let status = response.status();
+
+        println!("<- {}", status);
+
+        match status {
+            304 => Ok(Download::Unchanged),
+            200 => {
+                let etag = response.header("ETag").map(str::to_string);
+
+                match response.header("X-Signature") {
+                    Some(signature) => Spiffs::write_string(
+                        member_store::DOWNLOAD_SIGNATURE_FILE.to_string(),
+                        signature.to_string(),
+                    ),
+                    None if Spiffs::stamp(member_store::DOWNLOAD_SIGNATURE_FILE).is_some() => {
+                        Spiffs::remove(member_store::DOWNLOAD_SIGNATURE_FILE)?
+                    }
+                    None => {}
+                }
+
+                // Written out in pieces, the list can be larger than is comfortable to hold twice
+                let mut file = Spiffs::create(member_store::DOWNLOAD_FILE)?;
+                let mut buf = [0u8; 512];
+                let mut total = 0;
+
+                loop {
+                    let size = response.read(&mut buf)?;
+
+                    if size == 0 {
+                        break;
+                    }
+
+                    total += size;
+
+                    if total > MAX_LIST_LEN {
+                        bail!("Member list longer than {} bytes", MAX_LIST_LEN);
+                    }
+
+                    file.write_all(&buf[..size])?;
+                }
+
+                Ok(Download::Saved(etag))
+            }
+            _ => bail!("Unexpected response code: {}", status),
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use chrono::{NaiveDate, NaiveDateTime, Timelike};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+use crate::{
+    card_hash::{CardId, CardKey},
+    card_uid::CardUid,
+    pin::PinHash,
+    schedule::AccessRules,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Member,
+    Keyholder,
+    Admin,
+}
+
+// Start or end of a membership in local time, "2026-10-18" or "2026-10-18T19:30". A date
+// covers the whole day, so an end date is inclusive and an end time isn't.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DateOrTime {
+    Date(NaiveDate),
+    Time(NaiveDateTime),
+}
+
+impl DateOrTime {
+    fn reached(self, now: NaiveDateTime) -> bool {
+        match self {
+            DateOrTime::Date(date) => now.date() >= date,
+            DateOrTime::Time(time) => now >= time,
+        }
+    }
+
+    fn passed(self, now: NaiveDateTime) -> bool {
+        match self {
+            DateOrTime::Date(date) => now.date() > date,
+            DateOrTime::Time(time) => now >= time,
+        }
+    }
+}
+
+impl From<NaiveDate> for DateOrTime {
+    fn from(date: NaiveDate) -> Self {
+        DateOrTime::Date(date)
+    }
+}
+
+impl From<NaiveDateTime> for DateOrTime {
+    fn from(time: NaiveDateTime) -> Self {
+        DateOrTime::Time(time)
+    }
+}
+
+impl fmt::Display for DateOrTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DateOrTime::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            DateOrTime::Time(time) if time.second() == 0 => {
+                write!(f, "{}", time.format("%Y-%m-%dT%H:%M"))
+            }
+            DateOrTime::Time(time) => write!(f, "{}", time.format("%Y-%m-%dT%H:%M:%S")),
+        }
+    }
+}
+
+impl Serialize for DateOrTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DateOrTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+
+        NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+            .map(DateOrTime::Date)
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M:%S").map(DateOrTime::Time)
+            })
+            .or_else(|_| NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M").map(DateOrTime::Time))
+            .map_err(|_| de::Error::custom(format!("Invalid date or time: {:?}", s)))
+    }
+}
+
+// One of a member's cards: "<card>" on its own, or {"card": "<card>", "desfire": true} for a
+// DESFire card. Those only get in after authenticating, a bare read of their UID is turned
+// away so a copied UID gets nowhere.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "CardEntryJson", into = "CardEntryJson")]
+pub struct CardEntry {
+    pub card: CardId,
+    pub desfire: bool,
+}
+
+impl From<CardId> for CardEntry {
+    fn from(card: CardId) -> Self {
+        CardEntry {
+            card,
+            desfire: false,
+        }
+    }
+}
+
+impl From<CardUid> for CardEntry {
+    fn from(uid: CardUid) -> Self {
+        CardId::Uid(uid).into()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum CardEntryJson {
+    Plain(CardId),
+    Flagged {
+        card: CardId,
+        #[serde(default)]
+        desfire: bool,
+    },
+}
+
+impl From<CardEntryJson> for CardEntry {
+    fn from(json: CardEntryJson) -> Self {
+        match json {
+            CardEntryJson::Plain(card) => card.into(),
+            CardEntryJson::Flagged { card, desfire } => CardEntry { card, desfire },
+        }
+    }
+}
+
+impl From<CardEntry> for CardEntryJson {
+    fn from(entry: CardEntry) -> Self {
+        match entry.desfire {
+            true => CardEntryJson::Flagged {
+                card: entry.card,
+                desfire: true,
+            },
+            false => CardEntryJson::Plain(entry.card),
+        }
+    }
+}
+
+// One person in members.json. Either end of their membership can be left out.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    pub name: String,
+    // UIDs, or their hashes under the card key
+    pub cards: Vec<CardEntry>,
+    pub role: Role,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<DateOrTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<DateOrTime>,
+    // Guest passes that run out after so many uses. The controller counts uses itself, see
+    // `UseCounts`, so this is how many the pass starts with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining_uses: Option<u32>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    // Name of a weekly schedule in `AccessRules`, instead of the one for their role
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    // Asked for after the card on doors that need a PIN
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<PinHash>,
+}
+
+impl Member {
+    // Without the time only members with no dates at all are valid.
+    pub fn is_valid_at(&self, now: Option<NaiveDateTime>) -> bool {
+        match now {
+            Some(now) => {
+                self.valid_from.map_or(true, |from| from.reached(now))
+                    && self.valid_until.map_or(true, |until| !until.passed(now))
+            }
+            None => self.valid_from.is_none() && self.valid_until.is_none(),
+        }
+    }
+
+    pub fn has_expired(&self, now: NaiveDateTime) -> bool {
+        self.valid_until.is_some_and(|until| until.passed(now))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberDb {
+    pub members: Vec<Member>,
+    #[serde(default)]
+    pub access: AccessRules,
+}
+
+impl MemberDb {
+    pub fn from_json(json: &str) -> anyhow::Result<MemberDb> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    // codes.txt is one UID per entry with nothing else, so each becomes a member named after
+    // the UID, which is what the old lookup reported as the name. With a card key the UIDs
+    // are stored hashed, and the members numbered instead.
+    pub fn from_codes(codes: &str, key: Option<&CardKey>) -> MemberDb {
+        let members = codes
+            .split_whitespace()
+            .filter_map(|entry| match entry.parse::<CardUid>() {
+                Ok(uid) => Some(uid),
+                Err(err) => {
+                    println!("Skipping codes.txt entry: {err}");
+                    None
+                }
+            })
+            .enumerate()
+            .map(|(i, uid)| {
+                let name = match key {
+                    Some(_) => format!("Card {}", i + 1),
+                    None => uid.to_string(),
+                };
+
+                Member {
+                    name,
+                    cards: vec![CardId::Uid(uid).resolve(key).into()],
+                    role: Role::Member,
+                    valid_from: None,
+                    valid_until: None,
+                    remaining_uses: None,
+                    notes: "Migrated from codes.txt".to_string(),
+                    schedule: None,
+                    pin: None,
+                }
+            })
+            .collect();
+
+        MemberDb {
+            members,
+            access: AccessRules::default(),
+        }
+    }
+
+    // Adds a member holding just this card, named the way `from_codes` names them. A card that
+    // authenticated as a DESFire card is listed as one. Returns their name, or None when the
+    // card is listed already.
+    pub fn enroll(
+        &mut self,
+        uid: CardUid,
+        desfire: bool,
+        role: Role,
+        key: Option<&CardKey>,
+    ) -> Option<String> {
+        let card = CardId::Uid(uid).resolve(key);
+
+        if self.holder(card, key).is_some() {
+            return None;
+        }
+
+        let name = match key {
+            Some(_) => format!("Card {}", self.members.len() + 1),
+            None => uid.to_string(),
+        };
+
+        self.members.push(Member {
+            name: name.clone(),
+            cards: vec![CardEntry { card, desfire }],
+            role,
+            valid_from: None,
+            valid_until: None,
+            remaining_uses: None,
+            notes: "Enrolled on the device".to_string(),
+            schedule: None,
+            pin: None,
+        });
+
+        Some(name)
+    }
+
+    // Takes the card off whoever holds it, and drops them once they have no cards left.
+    // Returns their name, or None when the card isn't listed.
+    pub fn unenroll(&mut self, uid: CardUid, key: Option<&CardKey>) -> Option<String> {
+        let card = CardId::Uid(uid).resolve(key);
+        let i = self.holder(card, key)?;
+
+        let member = &mut self.members[i];
+        member.cards.retain(|held| held.card.resolve(key) != card);
+
+        let name = member.name.clone();
+
+        if member.cards.is_empty() {
+            self.members.remove(i);
+        }
+
+        Some(name)
+    }
+
+    fn holder(&self, card: CardId, key: Option<&CardKey>) -> Option<usize> {
+        self.members.iter().position(|member| {
+            member
+                .cards
+                .iter()
+                .any(|held| held.card.resolve(key) == card)
+        })
+    }
+}
+
+// Every card with the member holding it, sorted so a lookup is a binary search. With a card
+// key everything is looked up by hash, UIDs listed in the clear included.
+#[derive(Default)]
+pub struct MemberIndex {
+    key: Option<CardKey>,
+    members: Vec<Member>,
+    cards: Vec<(CardEntry, usize)>,
+}
+
+impl MemberIndex {
+    pub fn new(db: MemberDb, key: Option<CardKey>) -> MemberIndex {
+        let mut cards = db
+            .members
+            .iter()
+            .enumerate()
+            .flat_map(|(i, member)| {
+                let key = key.as_ref();
+                member.cards.iter().map(move |entry| {
+                    let entry = CardEntry {
+                        card: entry.card.resolve(key),
+                        ..*entry
+                    };
+
+                    (entry, i)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // The sort is stable, so a card listed twice stays with the first member holding it
+        cards.sort_by_key(|(entry, _)| entry.card);
+        cards.dedup_by_key(|(entry, _)| entry.card);
+
+        MemberIndex {
+            key,
+            members: db.members,
+            cards,
+        }
+    }
+
+    pub fn find(&self, uid: &CardUid) -> Option<&Member> {
+        self.find_entry(uid).map(|(_, member)| member)
+    }
+
+    // The card as the member's entry lists it, with the member.
+    pub fn find_entry(&self, uid: &CardUid) -> Option<(CardEntry, &Member)> {
+        let card = CardId::Uid(*uid).resolve(self.key.as_ref());
+
+        let i = self
+            .cards
+            .binary_search_by_key(&card, |(entry, _)| entry.card)
+            .ok()?;
+
+        let (entry, member) = self.cards[i];
+
+        Some((entry, &self.members[member]))
+    }
+
+    // Every card with its member, as looked up.
+    pub fn entries(&self) -> impl Iterator<Item = (CardEntry, &Member)> {
+        self.cards
+            .iter()
+            .map(|(entry, member)| (*entry, &self.members[*member]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
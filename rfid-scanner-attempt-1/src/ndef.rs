@@ -0,0 +1,251 @@
+use std::fmt;
+
+// NDEF message parsing (NFC Forum NDEF 1.0, RTD Text and URI). No hardware in here, the bytes
+// come from whatever read the tag.
+
+// Our own record type. The payload is "member:<token>" or "action:<name>".
+pub const HACKSPACE_MIME: &str = "application/vnd.leighhack";
+
+// TNF values
+const TNF_WELL_KNOWN: u8 = 0x01;
+const TNF_MIME: u8 = 0x02;
+
+// Header flags
+const MB: u8 = 0x80;
+const ME: u8 = 0x40;
+const CF: u8 = 0x20;
+const SR: u8 = 0x10;
+const IL: u8 = 0x08;
+
+// URI identifier codes 0x00-0x23 from the URI RTD
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum NdefError {
+    Truncated,
+    // Chunked records aren't used by tags and aren't supported
+    Chunked,
+    MissingMessageBegin,
+    Text,
+}
+
+impl fmt::Display for NdefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for NdefError {}
+
+// What a hackspace tag asks for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagPayload {
+    Member(String),
+    Action(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NdefRecord {
+    Text {
+        lang: String,
+        text: String,
+    },
+    Uri(String),
+    Hackspace(TagPayload),
+    Mime {
+        mime_type: String,
+        payload: Vec<u8>,
+    },
+    Other {
+        tnf: u8,
+        record_type: Vec<u8>,
+        payload: Vec<u8>,
+    },
+}
+
+pub fn parse_message(data: &[u8]) -> Result<Vec<NdefRecord>, NdefError> {
+    let mut records = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let header = data[pos];
+
+        if records.is_empty() && header & MB == 0 {
+            return Err(NdefError::MissingMessageBegin);
+        }
+
+        if header & CF != 0 {
+            return Err(NdefError::Chunked);
+        }
+
+        pos += 1;
+
+        let type_len = take(data, &mut pos, 1)?[0] as usize;
+
+        let payload_len = match header & SR {
+            0 => {
+                let len = take(data, &mut pos, 4)?;
+                u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize
+            }
+            _ => take(data, &mut pos, 1)?[0] as usize,
+        };
+
+        let id_len = match header & IL {
+            0 => 0,
+            _ => take(data, &mut pos, 1)?[0] as usize,
+        };
+
+        let record_type = take(data, &mut pos, type_len)?;
+        take(data, &mut pos, id_len)?;
+        let payload = take(data, &mut pos, payload_len)?;
+
+        records.push(parse_record(header & 0x07, record_type, payload)?);
+
+        if header & ME != 0 {
+            break;
+        }
+    }
+
+    Ok(records)
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], NdefError> {
+    // A 32 bit payload length can reach past usize on 32 bit targets
+    let Some(end) = pos.checked_add(len).filter(|end| *end <= data.len()) else {
+        return Err(NdefError::Truncated);
+    };
+
+    let bytes = &data[*pos..end];
+    *pos = end;
+
+    Ok(bytes)
+}
+
+fn parse_record(tnf: u8, record_type: &[u8], payload: &[u8]) -> Result<NdefRecord, NdefError> {
+    match (tnf, record_type) {
+        (TNF_WELL_KNOWN, b"T") => parse_text(payload),
+        (TNF_WELL_KNOWN, b"U") => {
+            let Some((&code, rest)) = payload.split_first() else {
+                return Err(NdefError::Truncated);
+            };
+
+            let prefix = URI_PREFIXES.get(code as usize).copied().unwrap_or("");
+
+            Ok(NdefRecord::Uri(format!(
+                "{}{}",
+                prefix,
+                String::from_utf8_lossy(rest)
+            )))
+        }
+        (TNF_MIME, mime) if mime.eq_ignore_ascii_case(HACKSPACE_MIME.as_bytes()) => {
+            let text = String::from_utf8_lossy(payload);
+
+            match text.split_once(':') {
+                Some(("member", token)) => Ok(NdefRecord::Hackspace(TagPayload::Member(
+                    token.trim().to_string(),
+                ))),
+                Some(("action", name)) => Ok(NdefRecord::Hackspace(TagPayload::Action(
+                    name.trim().to_string(),
+                ))),
+                _ => Ok(NdefRecord::Mime {
+                    mime_type: HACKSPACE_MIME.to_string(),
+                    payload: payload.to_vec(),
+                }),
+            }
+        }
+        (TNF_MIME, mime) => Ok(NdefRecord::Mime {
+            mime_type: String::from_utf8_lossy(mime).to_string(),
+            payload: payload.to_vec(),
+        }),
+        _ => Ok(NdefRecord::Other {
+            tnf,
+            record_type: record_type.to_vec(),
+            payload: payload.to_vec(),
+        }),
+    }
+}
+
+// Status byte: bit 7 set for UTF-16, bits 0-5 language code length.
+fn parse_text(payload: &[u8]) -> Result<NdefRecord, NdefError> {
+    let Some((&status, rest)) = payload.split_first() else {
+        return Err(NdefError::Truncated);
+    };
+
+    let lang_len = (status & 0x3F) as usize;
+
+    if rest.len() < lang_len {
+        return Err(NdefError::Truncated);
+    }
+
+    let (lang, text) = rest.split_at(lang_len);
+
+    let text = match status & 0x80 {
+        0 => String::from_utf8(text.to_vec()).map_err(|_| NdefError::Text)?,
+        _ => utf16(text)?,
+    };
+
+    Ok(NdefRecord::Text {
+        lang: String::from_utf8_lossy(lang).to_string(),
+        text,
+    })
+}
+
+// UTF-16 with an optional byte order mark, big-endian without one
+fn utf16(bytes: &[u8]) -> Result<String, NdefError> {
+    if bytes.len() % 2 != 0 {
+        return Err(NdefError::Text);
+    }
+
+    let (little_endian, bytes) = match bytes {
+        [0xFF, 0xFE, rest @ ..] => (true, rest),
+        [0xFE, 0xFF, rest @ ..] => (false, rest),
+        _ => (false, bytes),
+    };
+
+    let units = bytes
+        .chunks(2)
+        .map(|pair| match little_endian {
+            true => u16::from_le_bytes([pair[0], pair[1]]),
+            false => u16::from_be_bytes([pair[0], pair[1]]),
+        })
+        .collect::<Vec<_>>();
+
+    String::from_utf16(&units).map_err(|_| NdefError::Text)
+}
@@ -0,0 +1,147 @@
+use hmac::{Hmac, Mac};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha256;
+use std::{fmt, str::FromStr};
+
+use crate::hex;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SCHEME: &str = "pbkdf2-sha256";
+const HASH_LEN: usize = 32;
+
+// Keeps a typo in members.json from making every PIN check take minutes
+const MAX_ITERATIONS: u32 = 100_000;
+
+// A member's PIN as PBKDF2-HMAC-SHA256, written in members.json as
+// "pbkdf2-sha256$<iterations>$<salt hex>$<hash hex>". `deno task hash-pin` in api-server makes
+// one. The PIN itself is never stored.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PinHash {
+    iterations: u32,
+    salt: Vec<u8>,
+    hash: [u8; HASH_LEN],
+}
+
+impl PinHash {
+    pub fn verify(&self, pin: &str) -> bool {
+        let hash = pbkdf2(pin.as_bytes(), &self.salt, self.iterations);
+
+        // Compared in full so the time taken doesn't say how much of it matched
+        hash.iter()
+            .zip(self.hash.iter())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+}
+
+// A single block is all a 32 byte hash needs
+fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32) -> [u8; HASH_LEN] {
+    let keyed = HmacSha256::new_from_slice(password).unwrap();
+
+    let mut mac = keyed.clone();
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+
+    let mut block: [u8; HASH_LEN] = mac.finalize().into_bytes().into();
+    let mut hash = block;
+
+    for _ in 1..iterations {
+        let mut mac = keyed.clone();
+        mac.update(&block);
+        block = mac.finalize().into_bytes().into();
+
+        for (h, b) in hash.iter_mut().zip(block.iter()) {
+            *h ^= b;
+        }
+    }
+
+    hash
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PinHashError(pub String);
+
+impl fmt::Display for PinHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid PIN hash: {}", self.0)
+    }
+}
+
+impl std::error::Error for PinHashError {}
+
+impl FromStr for PinHash {
+    type Err = PinHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = |why: &str| PinHashError(why.to_string());
+
+        let mut parts = s.split('$');
+
+        if parts.next() != Some(SCHEME) {
+            return Err(err("expected pbkdf2-sha256"));
+        }
+
+        let iterations = parts
+            .next()
+            .and_then(|n| n.parse::<u32>().ok())
+            .filter(|n| (1..=MAX_ITERATIONS).contains(n))
+            .ok_or_else(|| err("bad iteration count"))?;
+
+        let salt = parts
+            .next()
+            .and_then(hex::decode)
+            .filter(|salt| !salt.is_empty())
+            .ok_or_else(|| err("bad salt"))?;
+
+        let hash = parts
+            .next()
+            .and_then(hex::decode)
+            .and_then(|hash| hash.try_into().ok())
+            .ok_or_else(|| err("bad hash"))?;
+
+        if parts.next().is_some() {
+            return Err(err("too many fields"));
+        }
+
+        Ok(PinHash {
+            iterations,
+            salt,
+            hash,
+        })
+    }
+}
+
+impl fmt::Display for PinHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}${}${}${}",
+            SCHEME,
+            self.iterations,
+            hex::encode(&self.salt),
+            hex::encode(&self.hash)
+        )
+    }
+}
+
+// Debug leaves out the hash, member records get printed
+impl fmt::Debug for PinHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PinHash({} iterations)", self.iterations)
+    }
+}
+
+impl Serialize for PinHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PinHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
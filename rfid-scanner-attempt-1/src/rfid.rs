@@ -1,87 +1,488 @@
-use esp_idf_hal::{
-    gpio::{Gpio44, Gpio7, Gpio8, Gpio9},
-    spi::{self, SpiDriver, SPI3},
+pub mod debounce;
+pub mod desfire;
+pub mod health;
+pub mod iso14443;
+pub mod iso14443_4;
+pub mod mfrc522;
+pub mod mifare;
+pub mod mock;
+pub mod ntag;
+pub mod pn532;
+pub mod presence;
+pub mod reader;
+
+use anyhow::bail;
+use debounce::{Debounce, DebouncePolicy};
+use desfire::{Desfire, MasterKey};
+use health::{Health, HealthEvent};
+use iso14443_4::IsoDep;
+use presence::{Presence, PresenceEvent};
+pub use reader::CardReader;
+use std::time::{Duration, Instant};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::sleep,
 };
-use mfrc522::Mfrc522;
-use std::time::Duration;
-use tokio::{sync::mpsc::Sender, time::sleep};
 
-use crate::common::SystemMessage;
+use crate::{
+    card_uid::CardUid,
+    common::{self, SystemMessage},
+    credential::{MacKey, MemberCredential},
+    ndef,
+};
 
-// #[derive(Copy, Clone)]
-pub struct RfidService {
-    tx: Sender<SystemMessage>,
+// Presence checks a card may miss before it counts as removed, so one bad frame doesn't end a
+// hold or an equipment session.
+const PRESENCE_MISSES: u8 = 3;
+
+// How often the reader is checked, and how many failed checks in a row before it is
+// re-initialised and reported missing.
+const HEALTH_INTERVAL: Duration = Duration::from_secs(10);
+const HEALTH_FAILURES: u8 = 3;
+
+// Which way through the door a reader lets people go.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
 }
 
-impl RfidService {
-    pub fn new(tx: Sender<SystemMessage>) -> RfidService {
-        RfidService { tx }
-    }
+// Identifies the reader a card was read on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReaderInfo {
+    pub name: &'static str,
+    pub direction: Direction,
+}
 
-    pub async fn run(&self) -> anyhow::Result<()> {
-        let sclk = unsafe { Gpio7::new() };
-        let sdo = unsafe { Gpio8::new() }; // MOSI
-        let sdi = unsafe { Gpio9::new() }; // MISO
+// Keys from NVS that cards are checked against. Cards that need a key that isn't set are
+// turned away rather than let in on their UID.
+#[derive(Clone, Debug, Default)]
+pub struct CardSecrets {
+    pub mac_key: Option<MacKey>,
+    pub desfire_key: Option<MasterKey>,
+}
 
-        let driver = SpiDriver::new(
-            unsafe { SPI3::new() },
-            sclk,
-            sdo,
-            Some(sdi),
-            &spi::config::DriverConfig {
-                dma: spi::Dma::Disabled,
-                intr_flags: enumset::EnumSet::new(),
-            },
-        )
-        .map_err(|err| anyhow::Error::msg(format!("SpiDriver Error: {}", err)))?;
+// Requests from the rest of the system that need the next card in the field.
+#[derive(Clone, Debug)]
+pub enum RfidCommand {
+    // Write a member credential onto the next card presented
+    WriteCredential(u32),
+    SetAntennaGain(u8),
+    SetRfField(bool),
+}
 
-        let spi_device_driver = spi::SpiDeviceDriver::new(
-            driver,
-            Some(unsafe { Gpio44::new() }),
-            &esp_idf_hal::spi::config::Config::new(),
-        )
-        .map_err(|err| anyhow::Error::msg(format!("SpiDeviceDriver Error: {}", err)))?;
+pub struct RfidService<R: CardReader> {
+    stations: Vec<Station<R>>,
+    commands_tx: Sender<RfidCommand>,
+    commands_rx: Receiver<RfidCommand>,
+    pending_credential: Option<u32>,
+}
+
+impl<R: CardReader> RfidService<R> {
+    pub fn new(
+        tx: Sender<SystemMessage>,
+        reader: R,
+        info: ReaderInfo,
+        secrets: CardSecrets,
+    ) -> RfidService<R> {
+        let (commands_tx, commands_rx) = mpsc::channel(4);
+
+        RfidService {
+            stations: vec![Station::new(tx, reader, info, secrets)],
+            commands_tx,
+            commands_rx,
+            pending_credential: None,
+        }
+    }
 
-        let itf = mfrc522::comm::blocking::spi::SpiInterface::new(spi_device_driver);
+    // Adds another reader of the same kind, polled in turn with the first.
+    pub fn with_reader(mut self, reader: R, info: ReaderInfo) -> RfidService<R> {
+        let tx = self.stations[0].tx.clone();
+        let secrets = self.stations[0].secrets.clone();
 
-        let mut mfrc522 = Mfrc522::new(itf).init().unwrap();
+        self.stations.push(Station::new(tx, reader, info, secrets));
+        self
+    }
 
-        let vers = mfrc522
-            .version()
-            .map_err(|err| anyhow::Error::msg(format!("mfrc522.version Error: {:?}", err)))?;
+    pub fn commands(&self) -> Sender<RfidCommand> {
+        self.commands_tx.clone()
+    }
 
-        println!("VERSION: 0x{:x}", vers);
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        for station in self.stations.iter_mut() {
+            if let Err(err) = station.reader.self_test() {
+                println!("{} self test failed: {err:?}", station.info.name);
+            }
+        }
 
         loop {
-            if let Ok(atqa) = mfrc522.reqa() {
-                if let Ok(uid) = mfrc522.select(&atqa) {
-                    let bytes = uid.as_bytes();
+            while let Ok(command) = self.commands_rx.try_recv() {
+                self.handle_command(command);
+            }
 
-                    println!("UID: {:?}", uid.as_bytes());
-                    println!("Number: {}", to_u32(bytes).unwrap_or_default());
+            for station in self.stations.iter_mut() {
+                station.poll(&mut self.pending_credential).await?;
+            }
 
-                    let code = to_u32(bytes).unwrap_or_default();
+            // print!(".");
 
-                    self.tx.send(SystemMessage::OnCard(code)).await?;
+            // Presence checks need the field on, an empty field can be left to the reader. Only
+            // a lone reader may wait for a card, otherwise the others would go unpolled.
+            match self.stations.as_mut_slice() {
+                [station] if station.presence.card().is_none() && !station.health.is_missing() => {
+                    if let Err(err) = station.reader.wait_for_card(common::POLL_INTERVAL).await {
+                        println!("Card detection failed: {err:?}");
 
-                    // Don't spam
-                    sleep(Duration::from_secs(3)).await;
+                        sleep(common::POLL_INTERVAL).await;
+                    }
                 }
+                _ => sleep(common::POLL_INTERVAL).await,
             }
+        }
+    }
 
-            // print!(".");
+    // A credential is written to the next card on any reader, settings apply to all of them.
+    fn handle_command(&mut self, command: RfidCommand) {
+        if let RfidCommand::WriteCredential(member_id) = command {
+            self.pending_credential = Some(member_id);
+            return;
+        }
+
+        for station in self.stations.iter_mut() {
+            let result = match command {
+                RfidCommand::SetAntennaGain(gain) => station.reader.set_antenna_gain(gain),
+                RfidCommand::SetRfField(on) => station.reader.set_rf_field(on),
+                RfidCommand::WriteCredential(_) => Ok(()),
+            };
 
-            sleep(Duration::from_millis(100)).await;
+            if let Err(err) = result {
+                println!("{} command failed: {err:?}", station.info.name);
+            }
         }
     }
 }
 
-fn to_u32(bytes: &[u8]) -> Option<u32> {
-    // Ensure the slice has exactly 4 bytes
-    if bytes.len() == 4 {
-        // Convert bytes to u32 assuming little-endian
-        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
-    } else {
-        None
+// One reader with the card on it and its own health and debounce state.
+struct Station<R: CardReader> {
+    tx: Sender<SystemMessage>,
+    info: ReaderInfo,
+    reader: R,
+    secrets: CardSecrets,
+    presence: Presence,
+    debounce: Debounce,
+    health: Health,
+    // Version seen at the first good health check, a different one later means a bad bus
+    version: Option<u8>,
+}
+
+impl<R: CardReader> Station<R> {
+    fn new(
+        tx: Sender<SystemMessage>,
+        reader: R,
+        info: ReaderInfo,
+        secrets: CardSecrets,
+    ) -> Station<R> {
+        Station {
+            tx,
+            info,
+            reader,
+            secrets,
+            presence: Presence::new(PRESENCE_MISSES),
+            debounce: Debounce::new(DebouncePolicy {
+                same_card: common::REPEAT_WINDOW,
+                other_card: common::OTHER_CARD_GAP,
+            }),
+            health: Health::new(HEALTH_INTERVAL, HEALTH_FAILURES, Instant::now()),
+            version: None,
+        }
+    }
+
+    async fn poll(&mut self, pending_credential: &mut Option<u32>) -> anyhow::Result<()> {
+        self.check_health().await?;
+
+        if self.health.is_missing() {
+            return Ok(());
+        }
+
+        // Read errors count as a miss
+        let seen = match self.presence.sense(&mut self.reader) {
+            Ok(seen) => seen,
+            Err(err) => {
+                println!("{} read error: {err:?}", self.info.name);
+
+                self.health.check_soon(Instant::now());
+                None
+            }
+        };
+
+        self.update_presence(seen, pending_credential).await
+    }
+
+    // Reads the version back and compares it with the first one seen. After repeated failures
+    // the chip is re-initialised and self tested before the check is repeated.
+    async fn check_health(&mut self) -> anyhow::Result<()> {
+        let now = Instant::now();
+
+        if !self.health.due(now) {
+            return Ok(());
+        }
+
+        let mut result = self.verify_reader();
+
+        if let Err(err) = &result {
+            println!("{} health check failed: {err:?}", self.info.name);
+
+            if self.health.should_reinit() {
+                result = self
+                    .reader
+                    .reinit()
+                    .and_then(|_| self.reader.self_test())
+                    .and_then(|_| self.verify_reader());
+            }
+        }
+
+        let name = self.info.name;
+
+        match self.health.record(result.is_ok(), now) {
+            Some(HealthEvent::Missing) => {
+                self.presence.clear();
+
+                self.tx.send(SystemMessage::ReaderMissing(name)).await?;
+            }
+            Some(HealthEvent::Restored) => {
+                self.tx.send(SystemMessage::ReaderRestored(name)).await?;
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    fn verify_reader(&mut self) -> anyhow::Result<()> {
+        let version = self.reader.version()?;
+
+        // A floating or shorted bus reads back all zeros or all ones
+        if version == 0x00 || version == 0xFF {
+            bail!("Reader version reads as 0x{:02x}", version);
+        }
+
+        match self.version {
+            Some(expected) if expected != version => {
+                bail!(
+                    "Reader version changed from 0x{:02x} to 0x{:02x}",
+                    expected,
+                    version
+                )
+            }
+            Some(_) => {}
+            None => {
+                println!(
+                    "{} {} VERSION: 0x{:x}",
+                    self.info.name,
+                    self.reader.name(),
+                    version
+                );
+
+                self.version = Some(version);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update_presence(
+        &mut self,
+        seen: Option<CardUid>,
+        pending_credential: &mut Option<u32>,
+    ) -> anyhow::Result<()> {
+        for event in self.presence.update(seen, Instant::now()) {
+            match event {
+                PresenceEvent::Removed(uid, held) => {
+                    println!("Removed: {} after {:?}", uid.to_hex(), held);
+
+                    self.tx
+                        .send(SystemMessage::OnCardRemoved(uid, held))
+                        .await?;
+                }
+                PresenceEvent::Presented(uid) => {
+                    self.on_presented(uid, pending_credential).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn on_presented(
+        &mut self,
+        uid: CardUid,
+        pending_credential: &mut Option<u32>,
+    ) -> anyhow::Result<()> {
+        println!("UID: {} on {}", uid.to_hex(), self.info.name);
+        println!("Number: {}", uid.to_decimal());
+
+        self.tx.send(SystemMessage::OnCardPresented(uid)).await?;
+
+        if let Some(member_id) = pending_credential.take() {
+            self.write_credential(&uid, member_id).await
+        } else if self.debounce.accept(uid, Instant::now()) {
+            self.identify(uid).await
+        } else {
+            println!("Debounced: {}", uid.to_hex());
+
+            Ok(())
+        }
+    }
+
+    // Prefer proof from the card itself: DESFire authentication or a MIFARE credential block.
+    // Anything else is reported as a bare UID, Ultralight/NTAG tags with their NDEF records.
+    // An ISO 14443-4 card that can't authenticate is reported as rejected, not as its UID.
+    async fn identify(&mut self, uid: CardUid) -> anyhow::Result<()> {
+        let info = self.info;
+
+        if self.reader.sak() == Some(iso14443::SAK_ULTRALIGHT) {
+            self.read_tag(uid).await?;
+
+            self.tx.send(SystemMessage::OnCard(uid, info)).await?;
+
+            return Ok(());
+        }
+
+        if self.reader.iso14443_4() {
+            match self.authenticate_desfire(&uid) {
+                Ok(()) => {
+                    self.tx
+                        .send(SystemMessage::OnAuthenticatedCard(uid, info))
+                        .await?;
+                }
+                Err(err) => {
+                    println!("DESFire authentication failed: {err:?}");
+
+                    self.tx
+                        .send(SystemMessage::OnRejectedCard(uid, info))
+                        .await?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        match self.read_credential() {
+            Ok(Some(credential)) => {
+                println!("Credential: member {}", credential.member_id);
+
+                self.tx
+                    .send(SystemMessage::OnCredential(uid, credential, info))
+                    .await?;
+            }
+            Ok(None) => {
+                self.tx.send(SystemMessage::OnCard(uid, info)).await?;
+            }
+            Err(err) => {
+                println!("No credential: {err:?}");
+
+                self.tx.send(SystemMessage::OnCard(uid, info)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn read_tag(&mut self, uid: CardUid) -> anyhow::Result<()> {
+        let message = match ntag::read_ndef(&mut self.reader) {
+            Ok(Some(message)) => message,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                println!("NDEF read failed: {err:?}");
+                return Ok(());
+            }
+        };
+
+        match ndef::parse_message(&message) {
+            Ok(records) => {
+                println!("NDEF: {:?}", records);
+
+                self.tx.send(SystemMessage::OnNdef(uid, records)).await?;
+            }
+            Err(err) => println!("NDEF parse failed: {err}"),
+        }
+
+        Ok(())
+    }
+
+    fn authenticate_desfire(&mut self, uid: &CardUid) -> anyhow::Result<()> {
+        let Some(master) = &self.secrets.desfire_key else {
+            bail!("No DESFire master key set");
+        };
+
+        let key = desfire::diversify_key(
+            master.as_bytes(),
+            uid.as_bytes(),
+            common::DESFIRE_AID,
+            common::DESFIRE_SYSTEM_ID,
+        );
+
+        // Hardware RNG, random as long as the radio is on
+        let mut rnd_a = [0u8; 16];
+        unsafe { esp_idf_sys::esp_fill_random(rnd_a.as_mut_ptr() as *mut _, rnd_a.len()) };
+
+        let mut card = Desfire::new(IsoDep::activate(&mut self.reader)?);
+
+        card.select_application(common::DESFIRE_AID)?;
+        card.authenticate_ev2_first(common::DESFIRE_KEY_NO, &key, rnd_a)?;
+
+        card.into_inner().deselect()
+    }
+
+    fn credential_block(&self) -> u8 {
+        mifare::first_block(common::MIFARE_SECTOR)
+    }
+
+    fn read_credential(&mut self) -> anyhow::Result<Option<MemberCredential>> {
+        let block = self.credential_block();
+
+        self.reader
+            .authenticate(block, common::MIFARE_KEY_TYPE, &common::MIFARE_KEY)?;
+
+        let data = self.reader.read_block(block);
+
+        self.reader.stop_crypto()?;
+
+        Ok(MemberCredential::from_block(&data?))
+    }
+
+    async fn write_credential(&mut self, uid: &CardUid, member_id: u32) -> anyhow::Result<()> {
+        let Some(key) = &self.secrets.mac_key else {
+            println!("No credential MAC key set, card not written");
+
+            self.tx
+                .send(SystemMessage::Speak("No credential key set.".to_string()))
+                .await?;
+
+            return Ok(());
+        };
+
+        let block = self.credential_block();
+        let credential = MemberCredential::issue(key, uid, member_id);
+
+        let result = self
+            .reader
+            .authenticate(block, common::MIFARE_KEY_TYPE, &common::MIFARE_KEY)
+            .and_then(|_| self.reader.write_block(block, &credential.to_block()));
+
+        self.reader.stop_crypto()?;
+
+        let msg = match result {
+            Ok(()) => format!("Card written for member {}.", member_id),
+            Err(err) => {
+                println!("Credential write failed: {err:?}");
+
+                "Card write failed.".to_string()
+            }
+        };
+
+        self.tx.send(SystemMessage::Speak(msg)).await?;
+
+        Ok(())
     }
 }
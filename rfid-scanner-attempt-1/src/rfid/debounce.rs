@@ -0,0 +1,56 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::card_uid::CardUid;
+
+// How soon cards may be identified again after being accepted.
+#[derive(Copy, Clone, Debug)]
+pub struct DebouncePolicy {
+    // The same card is ignored for this long after it was accepted
+    pub same_card: Duration,
+    // Any other card is ignored for this long after the last accepted one
+    pub other_card: Duration,
+}
+
+// Per-UID table of when each card was last accepted. Entries expire with the repeat window so
+// the table only ever holds the last few cards.
+pub struct Debounce {
+    policy: DebouncePolicy,
+    seen: HashMap<CardUid, Instant>,
+    last: Option<(CardUid, Instant)>,
+}
+
+impl Debounce {
+    pub fn new(policy: DebouncePolicy) -> Debounce {
+        Debounce {
+            policy,
+            seen: HashMap::new(),
+            last: None,
+        }
+    }
+
+    // Whether `uid` should be identified now. Accepting it starts its repeat window.
+    pub fn accept(&mut self, uid: CardUid, now: Instant) -> bool {
+        let window = self.policy.same_card;
+
+        self.seen
+            .retain(|_, accepted| now.saturating_duration_since(*accepted) < window);
+
+        if self.seen.contains_key(&uid) {
+            return false;
+        }
+
+        if let Some((last_uid, accepted)) = self.last {
+            if last_uid != uid && now.saturating_duration_since(accepted) < self.policy.other_card {
+                return false;
+            }
+        }
+
+        self.seen.insert(uid, now);
+        self.last = Some((uid, now));
+
+        true
+    }
+}
@@ -0,0 +1,325 @@
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use anyhow::bail;
+use std::fmt;
+
+use super::iso14443_4::{IsoDep, Transceive};
+
+// MIFARE DESFire EV2/EV3 native commands wrapped in ISO 7816-4 APDUs, and the AES-128 crypto
+// needed for EV2 first authentication. Takes the random challenge as a parameter so a
+// recorded session can be replayed.
+
+pub type AesKey = [u8; 16];
+
+const CLA_NATIVE: u8 = 0x90;
+
+const CMD_SELECT_APPLICATION: u8 = 0x5A;
+const CMD_AUTHENTICATE_EV2_FIRST: u8 = 0x71;
+const CMD_ADDITIONAL_FRAME: u8 = 0xAF;
+
+const SW1_DESFIRE: u8 = 0x91;
+const STATUS_OK: u8 = 0x00;
+const STATUS_ADDITIONAL_FRAME: u8 = 0xAF;
+
+// Session vector labels from the EV2 key derivation
+const SV1_LABEL: [u8; 2] = [0xA5, 0x5A];
+const SV2_LABEL: [u8; 2] = [0x5A, 0xA5];
+
+// AN10922 diversification constant for AES-128 keys
+const DIV_CONSTANT_AES128: u8 = 0x01;
+
+// Master key the card keys are diversified from, kept in NVS and set through /desfire-key.
+#[derive(Clone)]
+pub struct MasterKey(AesKey);
+
+impl MasterKey {
+    pub fn from_bytes(bytes: &[u8]) -> Option<MasterKey> {
+        Some(MasterKey(bytes.try_into().ok()?))
+    }
+
+    pub fn as_bytes(&self) -> &AesKey {
+        &self.0
+    }
+}
+
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MasterKey(..)")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DesfireError(pub u8);
+
+impl fmt::Display for DesfireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.0 {
+            0x0C => "no changes",
+            0x1C => "illegal command",
+            0x1E => "integrity error",
+            0x40 => "no such key",
+            0x7E => "length error",
+            0x9D => "permission denied",
+            0x9E => "parameter error",
+            0xA0 => "application not found",
+            0xAE => "authentication error",
+            0xBE => "boundary error",
+            0xCA => "command aborted",
+            _ => "unknown status",
+        };
+
+        write!(f, "DESFire {} ({:02X})", name, self.0)
+    }
+}
+
+impl std::error::Error for DesfireError {}
+
+// Keys and transaction identifier agreed during authentication.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub ti: [u8; 4],
+    pub enc_key: AesKey,
+    pub mac_key: AesKey,
+}
+
+pub fn wrap_native(cmd: u8, data: &[u8]) -> Vec<u8> {
+    let mut apdu = vec![CLA_NATIVE, cmd, 0x00, 0x00];
+
+    if !data.is_empty() {
+        apdu.push(data.len() as u8);
+        apdu.extend_from_slice(data);
+    }
+
+    apdu.push(0x00);
+    apdu
+}
+
+// Splits an APDU response into the DESFire status and the data before it.
+pub fn unwrap_response(response: &[u8]) -> anyhow::Result<(u8, &[u8])> {
+    match response {
+        [data @ .., SW1_DESFIRE, status] => Ok((*status, data)),
+        [.., sw1, sw2] => bail!("Unexpected status word {:02X}{:02X}", sw1, sw2),
+        _ => bail!("Response too short for a status word"),
+    }
+}
+
+pub struct Desfire<'a, T: Transceive + ?Sized> {
+    iso: IsoDep<'a, T>,
+}
+
+impl<'a, T: Transceive + ?Sized> Desfire<'a, T> {
+    pub fn new(iso: IsoDep<'a, T>) -> Desfire<'a, T> {
+        Desfire { iso }
+    }
+
+    pub fn into_inner(self) -> IsoDep<'a, T> {
+        self.iso
+    }
+
+    // Sends a native command and returns the status with any data.
+    fn command(&mut self, cmd: u8, data: &[u8]) -> anyhow::Result<(u8, Vec<u8>)> {
+        let response = self.iso.exchange(&wrap_native(cmd, data))?;
+        let (status, data) = unwrap_response(&response)?;
+
+        match status {
+            STATUS_OK | STATUS_ADDITIONAL_FRAME => Ok((status, data.to_vec())),
+            status => Err(DesfireError(status).into()),
+        }
+    }
+
+    // AIDs go over the air least significant byte first.
+    pub fn select_application(&mut self, aid: u32) -> anyhow::Result<()> {
+        self.command(CMD_SELECT_APPLICATION, &aid_bytes(aid))?;
+
+        Ok(())
+    }
+
+    // AuthenticateEV2First with an AES-128 key. `rnd_a` must be fresh random bytes.
+    pub fn authenticate_ev2_first(
+        &mut self,
+        key_no: u8,
+        key: &AesKey,
+        rnd_a: [u8; 16],
+    ) -> anyhow::Result<Session> {
+        // No PCD capabilities
+        let (status, enc_rnd_b) = self.command(CMD_AUTHENTICATE_EV2_FIRST, &[key_no, 0x00])?;
+
+        if status != STATUS_ADDITIONAL_FRAME || enc_rnd_b.len() != 16 {
+            bail!("Unexpected first authentication response");
+        }
+
+        let rnd_b: [u8; 16] = aes_cbc_decrypt(key, &enc_rnd_b).try_into().unwrap();
+
+        let mut token = rnd_a.to_vec();
+        token.extend_from_slice(&rotate_left(&rnd_b));
+
+        let (status, enc_answer) =
+            self.command(CMD_ADDITIONAL_FRAME, &aes_cbc_encrypt(key, &token))?;
+
+        if status != STATUS_OK || enc_answer.len() != 32 {
+            bail!("Unexpected second authentication response");
+        }
+
+        // TI (4) | RndA' (16) | PDcap2 (6) | PCDcap2 (6)
+        let answer = aes_cbc_decrypt(key, &enc_answer);
+
+        if answer[4..20] != rotate_left(&rnd_a) {
+            return Err(DesfireError(0xAE).into());
+        }
+
+        Ok(Session {
+            ti: answer[0..4].try_into().unwrap(),
+            enc_key: session_key(key, SV1_LABEL, &rnd_a, &rnd_b),
+            mac_key: session_key(key, SV2_LABEL, &rnd_a, &rnd_b),
+        })
+    }
+}
+
+pub fn aid_bytes(aid: u32) -> [u8; 3] {
+    let bytes = aid.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
+pub fn rotate_left(block: &[u8; 16]) -> [u8; 16] {
+    let mut rotated = *block;
+    rotated.rotate_left(1);
+    rotated
+}
+
+// SV = label || 00 01 00 80 || RndA[15..14] || (RndA[13..8] ^ RndB[15..10]) || RndB[9..0] || RndA[7..0]
+// with byte 15 being the first one sent.
+fn session_key(key: &AesKey, label: [u8; 2], rnd_a: &[u8; 16], rnd_b: &[u8; 16]) -> AesKey {
+    let mut sv = label.to_vec();
+    sv.extend_from_slice(&[0x00, 0x01, 0x00, 0x80]);
+    sv.extend_from_slice(&rnd_a[0..2]);
+    sv.extend(rnd_a[2..8].iter().zip(&rnd_b[0..6]).map(|(a, b)| a ^ b));
+    sv.extend_from_slice(&rnd_b[6..16]);
+    sv.extend_from_slice(&rnd_a[8..16]);
+
+    cmac(key, &sv)
+}
+
+// Per-card key from a master key as described in NXP AN10922:
+// CMAC(master, 01 || UID || AID || system identifier), always padded to two blocks.
+pub fn diversify_key(master: &AesKey, uid: &[u8], aid: u32, system_id: &[u8]) -> AesKey {
+    let mut input = vec![DIV_CONSTANT_AES128];
+    input.extend_from_slice(uid);
+    input.extend_from_slice(&aid_bytes(aid));
+    input.extend_from_slice(system_id);
+    input.truncate(32);
+
+    cmac_padded(master, &input, 32)
+}
+
+// Plain NIST SP 800-38B CMAC.
+pub fn cmac(key: &AesKey, data: &[u8]) -> AesKey {
+    let padded_len = data.len().div_ceil(16).max(1) * 16;
+
+    cmac_padded(key, data, padded_len)
+}
+
+// CMAC where the message is padded to `padded_len` bytes. K1 is used when no padding was
+// needed, K2 otherwise.
+fn cmac_padded(key: &AesKey, data: &[u8], padded_len: usize) -> AesKey {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+
+    let l = encrypt_block(&cipher, [0u8; 16]);
+    let k1 = double(&l);
+    let k2 = double(&k1);
+
+    let mut message = data.to_vec();
+
+    let subkey = if !data.is_empty() && data.len() == padded_len {
+        k1
+    } else {
+        message.push(0x80);
+        message.resize(padded_len, 0x00);
+        k2
+    };
+
+    let last = message.len() - 16;
+
+    for (b, k) in message[last..].iter_mut().zip(subkey) {
+        *b ^= k;
+    }
+
+    let mut state = [0u8; 16];
+
+    for block in message.chunks(16) {
+        for (s, b) in state.iter_mut().zip(block) {
+            *s ^= b;
+        }
+
+        state = encrypt_block(&cipher, state);
+    }
+
+    state
+}
+
+// Left shift by one bit in GF(2^128), as used for the CMAC subkeys.
+fn double(block: &[u8; 16]) -> [u8; 16] {
+    let mut out = [0u8; 16];
+
+    for i in 0..16 {
+        out[i] = block[i] << 1;
+
+        if i + 1 < 16 {
+            out[i] |= block[i + 1] >> 7;
+        }
+    }
+
+    if block[0] & 0x80 != 0 {
+        out[15] ^= 0x87;
+    }
+
+    out
+}
+
+fn encrypt_block(cipher: &Aes128, block: [u8; 16]) -> [u8; 16] {
+    let mut block = GenericArray::from(block);
+    cipher.encrypt_block(&mut block);
+    block.into()
+}
+
+// AES-128 CBC with a zero IV, as used during authentication. `data` is a multiple of 16 bytes.
+pub fn aes_cbc_encrypt(key: &AesKey, data: &[u8]) -> Vec<u8> {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+
+    let mut iv = [0u8; 16];
+    let mut out = Vec::with_capacity(data.len());
+
+    for chunk in data.chunks(16) {
+        let mut block = [0u8; 16];
+
+        for i in 0..16 {
+            block[i] = chunk[i] ^ iv[i];
+        }
+
+        iv = encrypt_block(&cipher, block);
+        out.extend_from_slice(&iv);
+    }
+
+    out
+}
+
+pub fn aes_cbc_decrypt(key: &AesKey, data: &[u8]) -> Vec<u8> {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+
+    let mut iv = [0u8; 16];
+    let mut out = Vec::with_capacity(data.len());
+
+    for chunk in data.chunks(16) {
+        let mut block = GenericArray::clone_from_slice(chunk);
+        cipher.decrypt_block(&mut block);
+
+        for i in 0..16 {
+            out.push(block[i] ^ iv[i]);
+        }
+
+        iv.copy_from_slice(chunk);
+    }
+
+    out
+}
@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+
+// Reader supervision state: when the next check is due, how many checks in a row have failed
+// and whether the reader has been reported missing. No hardware in here.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HealthEvent {
+    Missing,
+    Restored,
+}
+
+pub struct Health {
+    interval: Duration,
+    // Consecutive failures before the reader is re-initialised and reported missing
+    threshold: u8,
+    failures: u8,
+    missing: bool,
+    next_check: Instant,
+}
+
+impl Health {
+    pub fn new(interval: Duration, threshold: u8, now: Instant) -> Health {
+        Health {
+            interval,
+            threshold,
+            failures: 0,
+            missing: false,
+            next_check: now,
+        }
+    }
+
+    pub fn due(&self, now: Instant) -> bool {
+        now >= self.next_check
+    }
+
+    // An error outside the health check brings the next check forward.
+    pub fn check_soon(&mut self, now: Instant) {
+        self.next_check = self.next_check.min(now);
+    }
+
+    pub fn is_missing(&self) -> bool {
+        self.missing
+    }
+
+    // Whether a failure now would reach the threshold, so it's worth trying a re-init first.
+    pub fn should_reinit(&self) -> bool {
+        self.failures.saturating_add(1) >= self.threshold
+    }
+
+    // Records a check result and returns an event when the reader goes missing or comes back.
+    pub fn record(&mut self, ok: bool, now: Instant) -> Option<HealthEvent> {
+        self.next_check = now + self.interval;
+
+        if ok {
+            self.failures = 0;
+
+            return match std::mem::replace(&mut self.missing, false) {
+                true => Some(HealthEvent::Restored),
+                false => None,
+            };
+        }
+
+        self.failures = self.failures.saturating_add(1);
+
+        if self.failures >= self.threshold && !self.missing {
+            self.missing = true;
+            return Some(HealthEvent::Missing);
+        }
+
+        None
+    }
+}
@@ -0,0 +1,75 @@
+// ISO 14443-3 type A commands and helpers shared by the reader backends.
+
+pub const REQA: u8 = 0x26;
+pub const WUPA: u8 = 0x52;
+pub const HLTA: u8 = 0x50;
+pub const SEL_CL1: u8 = 0x93;
+pub const SEL_CL2: u8 = 0x95;
+pub const SEL_CL3: u8 = 0x97;
+
+// First byte of a cascade level that is followed by another one
+pub const CASCADE_TAG: u8 = 0x88;
+
+// SAK bit 3: UID not complete, another cascade level follows
+pub const SAK_CASCADE: u8 = 0x04;
+
+// SAK bit 6: card speaks ISO 14443-4 (DESFire, smart cards)
+pub const SAK_ISO14443_4: u8 = 0x20;
+
+// SAK of a MIFARE Classic 1K
+pub const SAK_MIFARE_1K: u8 = 0x08;
+
+// SAK of MIFARE Ultralight and NTAG21x: no Crypto1, no ISO 14443-4
+pub const SAK_ULTRALIGHT: u8 = 0x00;
+
+// Ultralight/NTAG read returns 4 pages of 4 bytes, same command as a MIFARE Classic block read
+pub const UL_PAGE_SIZE: usize = 4;
+
+// MIFARE Classic commands
+pub const MF_AUTH_KEY_A: u8 = 0x60;
+pub const MF_AUTH_KEY_B: u8 = 0x61;
+pub const MF_READ: u8 = 0x30;
+pub const MF_WRITE: u8 = 0xA0;
+
+// 4-bit ACK returned by MIFARE cards
+pub const MF_ACK: u8 = 0x0A;
+
+// CRC_A as defined in ISO 14443-3 annex B, least significant byte first.
+pub fn crc_a(data: &[u8]) -> [u8; 2] {
+    let mut crc: u16 = 0x6363;
+
+    for byte in data {
+        let mut ch = byte ^ (crc & 0xFF) as u8;
+        ch ^= ch << 4;
+
+        let ch = ch as u16;
+        crc = (crc >> 8) ^ (ch << 8) ^ (ch << 3) ^ (ch >> 4);
+    }
+
+    crc.to_le_bytes()
+}
+
+pub fn append_crc_a(data: &[u8]) -> Vec<u8> {
+    let mut frame = data.to_vec();
+    frame.extend_from_slice(&crc_a(data));
+    frame
+}
+
+// Checks and strips the trailing CRC_A of a received frame.
+pub fn strip_crc_a(frame: &[u8]) -> Option<&[u8]> {
+    if frame.len() < 2 {
+        return None;
+    }
+
+    let (data, crc) = frame.split_at(frame.len() - 2);
+
+    match crc_a(data) == crc {
+        true => Some(data),
+        false => None,
+    }
+}
+
+// Block check character sent after the 4 UID bytes of each cascade level.
+pub fn bcc(uid_part: &[u8]) -> u8 {
+    uid_part.iter().fold(0, |acc, b| acc ^ b)
+}
@@ -0,0 +1,211 @@
+use anyhow::bail;
+
+// ISO 14443-4 (T=CL) block transport. Only needs something that can swap frames with the
+// selected card, so it runs the same over any reader and against recorded transcripts.
+
+// Frame exchange with the selected card. CRC_A is added and checked by the implementation.
+pub trait Transceive {
+    fn transceive(&mut self, frame: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+    // Frame waiting time the card asked for in its ATS.
+    fn set_timeout_ms(&mut self, _ms: u32) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+const RATS: u8 = 0xE0;
+
+// Reader frame size: the MFRC522 FIFO is 64 bytes, so ask for FSDI 5 (64 byte frames)
+const FSDI: u8 = 5;
+
+// FSCI/FSDI to frame size in bytes
+const FRAME_SIZES: [usize; 9] = [16, 24, 32, 40, 48, 64, 96, 128, 256];
+
+// PCB values
+const I_BLOCK: u8 = 0x02;
+const R_ACK: u8 = 0xA2;
+const S_DESELECT: u8 = 0xC2;
+const S_WTX: u8 = 0xF2;
+const CHAINING: u8 = 0x10;
+const BLOCK_NUMBER: u8 = 0x01;
+
+// PCB + CRC_A around the information field
+const FRAME_OVERHEAD: usize = 3;
+
+// Don't let a card keep extending the waiting time forever
+const MAX_WTX: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ats {
+    // Largest frame the card accepts
+    pub fsc: usize,
+    // Frame waiting time integer, FWT = 302 us * 2^FWI
+    pub fwi: u8,
+    pub historical: Vec<u8>,
+}
+
+impl Ats {
+    pub fn parse(ats: &[u8]) -> anyhow::Result<Ats> {
+        let Some(&tl) = ats.first() else {
+            bail!("Empty ATS");
+        };
+
+        if tl as usize != ats.len() {
+            bail!("ATS length byte {} does not match {} bytes", tl, ats.len());
+        }
+
+        // Defaults when T0 is absent: FSCI 2, FWI 4
+        let mut fsci = 2;
+        let mut fwi = 4;
+        let mut pos = 1;
+
+        if let Some(&t0) = ats.get(1) {
+            fsci = (t0 & 0x0F) as usize;
+            pos = 2;
+
+            // TA, TB and TC follow T0 in that order when present
+            for (bit, interface_byte) in [(0x10, 'A'), (0x20, 'B'), (0x40, 'C')] {
+                if t0 & bit == 0 {
+                    continue;
+                }
+
+                let Some(&value) = ats.get(pos) else {
+                    bail!("ATS truncated");
+                };
+
+                if interface_byte == 'B' {
+                    fwi = value >> 4;
+                }
+
+                pos += 1;
+            }
+        }
+
+        Ok(Ats {
+            fsc: FRAME_SIZES[fsci.min(FRAME_SIZES.len() - 1)],
+            fwi: fwi.min(14),
+            historical: ats[pos.min(ats.len())..].to_vec(),
+        })
+    }
+
+    pub fn fwt_ms(&self) -> u32 {
+        // 302 us * 2^FWI, rounded up with a little slack for the reader
+        (302u32 << self.fwi) / 1000 + 5
+    }
+}
+
+pub struct IsoDep<'a, T: Transceive + ?Sized> {
+    link: &'a mut T,
+    ats: Ats,
+    block_number: u8,
+}
+
+impl<'a, T: Transceive + ?Sized> IsoDep<'a, T> {
+    // Sends RATS to the selected card and sets up the link from its ATS.
+    pub fn activate(link: &'a mut T) -> anyhow::Result<IsoDep<'a, T>> {
+        // CID 0, we only ever talk to one card
+        let ats = Ats::parse(&link.transceive(&[RATS, FSDI << 4])?)?;
+
+        link.set_timeout_ms(ats.fwt_ms())?;
+
+        Ok(IsoDep {
+            link,
+            ats,
+            block_number: 0,
+        })
+    }
+
+    pub fn ats(&self) -> &Ats {
+        &self.ats
+    }
+
+    // Sends one APDU and returns the complete response, chaining in both directions as needed.
+    pub fn exchange(&mut self, apdu: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let max_inf = self.ats.fsc.min(FRAME_SIZES[FSDI as usize]) - FRAME_OVERHEAD;
+        let chunks = apdu.chunks(max_inf).collect::<Vec<_>>();
+
+        let mut response = Vec::new();
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let more = i + 1 < chunks.len();
+
+            let mut frame = vec![I_BLOCK | self.block_number | if more { CHAINING } else { 0 }];
+            frame.extend_from_slice(chunk);
+
+            let block = self.send_block(&frame)?;
+            let pcb = block[0];
+
+            if more {
+                // Card acknowledges each chained block before taking the next
+                if pcb & 0xF6 != R_ACK & 0xF6 || pcb & BLOCK_NUMBER != self.block_number {
+                    bail!("Expected R(ACK), got PCB {:02X}", pcb);
+                }
+
+                self.block_number ^= 1;
+                continue;
+            }
+
+            response = self.receive_chain(block)?;
+        }
+
+        Ok(response)
+    }
+
+    // Collects the card's answer, acknowledging chained I-blocks until the last one.
+    fn receive_chain(&mut self, mut block: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let mut response = Vec::new();
+
+        loop {
+            let pcb = block[0];
+
+            if pcb & 0xE2 != I_BLOCK {
+                bail!("Expected I-block, got PCB {:02X}", pcb);
+            }
+
+            if pcb & BLOCK_NUMBER != self.block_number {
+                bail!("Unexpected block number in PCB {:02X}", pcb);
+            }
+
+            self.block_number ^= 1;
+            response.extend_from_slice(&block[1..]);
+
+            if pcb & CHAINING == 0 {
+                return Ok(response);
+            }
+
+            block = self.send_block(&[R_ACK | self.block_number])?;
+        }
+    }
+
+    // Sends a block and answers any waiting time extension requests before returning the reply.
+    fn send_block(&mut self, frame: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut reply = self.link.transceive(frame)?;
+
+        for _ in 0..MAX_WTX {
+            match reply.first() {
+                None => bail!("Empty block from card"),
+                Some(&pcb) if pcb & 0xF7 == S_WTX & 0xF7 => {
+                    let Some(&wtxm) = reply.get(1) else {
+                        bail!("S(WTX) without WTXM");
+                    };
+
+                    let wtxm = wtxm & 0x3F;
+
+                    self.link
+                        .set_timeout_ms(self.ats.fwt_ms() * wtxm.max(1) as u32)?;
+                    let result = self.link.transceive(&[S_WTX, wtxm]);
+                    self.link.set_timeout_ms(self.ats.fwt_ms())?;
+
+                    reply = result?;
+                }
+                Some(_) => return Ok(reply),
+            }
+        }
+
+        bail!("Too many S(WTX) requests");
+    }
+
+    pub fn deselect(self) -> anyhow::Result<()> {
+        self.link.transceive(&[S_DESELECT]).map(|_| ())
+    }
+}
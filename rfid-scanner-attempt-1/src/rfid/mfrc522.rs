@@ -0,0 +1,792 @@
+use anyhow::bail;
+use esp_idf_hal::{
+    delay::BLOCK,
+    gpio::{
+        Gpio14, Gpio4, Gpio44, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Input, InterruptType, OutputPin,
+        PinDriver, Pull,
+    },
+    i2c::{I2cConfig, I2cDriver, I2C0},
+    peripheral::Peripheral,
+    prelude::*,
+    spi::{self, SpiDeviceDriver, SpiDriver, SPI3},
+};
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::time::{sleep, timeout};
+
+use super::{
+    iso14443::{self, append_crc_a, strip_crc_a},
+    iso14443_4::Transceive,
+    mifare::{KeyType, MifareKey},
+    CardReader,
+};
+use crate::card_uid::CardUid;
+
+// Default address with both I2C address pins tied low
+const MFRC522_I2C_ADDRESS: u8 = 0x28;
+
+// Registers
+const COMMAND_REG: u8 = 0x01;
+const COM_I_EN_REG: u8 = 0x02;
+const DIV_I_EN_REG: u8 = 0x03;
+const COM_IRQ_REG: u8 = 0x04;
+const DIV_IRQ_REG: u8 = 0x05;
+const ERROR_REG: u8 = 0x06;
+const STATUS2_REG: u8 = 0x08;
+const FIFO_DATA_REG: u8 = 0x09;
+const FIFO_LEVEL_REG: u8 = 0x0A;
+const CONTROL_REG: u8 = 0x0C;
+const BIT_FRAMING_REG: u8 = 0x0D;
+const COLL_REG: u8 = 0x0E;
+const MODE_REG: u8 = 0x11;
+const TX_MODE_REG: u8 = 0x12;
+const RX_MODE_REG: u8 = 0x13;
+const TX_CONTROL_REG: u8 = 0x14;
+const TX_ASK_REG: u8 = 0x15;
+const RX_THRESHOLD_REG: u8 = 0x18;
+const MOD_WIDTH_REG: u8 = 0x24;
+const RF_CFG_REG: u8 = 0x26;
+const AUTO_TEST_REG: u8 = 0x36;
+const T_MODE_REG: u8 = 0x2A;
+const T_PRESCALER_REG: u8 = 0x2B;
+const T_RELOAD_REG_H: u8 = 0x2C;
+const T_RELOAD_REG_L: u8 = 0x2D;
+const VERSION_REG: u8 = 0x37;
+
+// Commands
+const CMD_IDLE: u8 = 0x00;
+const CMD_MEM: u8 = 0x01;
+const CMD_CALC_CRC: u8 = 0x03;
+const CMD_TRANSCEIVE: u8 = 0x0C;
+const CMD_MF_AUTHENT: u8 = 0x0E;
+const CMD_SOFT_RESET: u8 = 0x0F;
+
+// CommandReg: analog part and oscillator off
+const POWER_DOWN: u8 = 0x10;
+
+// ComIEnReg: IRQ pin active low. DivIEnReg: IRQ pin push-pull.
+const IRQ_INV: u8 = 0x80;
+const IRQ_PUSH_PULL: u8 = 0x80;
+
+// Reset value of RxThresholdReg, used for everything but card detection
+const RX_THRESHOLD_DEFAULT: u8 = 0x84;
+
+// RFCfgReg RxGain reset value, 33 dB
+const DEFAULT_GAIN: u8 = 4;
+
+// AutoTestReg value that runs the digital self test
+const SELF_TEST_ENABLE: u8 = 0x09;
+
+// Expected FIFO contents after the self test, from the datasheet. Clones report other versions
+// and have no published reference, so they only get the version check.
+const SELF_TEST_V1: [u8; 64] = [
+    0x00, 0xC6, 0x37, 0xD5, 0x32, 0xB7, 0x57, 0x5C, 0xC2, 0xD8, 0x7C, 0x4D, 0xD9, 0x70, 0xC7, 0x73,
+    0x10, 0xE6, 0xD2, 0xAA, 0x5E, 0xA1, 0x3E, 0x5A, 0x14, 0xAF, 0x30, 0x61, 0xC9, 0x70, 0xDB, 0x2E,
+    0x64, 0x22, 0x72, 0xB5, 0xBD, 0x65, 0xF4, 0xEC, 0x22, 0xBC, 0xD3, 0x72, 0x35, 0xCD, 0xAA, 0x41,
+    0x1F, 0xA7, 0xF3, 0x53, 0x14, 0xDE, 0x7E, 0x02, 0xD9, 0x0F, 0xB5, 0x5E, 0x25, 0x1D, 0x29, 0x79,
+];
+const SELF_TEST_V2: [u8; 64] = [
+    0x00, 0xEB, 0x66, 0xBA, 0x57, 0xBF, 0x23, 0x95, 0xD0, 0xE3, 0x0D, 0x3D, 0x27, 0x89, 0x5C, 0xDE,
+    0x9D, 0x3B, 0xA7, 0x00, 0x21, 0x5B, 0x89, 0x82, 0x51, 0x3A, 0xEB, 0x02, 0x0C, 0xA5, 0x00, 0x49,
+    0x7C, 0x84, 0x4D, 0xB3, 0xCC, 0xD2, 0x1B, 0x81, 0x5D, 0x48, 0x76, 0xD5, 0x71, 0x61, 0x21, 0xA9,
+    0x86, 0x96, 0x83, 0x38, 0xCF, 0x9D, 0x5B, 0x6D, 0xDC, 0x15, 0xBA, 0x3E, 0x7D, 0x95, 0x3B, 0x2F,
+];
+
+// ComIrqReg bits
+const TIMER_IRQ: u8 = 0x01;
+const ERR_IRQ: u8 = 0x02;
+const IDLE_IRQ: u8 = 0x10;
+const RX_IRQ: u8 = 0x20;
+
+// ErrorReg bits
+const PROTOCOL_ERR: u8 = 0x01;
+const PARITY_ERR: u8 = 0x02;
+const CRC_ERR: u8 = 0x04;
+const COLL_ERR: u8 = 0x08;
+const BUFFER_OVFL: u8 = 0x10;
+
+// Status2Reg: Crypto1 unit is switched on after a successful MFAuthent
+const MF_CRYPTO1_ON: u8 = 0x08;
+
+// Timer ticks at 40 kHz (25 us) with the prescaler set in `init`
+const TIMER_HZ: u32 = 40_000;
+const DEFAULT_TIMEOUT_MS: u32 = 25;
+
+// Field off time that sends every card back to IDLE
+const FIELD_RESET: Duration = Duration::from_millis(5);
+
+// How many times to look at ComIrqReg before giving up on a chip that has stopped answering
+const MAX_IRQ_POLLS: u32 = 2000;
+
+// Register access to the chip. SPI and I2C frame the address differently.
+pub trait Mfrc522Bus {
+    fn read(&mut self, reg: u8, buf: &mut [u8]) -> anyhow::Result<()>;
+    fn write(&mut self, reg: u8, bytes: &[u8]) -> anyhow::Result<()>;
+}
+
+impl Mfrc522Bus for SpiDeviceDriver<'static, Arc<SpiDriver<'static>>> {
+    // Address byte: MSB set for read, register in bits 6..1. Repeating the address keeps
+    // reading the same register, which is how the FIFO is drained.
+    fn read(&mut self, reg: u8, buf: &mut [u8]) -> anyhow::Result<()> {
+        let addr = 0x80 | ((reg << 1) & 0x7E);
+
+        let mut tx = vec![addr; buf.len() + 1];
+        tx[buf.len()] = 0x00;
+        let mut rx = vec![0u8; buf.len() + 1];
+
+        self.transfer(&mut rx, &tx)
+            .map_err(|err| anyhow::Error::msg(format!("MFRC522 SPI read Error: {}", err)))?;
+
+        buf.copy_from_slice(&rx[1..]);
+
+        Ok(())
+    }
+
+    fn write(&mut self, reg: u8, bytes: &[u8]) -> anyhow::Result<()> {
+        let mut tx = vec![(reg << 1) & 0x7E];
+        tx.extend_from_slice(bytes);
+
+        SpiDeviceDriver::write(self, &tx)
+            .map_err(|err| anyhow::Error::msg(format!("MFRC522 SPI write Error: {}", err)))
+    }
+}
+
+pub struct Mfrc522I2cBus {
+    driver: I2cDriver<'static>,
+    address: u8,
+}
+
+impl Mfrc522Bus for Mfrc522I2cBus {
+    fn read(&mut self, reg: u8, buf: &mut [u8]) -> anyhow::Result<()> {
+        self.driver
+            .write_read(self.address, &[reg], buf, BLOCK)
+            .map_err(|err| anyhow::Error::msg(format!("MFRC522 I2C read Error: {}", err)))
+    }
+
+    fn write(&mut self, reg: u8, bytes: &[u8]) -> anyhow::Result<()> {
+        let mut tx = vec![reg];
+        tx.extend_from_slice(bytes);
+
+        self.driver
+            .write(self.address, &tx, BLOCK)
+            .map_err(|err| anyhow::Error::msg(format!("MFRC522 I2C write Error: {}", err)))
+    }
+}
+
+// Errors reported by the chip about the exchange with the card, as opposed to bus errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PiccError {
+    Timeout,
+    Collision,
+    Crc,
+    Parity,
+    Protocol,
+    BufferOverflow,
+    Nak,
+    IncompleteFrame,
+    AuthFailed,
+}
+
+impl fmt::Display for PiccError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for PiccError {}
+
+// What was left in the FIFO after a transceive
+struct Frame {
+    data: Vec<u8>,
+    // Valid bits in the last byte, 0 means the whole byte
+    last_bits: u8,
+}
+
+pub type Mfrc522Spi = Mfrc522Reader<SpiDeviceDriver<'static, Arc<SpiDriver<'static>>>>;
+pub type Mfrc522I2c = Mfrc522Reader<Mfrc522I2cBus>;
+
+// Low-power card detection. The MFRC522 has no detection cycle of its own, so between cards the
+// field is off and the chip powered down, and every `interval` it wakes for one REQA whose
+// answer (or timeout) comes back on the IRQ pin instead of being polled for.
+#[derive(Copy, Clone, Debug)]
+pub struct LpcdConfig {
+    pub interval: Duration,
+    // How long the field stays on for each detection pulse
+    pub pulse: Duration,
+    // RxThresholdReg MinLevel (0-15) during detection, lower is more sensitive
+    pub min_level: u8,
+    // RFCfgReg RxGain (0-7) during detection
+    pub rx_gain: u8,
+}
+
+pub struct Mfrc522Reader<B: Mfrc522Bus> {
+    bus: B,
+    uid: Option<CardUid>,
+    sak: u8,
+    lpcd: Option<(LpcdConfig, PinDriver<'static, Gpio4, Input>)>,
+    // RFCfgReg RxGain (0-7), kept so a re-init doesn't lose it
+    gain: u8,
+    field: bool,
+}
+
+impl Mfrc522Spi {
+    // A single reader with its chip select on GPIO 44.
+    pub fn spi() -> anyhow::Result<Mfrc522Spi> {
+        Self::spi_device(&Self::spi_bus()?, unsafe { Gpio44::new() })
+    }
+
+    // Two readers sharing the bus, chip selects on GPIO 44 (outer) and GPIO 14 (inner).
+    pub fn spi_pair() -> anyhow::Result<(Mfrc522Spi, Mfrc522Spi)> {
+        let bus = Self::spi_bus()?;
+
+        Ok((
+            Self::spi_device(&bus, unsafe { Gpio44::new() })?,
+            Self::spi_device(&bus, unsafe { Gpio14::new() })?,
+        ))
+    }
+
+    fn spi_bus() -> anyhow::Result<Arc<SpiDriver<'static>>> {
+        let sclk = unsafe { Gpio7::new() };
+        let sdo = unsafe { Gpio8::new() }; // MOSI
+        let sdi = unsafe { Gpio9::new() }; // MISO
+
+        let driver = SpiDriver::new(
+            unsafe { SPI3::new() },
+            sclk,
+            sdo,
+            Some(sdi),
+            &spi::config::DriverConfig {
+                dma: spi::Dma::Disabled,
+                intr_flags: enumset::EnumSet::new(),
+            },
+        )
+        .map_err(|err| anyhow::Error::msg(format!("SpiDriver Error: {}", err)))?;
+
+        Ok(Arc::new(driver))
+    }
+
+    fn spi_device(
+        bus: &Arc<SpiDriver<'static>>,
+        cs: impl Peripheral<P = impl OutputPin> + 'static,
+    ) -> anyhow::Result<Mfrc522Spi> {
+        let spi_device_driver = SpiDeviceDriver::new(
+            bus.clone(),
+            Some(cs),
+            &esp_idf_hal::spi::config::Config::new(),
+        )
+        .map_err(|err| anyhow::Error::msg(format!("SpiDeviceDriver Error: {}", err)))?;
+
+        Mfrc522Reader::new(spi_device_driver)
+    }
+}
+
+impl Mfrc522I2c {
+    pub fn i2c() -> anyhow::Result<Mfrc522I2c> {
+        let sda = unsafe { Gpio5::new() };
+        let scl = unsafe { Gpio6::new() };
+
+        let driver = I2cDriver::new(
+            unsafe { I2C0::new() },
+            sda,
+            scl,
+            &I2cConfig::new().baudrate(400.kHz().into()),
+        )
+        .map_err(|err| anyhow::Error::msg(format!("I2cDriver Error: {}", err)))?;
+
+        Mfrc522Reader::new(Mfrc522I2cBus {
+            driver,
+            address: MFRC522_I2C_ADDRESS,
+        })
+    }
+}
+
+impl<B: Mfrc522Bus> Mfrc522Reader<B> {
+    pub fn new(bus: B) -> anyhow::Result<Mfrc522Reader<B>> {
+        let mut reader = Mfrc522Reader {
+            bus,
+            uid: None,
+            sak: 0,
+            lpcd: None,
+            gain: DEFAULT_GAIN,
+            field: true,
+        };
+
+        // A reader that doesn't come up isn't fatal, the health check keeps retrying
+        if let Err(err) = reader.init() {
+            println!("MFRC522 init failed: {err:?}");
+        }
+
+        Ok(reader)
+    }
+
+    // Switches to low-power card detection with the chip's IRQ pin wired to GPIO 4.
+    pub fn with_lpcd(mut self, config: LpcdConfig) -> anyhow::Result<Mfrc522Reader<B>> {
+        let mut irq = PinDriver::input(unsafe { Gpio4::new() })?;
+        irq.set_pull(Pull::Up)?;
+        irq.set_interrupt_type(InterruptType::NegEdge)?;
+
+        self.lpcd = Some((config, irq));
+        self.init()?;
+
+        Ok(self)
+    }
+
+    pub fn init(&mut self) -> anyhow::Result<()> {
+        self.write(COMMAND_REG, CMD_SOFT_RESET)?;
+        self.wait_powered_up()?;
+
+        self.write(TX_MODE_REG, 0x00)?;
+        self.write(RX_MODE_REG, 0x00)?;
+        self.write(MOD_WIDTH_REG, 0x26)?;
+
+        // Timer starts at the end of each transmission so a silent card times out:
+        // f_timer = 13.56 MHz / (2 * 0xA9 + 1) = 40 kHz
+        self.write(T_MODE_REG, 0x80)?;
+        self.write(T_PRESCALER_REG, 0xA9)?;
+        self.set_timer_ms(DEFAULT_TIMEOUT_MS)?;
+
+        // 100% ASK modulation, CRC preset 6363h as ISO 14443-3 requires
+        self.write(TX_ASK_REG, 0x40)?;
+        self.write(MODE_REG, 0x3D)?;
+
+        self.write(RF_CFG_REG, self.gain << 4)?;
+
+        if self.lpcd.is_some() {
+            self.write(DIV_I_EN_REG, IRQ_PUSH_PULL)?;
+            self.write(COM_I_EN_REG, IRQ_INV)?;
+        }
+
+        self.antenna(self.field)
+    }
+
+    // Digital self test from the datasheet. Leaves the chip reset, so `init` runs afterwards.
+    fn run_self_test(&mut self) -> anyhow::Result<()> {
+        let reference = match self.read(VERSION_REG)? {
+            0x91 => Some(SELF_TEST_V1),
+            0x92 => Some(SELF_TEST_V2),
+            0x00 | 0xFF => bail!("MFRC522 not responding"),
+            _ => None,
+        };
+
+        self.write(COMMAND_REG, CMD_SOFT_RESET)?;
+        self.wait_powered_up()?;
+
+        // Clear the internal buffer
+        self.write(FIFO_LEVEL_REG, 0x80)?;
+        self.bus.write(FIFO_DATA_REG, &[0x00; 25])?;
+        self.write(COMMAND_REG, CMD_MEM)?;
+
+        self.write(AUTO_TEST_REG, SELF_TEST_ENABLE)?;
+        self.write(FIFO_DATA_REG, 0x00)?;
+        self.write(COMMAND_REG, CMD_CALC_CRC)?;
+
+        let mut complete = false;
+
+        for _ in 0..MAX_IRQ_POLLS {
+            if self.read(FIFO_LEVEL_REG)? >= 64 {
+                complete = true;
+                break;
+            }
+        }
+
+        let mut result = [0u8; 64];
+
+        if complete {
+            self.bus.read(FIFO_DATA_REG, &mut result)?;
+        }
+
+        self.write(COMMAND_REG, CMD_IDLE)?;
+        self.write(AUTO_TEST_REG, 0x00)?;
+
+        if !complete {
+            bail!("MFRC522 self test did not finish");
+        }
+
+        match reference {
+            Some(reference) if reference != result => bail!("MFRC522 self test mismatch"),
+            _ => Ok(()),
+        }
+    }
+
+    // PowerDown bit clears once the oscillator is back up
+    fn wait_powered_up(&mut self) -> anyhow::Result<()> {
+        for _ in 0..MAX_IRQ_POLLS {
+            if self.read(COMMAND_REG)? & POWER_DOWN == 0 {
+                return Ok(());
+            }
+        }
+
+        bail!("MFRC522 did not power up");
+    }
+
+    fn antenna(&mut self, on: bool) -> anyhow::Result<()> {
+        match on {
+            true => self.rmw(TX_CONTROL_REG, |b| b | 0x03),
+            false => self.rmw(TX_CONTROL_REG, |b| b & !0x03),
+        }
+    }
+
+    // One detection pulse: REQA with the result signalled on the IRQ pin. True if anything
+    // answered, which may also be noise the following poll will reject.
+    async fn detect_pulse(&mut self) -> anyhow::Result<bool> {
+        let Some((config, _)) = self.lpcd else {
+            bail!("Low-power card detection not configured");
+        };
+
+        self.set_timer_ms(DEFAULT_TIMEOUT_MS)?;
+        self.write(RX_THRESHOLD_REG, (config.min_level.min(15) << 4) | 0x04)?;
+        self.write(RF_CFG_REG, config.rx_gain.min(7) << 4)?;
+        self.antenna(true)?;
+
+        self.write(COMMAND_REG, CMD_IDLE)?;
+        self.write(COM_IRQ_REG, 0x7F)?;
+        self.write(COM_I_EN_REG, IRQ_INV | RX_IRQ | TIMER_IRQ)?;
+        self.write(FIFO_LEVEL_REG, 0x80)?;
+        self.bus.write(FIFO_DATA_REG, &[iso14443::REQA])?;
+        self.write(COMMAND_REG, CMD_TRANSCEIVE)?;
+
+        // REQA is a 7 bit short frame
+        self.write(BIT_FRAMING_REG, 0x87)?;
+
+        // The pin stays low until ComIrqReg is cleared, so an edge that fired before we started
+        // waiting is still seen. ComIrqReg is the real answer either way, the pin only saves
+        // polling it.
+        let irq = &mut self.lpcd.as_mut().unwrap().1;
+
+        if irq.is_high() {
+            if let Ok(result) = timeout(config.pulse, irq.wait_for_falling_edge()).await {
+                result?;
+            }
+        }
+
+        let status = self.read(COM_IRQ_REG)?;
+
+        self.write(COM_I_EN_REG, IRQ_INV)?;
+        self.write(COMMAND_REG, CMD_IDLE)?;
+        self.write(RX_THRESHOLD_REG, RX_THRESHOLD_DEFAULT)?;
+        self.write(RF_CFG_REG, self.gain << 4)?;
+
+        Ok(status & RX_IRQ != 0)
+    }
+
+    pub fn set_timer_ms(&mut self, ms: u32) -> anyhow::Result<()> {
+        let reload = (ms * TIMER_HZ / 1000).min(0xFFFF) as u16;
+
+        self.write(T_RELOAD_REG_H, (reload >> 8) as u8)?;
+        self.write(T_RELOAD_REG_L, reload as u8)
+    }
+
+    fn read(&mut self, reg: u8) -> anyhow::Result<u8> {
+        let mut buf = [0u8; 1];
+        self.bus.read(reg, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn write(&mut self, reg: u8, val: u8) -> anyhow::Result<()> {
+        self.bus.write(reg, &[val])
+    }
+
+    fn rmw(&mut self, reg: u8, f: impl FnOnce(u8) -> u8) -> anyhow::Result<()> {
+        let val = self.read(reg)?;
+        self.write(reg, f(val))
+    }
+
+    fn check_error_register(&mut self) -> anyhow::Result<()> {
+        let err = self.read(ERROR_REG)?;
+
+        if err & PROTOCOL_ERR != 0 {
+            Err(PiccError::Protocol.into())
+        } else if err & PARITY_ERR != 0 {
+            Err(PiccError::Parity.into())
+        } else if err & CRC_ERR != 0 {
+            Err(PiccError::Crc.into())
+        } else if err & COLL_ERR != 0 {
+            Err(PiccError::Collision.into())
+        } else if err & BUFFER_OVFL != 0 {
+            Err(PiccError::BufferOverflow.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Runs `command` with `tx` in the FIFO and waits for `done` (or the timer) in ComIrqReg.
+    fn execute(&mut self, command: u8, tx: &[u8], bit_framing: u8, done: u8) -> anyhow::Result<()> {
+        self.write(COMMAND_REG, CMD_IDLE)?;
+        self.write(COM_IRQ_REG, 0x7F)?;
+        self.write(FIFO_LEVEL_REG, 0x80)?;
+        self.bus.write(FIFO_DATA_REG, tx)?;
+        self.write(COMMAND_REG, command)?;
+
+        // StartSend only applies to Transceive
+        self.write(BIT_FRAMING_REG, bit_framing)?;
+
+        for _ in 0..MAX_IRQ_POLLS {
+            let irq = self.read(COM_IRQ_REG)?;
+
+            if irq & done != 0 {
+                return self.check_error_register();
+            }
+
+            if irq & TIMER_IRQ != 0 {
+                return Err(PiccError::Timeout.into());
+            }
+        }
+
+        bail!("MFRC522 stopped responding");
+    }
+
+    fn transceive_bits(&mut self, tx: &[u8], tx_last_bits: u8) -> anyhow::Result<Frame> {
+        let bit_framing = 0x80 | (tx_last_bits & 0x07);
+
+        self.execute(CMD_TRANSCEIVE, tx, bit_framing, RX_IRQ | IDLE_IRQ | ERR_IRQ)?;
+
+        let len = self.read(FIFO_LEVEL_REG)? as usize & 0x7F;
+        let mut data = vec![0u8; len];
+
+        if len > 0 {
+            self.bus.read(FIFO_DATA_REG, &mut data)?;
+        }
+
+        let last_bits = self.read(CONTROL_REG)? & 0x07;
+
+        Ok(Frame { data, last_bits })
+    }
+
+    fn request(&mut self, command: u8) -> anyhow::Result<[u8; 2]> {
+        // REQA and WUPA are 7 bit short frames
+        let frame = self.transceive_bits(&[command], 7)?;
+
+        match frame.data.as_slice() {
+            [a, b] if frame.last_bits == 0 => Ok([*a, *b]),
+            _ => Err(PiccError::IncompleteFrame.into()),
+        }
+    }
+
+    // Anticollision and select for each cascade level. Returns the UID and the final SAK.
+    // A collision is reported as an error rather than resolved, the next poll tries again.
+    fn select(&mut self) -> anyhow::Result<(CardUid, u8)> {
+        // ValuesAfterColl: clear received bits after a collision
+        self.rmw(COLL_REG, |b| b & !0x80)?;
+
+        let mut uid = Vec::with_capacity(10);
+
+        for sel in [iso14443::SEL_CL1, iso14443::SEL_CL2, iso14443::SEL_CL3] {
+            let frame = self.transceive_bits(&[sel, 0x20], 0)?;
+
+            if frame.data.len() != 5 || iso14443::bcc(&frame.data[..4]) != frame.data[4] {
+                return Err(PiccError::IncompleteFrame.into());
+            }
+
+            let mut select = vec![sel, 0x70];
+            select.extend_from_slice(&frame.data);
+
+            let response = self.transceive_bits(&append_crc_a(&select), 0)?;
+
+            let Some(&[sak]) = strip_crc_a(&response.data) else {
+                return Err(PiccError::Crc.into());
+            };
+
+            if sak & iso14443::SAK_CASCADE != 0 {
+                // Cascade tag then 3 UID bytes
+                uid.extend_from_slice(&frame.data[1..4]);
+                continue;
+            }
+
+            uid.extend_from_slice(&frame.data[..4]);
+
+            return match CardUid::from_bytes(&uid) {
+                Some(uid) => Ok((uid, sak)),
+                None => Err(PiccError::IncompleteFrame.into()),
+            };
+        }
+
+        Err(PiccError::Protocol.into())
+    }
+
+    // REQA or WUPA followed by select. No answer just means there is no (matching) card.
+    fn activate(&mut self, command: u8) -> anyhow::Result<Option<CardUid>> {
+        self.uid = None;
+        self.sak = 0;
+
+        // An ISO 14443-4 session may have stretched the timer
+        self.set_timer_ms(DEFAULT_TIMEOUT_MS)?;
+
+        if let Err(err) = self.request(command) {
+            return match Mfrc522Reader::<B>::is_timeout(&err) {
+                true => Ok(None),
+                false => Err(err),
+            };
+        }
+
+        let (uid, sak) = self.select()?;
+
+        self.uid = Some(uid);
+        self.sak = sak;
+
+        Ok(Some(uid))
+    }
+
+    fn is_timeout(err: &anyhow::Error) -> bool {
+        err.downcast_ref::<PiccError>() == Some(&PiccError::Timeout)
+    }
+
+    // MIFARE writes are acknowledged with a 4 bit ACK
+    fn expect_ack(frame: &Frame) -> anyhow::Result<()> {
+        match frame.data.as_slice() {
+            [ack] if frame.last_bits == 4 && ack & 0x0F == iso14443::MF_ACK => Ok(()),
+            _ => Err(PiccError::Nak.into()),
+        }
+    }
+}
+
+impl<B: Mfrc522Bus> CardReader for Mfrc522Reader<B> {
+    fn name(&self) -> &'static str {
+        "MFRC522"
+    }
+
+    fn version(&mut self) -> anyhow::Result<u8> {
+        self.read(VERSION_REG)
+    }
+
+    fn poll(&mut self) -> anyhow::Result<Option<CardUid>> {
+        self.activate(iso14443::REQA)
+    }
+
+    fn wake(&mut self) -> anyhow::Result<Option<CardUid>> {
+        self.activate(iso14443::WUPA)
+    }
+
+    fn sak(&self) -> Option<u8> {
+        self.uid.map(|_| self.sak)
+    }
+
+    async fn wait_for_card(&mut self, interval: Duration) -> anyhow::Result<()> {
+        // Nothing to detect with the field switched off
+        let config = match self.lpcd {
+            Some((config, _)) if self.field => config,
+            _ => {
+                sleep(interval).await;
+                return Ok(());
+            }
+        };
+
+        // One power down and detection pulse per call, so commands and health checks still get
+        // their turn while the field stays empty
+        self.antenna(false)?;
+        self.write(COMMAND_REG, POWER_DOWN)?;
+
+        sleep(config.interval).await;
+
+        self.rmw(COMMAND_REG, |b| b & !POWER_DOWN)?;
+        self.wait_powered_up()?;
+
+        if self.detect_pulse().await? {
+            // The card answered the detection REQA, reset it so the real poll finds it idle
+            self.antenna(false)?;
+            sleep(FIELD_RESET).await;
+            self.antenna(true)?;
+        }
+
+        Ok(())
+    }
+
+    fn self_test(&mut self) -> anyhow::Result<()> {
+        let result = self.run_self_test();
+
+        self.init()?;
+
+        result
+    }
+
+    fn reinit(&mut self) -> anyhow::Result<()> {
+        self.init()
+    }
+
+    fn set_antenna_gain(&mut self, gain: u8) -> anyhow::Result<()> {
+        self.gain = gain.min(7);
+        self.write(RF_CFG_REG, self.gain << 4)
+    }
+
+    fn set_rf_field(&mut self, on: bool) -> anyhow::Result<()> {
+        self.field = on;
+        self.antenna(on)
+    }
+
+    fn halt(&mut self) -> anyhow::Result<()> {
+        self.stop_crypto()?;
+
+        // Any answer within the timeout counts as NAK, silence means the card halted
+        match self.transceive_bits(&append_crc_a(&[iso14443::HLTA, 0x00]), 0) {
+            Err(err) if Mfrc522Reader::<B>::is_timeout(&err) => Ok(()),
+            Err(err) => Err(err),
+            Ok(_) => Err(PiccError::Nak.into()),
+        }
+    }
+
+    fn authenticate(
+        &mut self,
+        block: u8,
+        key_type: KeyType,
+        key: &MifareKey,
+    ) -> anyhow::Result<()> {
+        let Some(uid) = self.uid else {
+            bail!("No card selected");
+        };
+
+        let mut tx = vec![
+            match key_type {
+                KeyType::A => iso14443::MF_AUTH_KEY_A,
+                KeyType::B => iso14443::MF_AUTH_KEY_B,
+            },
+            block,
+        ];
+        tx.extend_from_slice(key);
+        // Crypto1 uses the last 4 bytes of double and triple size UIDs
+        tx.extend_from_slice(&uid.as_bytes()[uid.as_bytes().len() - 4..]);
+
+        self.write(BIT_FRAMING_REG, 0x00)?;
+        self.execute(CMD_MF_AUTHENT, &tx, 0x00, IDLE_IRQ | ERR_IRQ)?;
+
+        match self.read(STATUS2_REG)? & MF_CRYPTO1_ON {
+            0 => Err(PiccError::AuthFailed.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn read_block(&mut self, block: u8) -> anyhow::Result<[u8; 16]> {
+        let frame = self.transceive_bits(&append_crc_a(&[iso14443::MF_READ, block]), 0)?;
+
+        match strip_crc_a(&frame.data) {
+            Some(data) if data.len() == 16 => Ok(data.try_into().unwrap()),
+            Some(_) => Err(PiccError::IncompleteFrame.into()),
+            None => Err(PiccError::Crc.into()),
+        }
+    }
+
+    fn write_block(&mut self, block: u8, data: &[u8; 16]) -> anyhow::Result<()> {
+        let frame = self.transceive_bits(&append_crc_a(&[iso14443::MF_WRITE, block]), 0)?;
+        Mfrc522Reader::<B>::expect_ack(&frame)?;
+
+        let frame = self.transceive_bits(&append_crc_a(data), 0)?;
+        Mfrc522Reader::<B>::expect_ack(&frame)
+    }
+
+    fn stop_crypto(&mut self) -> anyhow::Result<()> {
+        self.rmw(STATUS2_REG, |b| b & !MF_CRYPTO1_ON)
+    }
+}
+
+impl<B: Mfrc522Bus> Transceive for Mfrc522Reader<B> {
+    // Standard frames with CRC_A done in software, the FIFO limits them to 64 bytes
+    fn transceive(&mut self, frame: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let response = self.transceive_bits(&append_crc_a(frame), 0)?;
+
+        match strip_crc_a(&response.data) {
+            Some(data) => Ok(data.to_vec()),
+            None => Err(PiccError::Crc.into()),
+        }
+    }
+
+    fn set_timeout_ms(&mut self, ms: u32) -> anyhow::Result<()> {
+        self.set_timer_ms(ms)
+    }
+}
@@ -0,0 +1,141 @@
+// MIFARE Classic memory layout and access conditions. No hardware in here.
+
+pub type MifareKey = [u8; 6];
+
+pub const DEFAULT_KEY: MifareKey = [0xFF; 6];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyType {
+    A,
+    B,
+}
+
+// Sectors 0-31 have 4 blocks, the extra sectors on a 4K card have 16.
+pub fn first_block(sector: u8) -> u8 {
+    if sector < 32 {
+        sector * 4
+    } else {
+        128 + (sector - 32) * 16
+    }
+}
+
+pub fn blocks_in_sector(sector: u8) -> u8 {
+    if sector < 32 {
+        4
+    } else {
+        16
+    }
+}
+
+pub fn sector_of(block: u8) -> u8 {
+    if block < 128 {
+        block / 4
+    } else {
+        32 + (block - 128) / 16
+    }
+}
+
+pub fn trailer_block(sector: u8) -> u8 {
+    first_block(sector) + (blocks_in_sector(sector) - 1)
+}
+
+pub fn is_trailer(block: u8) -> bool {
+    block == trailer_block(sector_of(block))
+}
+
+// Access condition group (0-3) that covers a block. Large sectors share one group per 5 blocks.
+fn access_group(block: u8) -> usize {
+    let offset = block - first_block(sector_of(block));
+
+    if sector_of(block) < 32 {
+        offset as usize
+    } else if is_trailer(block) {
+        3
+    } else {
+        (offset / 5) as usize
+    }
+}
+
+// The C1 C2 C3 bits of one access group, decoded from trailer bytes 6-8.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AccessBits {
+    pub c1: bool,
+    pub c2: bool,
+    pub c3: bool,
+}
+
+// Decodes the access bits for every group, or `None` if the inverted copies don't match
+// (a card with inconsistent access bits locks the sector forever).
+pub fn decode_access_bits(trailer: &[u8; 16]) -> Option<[AccessBits; 4]> {
+    let (b6, b7, b8) = (trailer[6], trailer[7], trailer[8]);
+
+    if (b6 & 0x0F) != (!b7 >> 4) & 0x0F
+        || (b6 >> 4) != (!b8 & 0x0F)
+        || (b7 & 0x0F) != (!b8 >> 4) & 0x0F
+    {
+        return None;
+    }
+
+    let mut groups = [AccessBits {
+        c1: false,
+        c2: false,
+        c3: false,
+    }; 4];
+
+    for (i, group) in groups.iter_mut().enumerate() {
+        group.c1 = (b7 >> (4 + i)) & 1 != 0;
+        group.c2 = (b8 >> i) & 1 != 0;
+        group.c3 = (b8 >> (4 + i)) & 1 != 0;
+    }
+
+    Some(groups)
+}
+
+pub fn encode_access_bits(groups: &[AccessBits; 4]) -> [u8; 3] {
+    let mut c1 = 0u8;
+    let mut c2 = 0u8;
+    let mut c3 = 0u8;
+
+    for (i, group) in groups.iter().enumerate() {
+        c1 |= (group.c1 as u8) << i;
+        c2 |= (group.c2 as u8) << i;
+        c3 |= (group.c3 as u8) << i;
+    }
+
+    [
+        (!c2 << 4) | (!c1 & 0x0F),
+        (c1 << 4) | (!c3 & 0x0F),
+        (c3 << 4) | c2,
+    ]
+}
+
+// Whether `key_type` may read a data block with these access bits.
+pub fn can_read(bits: AccessBits, key_type: KeyType) -> bool {
+    match (bits.c1, bits.c2, bits.c3) {
+        (true, true, true) => false,
+        (false, true, true) | (true, false, true) => key_type == KeyType::B,
+        _ => true,
+    }
+}
+
+// Whether `key_type` may write a data block with these access bits.
+pub fn can_write(bits: AccessBits, key_type: KeyType) -> bool {
+    match (bits.c1, bits.c2, bits.c3) {
+        (false, false, false) => true,
+        (true, false, false) | (true, true, false) | (false, true, true) => key_type == KeyType::B,
+        _ => false,
+    }
+}
+
+pub fn access_bits_for(trailer: &[u8; 16], block: u8) -> Option<AccessBits> {
+    decode_access_bits(trailer).map(|groups| groups[access_group(block)])
+}
+
+// The sector trailer a blank card ships with: both keys FFFFFFFFFFFF, transport configuration.
+pub fn transport_trailer() -> [u8; 16] {
+    let mut trailer = [0u8; 16];
+    trailer[0..6].copy_from_slice(&DEFAULT_KEY);
+    trailer[6..10].copy_from_slice(&[0xFF, 0x07, 0x80, 0x69]);
+    trailer[10..16].copy_from_slice(&DEFAULT_KEY);
+    trailer
+}
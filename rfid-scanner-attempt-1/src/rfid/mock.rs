@@ -0,0 +1,242 @@
+use anyhow::bail;
+use std::sync::{Arc, Mutex};
+
+use super::{
+    iso14443,
+    iso14443_4::Transceive,
+    mifare::{self, KeyType, MifareKey},
+    CardReader,
+};
+use crate::card_uid::CardUid;
+
+// A MIFARE Classic 1K image: 64 blocks with a transport trailer in every sector.
+#[derive(Clone)]
+pub struct MockCard {
+    pub uid: CardUid,
+    pub blocks: Vec<[u8; 16]>,
+}
+
+impl MockCard {
+    pub fn new(uid: CardUid) -> MockCard {
+        let mut blocks = vec![[0u8; 16]; 64];
+
+        for sector in 0..16 {
+            blocks[mifare::trailer_block(sector) as usize] = mifare::transport_trailer();
+        }
+
+        MockCard { uid, blocks }
+    }
+}
+
+// What the mock reader currently has in its field. Shared so a test (or the HTTP server)
+// can place and remove cards while `RfidService` is polling.
+#[derive(Default)]
+struct Field {
+    card: Option<MockCard>,
+    halted: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct MockField {
+    field: Arc<Mutex<Field>>,
+}
+
+impl MockField {
+    pub fn present(&self, card: MockCard) {
+        *self.field.lock().unwrap() = Field {
+            card: Some(card),
+            halted: false,
+        };
+    }
+
+    // Takes the card away and hands back its (possibly rewritten) contents.
+    pub fn remove(&self) -> Option<MockCard> {
+        std::mem::take(&mut *self.field.lock().unwrap()).card
+    }
+}
+
+// In-memory reader with no hardware behind it.
+pub struct MockReader {
+    field: MockField,
+    // Sector and key type of the last successful authentication
+    auth: Option<(u8, KeyType)>,
+}
+
+impl MockReader {
+    pub fn new() -> (MockReader, MockField) {
+        let field = MockField::default();
+
+        (
+            MockReader {
+                field: field.clone(),
+                auth: None,
+            },
+            field,
+        )
+    }
+
+    // Access bits for `block`, provided the current authentication covers its sector.
+    fn access(&self, card: &MockCard, block: u8) -> anyhow::Result<(mifare::AccessBits, KeyType)> {
+        let sector = mifare::sector_of(block);
+
+        let Some((auth_sector, key_type)) = self.auth.filter(|(s, _)| *s == sector) else {
+            bail!("Sector {} not authenticated", sector);
+        };
+
+        let trailer = card.blocks[mifare::trailer_block(auth_sector) as usize];
+
+        match mifare::access_bits_for(&trailer, block) {
+            Some(bits) => Ok((bits, key_type)),
+            None => bail!("Sector {} has invalid access bits", sector),
+        }
+    }
+}
+
+impl CardReader for MockReader {
+    fn name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn version(&mut self) -> anyhow::Result<u8> {
+        Ok(0x00)
+    }
+
+    // A halted card stays quiet until it is taken away and presented again, like a real one.
+    fn poll(&mut self) -> anyhow::Result<Option<CardUid>> {
+        self.auth = None;
+
+        let field = self.field.field.lock().unwrap();
+
+        match field.halted {
+            true => Ok(None),
+            false => Ok(field.card.as_ref().map(|card| card.uid)),
+        }
+    }
+
+    fn wake(&mut self) -> anyhow::Result<Option<CardUid>> {
+        self.field.field.lock().unwrap().halted = false;
+
+        self.poll()
+    }
+
+    // Mock cards are all MIFARE Classic 1K
+    fn sak(&self) -> Option<u8> {
+        let field = self.field.field.lock().unwrap();
+
+        field.card.as_ref().map(|_| iso14443::SAK_MIFARE_1K)
+    }
+
+    fn self_test(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn reinit(&mut self) -> anyhow::Result<()> {
+        self.auth = None;
+
+        Ok(())
+    }
+
+    // No analog front end to configure
+    fn set_antenna_gain(&mut self, _gain: u8) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_rf_field(&mut self, _on: bool) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn halt(&mut self) -> anyhow::Result<()> {
+        self.auth = None;
+        self.field.field.lock().unwrap().halted = true;
+
+        Ok(())
+    }
+
+    fn authenticate(
+        &mut self,
+        block: u8,
+        key_type: KeyType,
+        key: &MifareKey,
+    ) -> anyhow::Result<()> {
+        self.auth = None;
+
+        let field = self.field.field.lock().unwrap();
+
+        let Some(card) = field.card.as_ref() else {
+            bail!("No card in field");
+        };
+
+        let sector = mifare::sector_of(block);
+        let trailer = card.blocks[mifare::trailer_block(sector) as usize];
+
+        let expected = match key_type {
+            KeyType::A => &trailer[0..6],
+            KeyType::B => &trailer[10..16],
+        };
+
+        if expected != key {
+            bail!("Authentication failed");
+        }
+
+        self.auth = Some((sector, key_type));
+
+        Ok(())
+    }
+
+    fn read_block(&mut self, block: u8) -> anyhow::Result<[u8; 16]> {
+        let field = self.field.field.lock().unwrap();
+
+        let Some(card) = field.card.as_ref() else {
+            bail!("No card in field");
+        };
+
+        let (bits, key_type) = self.access(card, block)?;
+
+        if !mifare::is_trailer(block) && !mifare::can_read(bits, key_type) {
+            bail!("Block {} not readable with key {:?}", block, key_type);
+        }
+
+        let mut data = card.blocks[block as usize];
+
+        // Key A never reads back
+        if mifare::is_trailer(block) {
+            data[0..6].fill(0);
+        }
+
+        Ok(data)
+    }
+
+    fn write_block(&mut self, block: u8, data: &[u8; 16]) -> anyhow::Result<()> {
+        let mut field = self.field.field.lock().unwrap();
+
+        let Some(card) = field.card.as_ref() else {
+            bail!("No card in field");
+        };
+
+        let (bits, key_type) = self.access(card, block)?;
+
+        if mifare::is_trailer(block) {
+            bail!("Writing sector trailers is not supported by the mock");
+        }
+
+        if !mifare::can_write(bits, key_type) {
+            bail!("Block {} not writable with key {:?}", block, key_type);
+        }
+
+        field.card.as_mut().unwrap().blocks[block as usize] = *data;
+
+        Ok(())
+    }
+
+    fn stop_crypto(&mut self) -> anyhow::Result<()> {
+        self.auth = None;
+
+        Ok(())
+    }
+}
+
+impl Transceive for MockReader {
+    fn transceive(&mut self, _frame: &[u8]) -> anyhow::Result<Vec<u8>> {
+        bail!("Mock cards do not support ISO 14443-4");
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::bail;
+
+use super::{iso14443::UL_PAGE_SIZE, CardReader};
+
+// NFC Forum Type 2 tags (MIFARE Ultralight, NTAG21x): the capability container on page 3 and
+// the TLV area from page 4 onwards.
+
+const CC_PAGE: u8 = 3;
+const DATA_PAGE: u8 = 4;
+
+// Capability container magic number
+const NDEF_MAGIC: u8 = 0xE1;
+
+// TLV tags
+const TLV_NULL: u8 = 0x00;
+const TLV_NDEF: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xFE;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TlvScan {
+    // The NDEF message occupies this range of the data area
+    Found(std::ops::Range<usize>),
+    // Need more bytes before we can tell
+    Incomplete,
+    // Terminator or end of the data area without an NDEF message
+    Absent,
+}
+
+// Size of the data area in bytes, `None` if the tag isn't formatted for NDEF.
+pub fn data_area_size(cc: &[u8]) -> Option<usize> {
+    match cc {
+        [NDEF_MAGIC, _version, size, _access, ..] => Some(*size as usize * 8),
+        _ => None,
+    }
+}
+
+// Looks for the first NDEF TLV in the data read so far. `complete` says whether `data` already
+// holds the whole data area.
+pub fn scan_tlvs(data: &[u8], complete: bool) -> TlvScan {
+    let more = || match complete {
+        true => TlvScan::Absent,
+        false => TlvScan::Incomplete,
+    };
+
+    let mut pos = 0;
+
+    loop {
+        let Some(&tag) = data.get(pos) else {
+            return more();
+        };
+
+        match tag {
+            TLV_NULL => {
+                pos += 1;
+                continue;
+            }
+            TLV_TERMINATOR => return TlvScan::Absent,
+            _ => {}
+        }
+
+        // One byte length, or FF followed by two bytes
+        let (len, header) = match data.get(pos + 1) {
+            None => return more(),
+            Some(0xFF) => match data.get(pos + 2..pos + 4) {
+                Some(len) => (u16::from_be_bytes([len[0], len[1]]) as usize, 4),
+                None => return more(),
+            },
+            Some(len) => (*len as usize, 2),
+        };
+
+        let value = pos + header..pos + header + len;
+
+        if tag == TLV_NDEF {
+            return match value.end <= data.len() {
+                true => TlvScan::Found(value),
+                false => more(),
+            };
+        }
+
+        pos = value.end;
+    }
+}
+
+// Reads the NDEF message from the selected tag, 16 bytes at a time until the TLV is complete.
+// `Ok(None)` for tags without NDEF data.
+pub fn read_ndef<R: CardReader + ?Sized>(reader: &mut R) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(size) = data_area_size(&reader.read_block(CC_PAGE)?) else {
+        return Ok(None);
+    };
+
+    let mut data = Vec::with_capacity(size);
+    let mut page = DATA_PAGE;
+
+    loop {
+        let complete = data.len() >= size;
+
+        match scan_tlvs(&data[..data.len().min(size)], complete) {
+            TlvScan::Found(range) => return Ok(Some(data[range].to_vec())),
+            TlvScan::Absent => return Ok(None),
+            TlvScan::Incomplete => {}
+        }
+
+        if page == u8::MAX {
+            bail!("NDEF data runs past the last page");
+        }
+
+        data.extend_from_slice(&reader.read_block(page)?);
+        page = page.saturating_add((16 / UL_PAGE_SIZE) as u8);
+    }
+}
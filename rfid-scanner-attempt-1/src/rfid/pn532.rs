@@ -0,0 +1,518 @@
+use anyhow::bail;
+use esp_idf_hal::{
+    delay::TickType,
+    gpio::{AnyIOPin, Gpio17, Gpio18, Gpio5, Gpio6},
+    i2c::{I2cConfig, I2cDriver, I2C0},
+    prelude::*,
+    uart::{UartConfig, UartDriver, UART1},
+};
+use std::time::{Duration, Instant};
+
+use super::{
+    iso14443,
+    iso14443_4::Transceive,
+    mifare::{KeyType, MifareKey},
+    CardReader,
+};
+use crate::card_uid::CardUid;
+
+const PN532_I2C_ADDRESS: u8 = 0x24;
+
+const HOST_TO_PN532: u8 = 0xD4;
+const PN532_TO_HOST: u8 = 0xD5;
+
+const ACK_FRAME: [u8; 6] = [0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00];
+
+const CMD_DIAGNOSE: u8 = 0x00;
+const CMD_GET_FIRMWARE_VERSION: u8 = 0x02;
+const CMD_SAM_CONFIGURATION: u8 = 0x14;
+const CMD_SET_PARAMETERS: u8 = 0x12;
+const CMD_RF_CONFIGURATION: u8 = 0x32;
+const CMD_IN_DATA_EXCHANGE: u8 = 0x40;
+const CMD_IN_COMMUNICATE_THRU: u8 = 0x42;
+const CMD_IN_LIST_PASSIVE_TARGET: u8 = 0x4A;
+const CMD_IN_RELEASE: u8 = 0x52;
+
+// 106 kbps type A (ISO 14443-A / MIFARE)
+const BRTY_106_TYPE_A: u8 = 0x00;
+
+// RFConfiguration items
+const RF_FIELD: u8 = 0x01;
+const RF_ANALOG_TYPE_A: u8 = 0x0A;
+
+// Analog settings for 106 kbps type A from the user manual. The first byte is CIU_RFCfg, whose
+// upper nibble is the receiver gain.
+const ANALOG_TYPE_A: [u8; 11] = [
+    0x59, 0xF4, 0x3F, 0x11, 0x4D, 0x85, 0x61, 0x6F, 0x26, 0x62, 0x87,
+];
+
+// Diagnose communication line test, the PN532 echoes the data back
+const DIAGNOSE_LINE_TEST: [u8; 5] = [0x00, 0xA5, 0x5A, 0x0F, 0xF0];
+
+// Status byte followed by the longest normal frame: preamble, start code, LEN, LCS, up to 255
+// bytes of TFI and data, DCS and postamble
+const I2C_READ_LEN: usize = 1 + 3 + 2 + 255 + 2;
+
+const ACK_TIMEOUT: Duration = Duration::from_millis(30);
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Byte level access to the PN532, the framing is the same over every interface.
+pub trait Pn532Transport {
+    fn write(&mut self, bytes: &[u8]) -> anyhow::Result<()>;
+
+    // Read one frame (from the preamble on) once the PN532 has one ready.
+    fn read_frame(&mut self, timeout: Duration) -> anyhow::Result<Vec<u8>>;
+}
+
+// Normal information frame: 00 00 FF LEN LCS D4 CMD PARAMS.. DCS 00
+pub fn encode_frame(cmd: u8, params: &[u8]) -> Vec<u8> {
+    let len = (params.len() + 2) as u8;
+
+    let mut frame = vec![
+        0x00,
+        0x00,
+        0xFF,
+        len,
+        len.wrapping_neg(),
+        HOST_TO_PN532,
+        cmd,
+    ];
+    frame.extend_from_slice(params);
+
+    let sum = params
+        .iter()
+        .fold(HOST_TO_PN532.wrapping_add(cmd), |acc, b| {
+            acc.wrapping_add(*b)
+        });
+
+    frame.push(sum.wrapping_neg());
+    frame.push(0x00);
+
+    frame
+}
+
+pub fn is_ack(frame: &[u8]) -> bool {
+    frame.starts_with(&ACK_FRAME)
+}
+
+// Checks the length and data checksums and returns the payload after the D5 CMD+1 header.
+pub fn decode_frame(cmd: u8, frame: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let Some(start) = frame.windows(2).position(|w| w == [0x00, 0xFF]) else {
+        bail!("PN532 frame has no start code");
+    };
+
+    let body = &frame[start + 2..];
+
+    if body.len() < 2 {
+        bail!("PN532 frame too short");
+    }
+
+    let len = body[0] as usize;
+
+    if body[0].wrapping_add(body[1]) != 0 {
+        bail!("PN532 frame length checksum mismatch");
+    }
+
+    if len == 0 || body.len() < 2 + len + 1 {
+        bail!("PN532 frame truncated");
+    }
+
+    let data = &body[2..2 + len];
+    let dcs = body[2 + len];
+
+    if data.iter().fold(dcs, |acc, b| acc.wrapping_add(*b)) != 0 {
+        bail!("PN532 frame data checksum mismatch");
+    }
+
+    // The error frame carries nothing but 7F
+    if data[0] == 0x7F {
+        bail!("PN532 reported a syntax error");
+    }
+
+    if len < 2 {
+        bail!("PN532 frame too short");
+    }
+
+    if data[0] != PN532_TO_HOST || data[1] != cmd + 1 {
+        bail!("PN532 unexpected response {:02X} {:02X}", data[0], data[1]);
+    }
+
+    Ok(data[2..].to_vec())
+}
+
+// InListPassiveTarget response: NbTg [Tg SENS_RES(2) SEL_RES NFCIDLength NFCID..]
+// Returns the UID and SEL_RES (the SAK).
+pub fn parse_target(data: &[u8]) -> anyhow::Result<Option<(CardUid, u8)>> {
+    if data.first().copied().unwrap_or(0) == 0 {
+        return Ok(None);
+    }
+
+    if data.len() < 6 || data.len() < 6 + data[5] as usize {
+        bail!("PN532 target data truncated");
+    }
+
+    let uid_len = data[5] as usize;
+
+    Ok(CardUid::from_bytes(&data[6..6 + uid_len]).map(|uid| (uid, data[4])))
+}
+
+pub struct Pn532Reader<T: Pn532Transport> {
+    transport: T,
+    uid: Option<CardUid>,
+    sak: u8,
+    // How long to wait for the answer to a command, stretched for slow ISO 14443-4 cards
+    response_timeout: Duration,
+    // Receiver gain (0-7) and field state, reapplied after a re-init
+    gain: u8,
+    field: bool,
+}
+
+impl<T: Pn532Transport> Pn532Reader<T> {
+    pub fn new(transport: T) -> anyhow::Result<Pn532Reader<T>> {
+        let mut reader = Pn532Reader {
+            transport,
+            uid: None,
+            sak: 0,
+            response_timeout: RESPONSE_TIMEOUT,
+            gain: ANALOG_TYPE_A[0] >> 4,
+            field: true,
+        };
+
+        // A reader that doesn't come up isn't fatal, the health check keeps retrying
+        if let Err(err) = reader.init() {
+            println!("PN532 init failed: {err:?}");
+        }
+
+        Ok(reader)
+    }
+
+    fn init(&mut self) -> anyhow::Result<()> {
+        // Normal mode, no SAM, use the IRQ pin
+        self.command(CMD_SAM_CONFIGURATION, &[0x01, 0x14, 0x01])?;
+
+        // MaxRetries: only try passive activation once so polling returns straight away
+        self.command(CMD_RF_CONFIGURATION, &[0x05, 0xFF, 0x01, 0x01])?;
+
+        // Keep automatic ATR_RES but turn off automatic RATS, ISO 14443-4 is done by `IsoDep`
+        self.command(CMD_SET_PARAMETERS, &[0x04])?;
+
+        self.set_antenna_gain(self.gain)?;
+        self.set_rf_field(self.field)
+    }
+
+    fn command(&mut self, cmd: u8, params: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.transport.write(&encode_frame(cmd, params))?;
+
+        if !is_ack(&self.transport.read_frame(ACK_TIMEOUT)?) {
+            bail!("PN532 did not acknowledge command {:02X}", cmd);
+        }
+
+        decode_frame(cmd, &self.transport.read_frame(self.response_timeout)?)
+    }
+
+    // Sends `data` to the activated target. The PN532 handles CRC and Crypto1 itself.
+    fn data_exchange(&mut self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut params = vec![0x01];
+        params.extend_from_slice(data);
+
+        let response = self.command(CMD_IN_DATA_EXCHANGE, &params)?;
+
+        match response.split_first() {
+            Some((status, data)) if status & 0x3F == 0 => Ok(data.to_vec()),
+            Some((status, _)) => bail!("PN532 InDataExchange status {:02X}", status),
+            None => bail!("PN532 InDataExchange response empty"),
+        }
+    }
+}
+
+impl<T: Pn532Transport> CardReader for Pn532Reader<T> {
+    fn name(&self) -> &'static str {
+        "PN532"
+    }
+
+    // IC, Ver, Rev, Support. Report the firmware version byte.
+    fn version(&mut self) -> anyhow::Result<u8> {
+        let data = self.command(CMD_GET_FIRMWARE_VERSION, &[])?;
+
+        match data.get(1) {
+            Some(ver) => Ok(*ver),
+            None => bail!("PN532 firmware version response too short"),
+        }
+    }
+
+    fn poll(&mut self) -> anyhow::Result<Option<CardUid>> {
+        self.response_timeout = RESPONSE_TIMEOUT;
+
+        let data = self.command(CMD_IN_LIST_PASSIVE_TARGET, &[0x01, BRTY_106_TYPE_A])?;
+        let target = parse_target(&data)?;
+
+        self.uid = target.map(|(uid, _)| uid);
+        self.sak = target.map(|(_, sak)| sak).unwrap_or(0);
+
+        Ok(self.uid)
+    }
+
+    // InRelease doesn't reliably leave the card halted, so reset everything in the field by
+    // switching it off and on before listing targets again.
+    fn wake(&mut self) -> anyhow::Result<Option<CardUid>> {
+        self.command(CMD_RF_CONFIGURATION, &[RF_FIELD, 0x00])?;
+        self.command(CMD_RF_CONFIGURATION, &[RF_FIELD, 0x01])?;
+
+        self.poll()
+    }
+
+    fn sak(&self) -> Option<u8> {
+        self.uid.map(|_| self.sak)
+    }
+
+    fn self_test(&mut self) -> anyhow::Result<()> {
+        let echo = self.command(CMD_DIAGNOSE, &DIAGNOSE_LINE_TEST)?;
+
+        if echo != DIAGNOSE_LINE_TEST {
+            bail!("PN532 line test returned {:02X?}", echo);
+        }
+
+        Ok(())
+    }
+
+    fn reinit(&mut self) -> anyhow::Result<()> {
+        self.init()
+    }
+
+    fn set_antenna_gain(&mut self, gain: u8) -> anyhow::Result<()> {
+        self.gain = gain.min(7);
+
+        let mut settings = vec![RF_ANALOG_TYPE_A];
+        settings.extend_from_slice(&ANALOG_TYPE_A);
+        settings[1] = (self.gain << 4) | (ANALOG_TYPE_A[0] & 0x0F);
+
+        self.command(CMD_RF_CONFIGURATION, &settings).map(|_| ())
+    }
+
+    // Auto RFCA stays off, we only ever talk to passive cards
+    fn set_rf_field(&mut self, on: bool) -> anyhow::Result<()> {
+        self.field = on;
+
+        self.command(CMD_RF_CONFIGURATION, &[RF_FIELD, on as u8])
+            .map(|_| ())
+    }
+
+    fn halt(&mut self) -> anyhow::Result<()> {
+        // Target 0 releases everything the PN532 has activated
+        self.command(CMD_IN_RELEASE, &[0x00])?;
+
+        Ok(())
+    }
+
+    fn authenticate(
+        &mut self,
+        block: u8,
+        key_type: KeyType,
+        key: &MifareKey,
+    ) -> anyhow::Result<()> {
+        let Some(uid) = self.uid else {
+            bail!("No card selected");
+        };
+
+        let mut tx = vec![
+            match key_type {
+                KeyType::A => iso14443::MF_AUTH_KEY_A,
+                KeyType::B => iso14443::MF_AUTH_KEY_B,
+            },
+            block,
+        ];
+        tx.extend_from_slice(key);
+        tx.extend_from_slice(&uid.as_bytes()[uid.as_bytes().len() - 4..]);
+
+        self.data_exchange(&tx).map(|_| ())
+    }
+
+    fn read_block(&mut self, block: u8) -> anyhow::Result<[u8; 16]> {
+        let data = self.data_exchange(&[iso14443::MF_READ, block])?;
+
+        match data.get(..16) {
+            Some(block) => Ok(block.try_into().unwrap()),
+            None => bail!("PN532 block read too short"),
+        }
+    }
+
+    fn write_block(&mut self, block: u8, data: &[u8; 16]) -> anyhow::Result<()> {
+        let mut tx = vec![iso14443::MF_WRITE, block];
+        tx.extend_from_slice(data);
+
+        self.data_exchange(&tx).map(|_| ())
+    }
+
+    // Crypto1 state lives in the PN532 and is dropped with the target
+    fn stop_crypto(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: Pn532Transport> Transceive for Pn532Reader<T> {
+    // InCommunicateThru sends raw frames, the PN532 adds and checks CRC_A
+    fn transceive(&mut self, frame: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let response = self.command(CMD_IN_COMMUNICATE_THRU, frame)?;
+
+        match response.split_first() {
+            Some((status, data)) if status & 0x3F == 0 => Ok(data.to_vec()),
+            Some((status, _)) => bail!("PN532 InCommunicateThru status {:02X}", status),
+            None => bail!("PN532 InCommunicateThru response empty"),
+        }
+    }
+
+    fn set_timeout_ms(&mut self, ms: u32) -> anyhow::Result<()> {
+        self.response_timeout = RESPONSE_TIMEOUT + Duration::from_millis(ms as u64);
+
+        Ok(())
+    }
+}
+
+pub struct Pn532I2c {
+    driver: I2cDriver<'static>,
+}
+
+impl Pn532I2c {
+    pub fn new() -> anyhow::Result<Pn532I2c> {
+        let sda = unsafe { Gpio5::new() };
+        let scl = unsafe { Gpio6::new() };
+
+        let driver = I2cDriver::new(
+            unsafe { I2C0::new() },
+            sda,
+            scl,
+            &I2cConfig::new().baudrate(100.kHz().into()),
+        )
+        .map_err(|err| anyhow::Error::msg(format!("I2cDriver Error: {}", err)))?;
+
+        Ok(Pn532I2c { driver })
+    }
+}
+
+impl Pn532Transport for Pn532I2c {
+    fn write(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        let timeout = TickType::from(RESPONSE_TIMEOUT).0;
+
+        self.driver
+            .write(PN532_I2C_ADDRESS, bytes, timeout)
+            .map_err(|err| anyhow::Error::msg(format!("PN532 I2C write Error: {}", err)))
+    }
+
+    // Every I2C read starts with a status byte, bit 0 is set once a frame is ready. The status
+    // alone is polled, then the frame is read in one go with the PN532 padding the rest.
+    fn read_frame(&mut self, timeout: Duration) -> anyhow::Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        let ticks = TickType::from(timeout).0;
+
+        loop {
+            let mut status = [0u8; 1];
+
+            if self
+                .driver
+                .read(PN532_I2C_ADDRESS, &mut status, ticks)
+                .is_ok()
+                && status[0] & 0x01 != 0
+            {
+                let mut buf = [0u8; I2C_READ_LEN];
+
+                self.driver
+                    .read(PN532_I2C_ADDRESS, &mut buf, ticks)
+                    .map_err(|err| anyhow::Error::msg(format!("PN532 I2C read Error: {}", err)))?;
+
+                return Ok(buf[1..].to_vec());
+            }
+
+            if Instant::now() >= deadline {
+                bail!("PN532 I2C read timed out");
+            }
+
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+pub struct Pn532Uart {
+    driver: UartDriver<'static>,
+}
+
+impl Pn532Uart {
+    pub fn new() -> anyhow::Result<Pn532Uart> {
+        let tx = unsafe { Gpio17::new() };
+        let rx = unsafe { Gpio18::new() };
+
+        let driver = UartDriver::new(
+            unsafe { UART1::new() },
+            tx,
+            rx,
+            Option::<AnyIOPin>::None,
+            Option::<AnyIOPin>::None,
+            &UartConfig::new().baudrate(115_200.Hz()),
+        )
+        .map_err(|err| anyhow::Error::msg(format!("UartDriver Error: {}", err)))?;
+
+        // HSU wake up: a long preamble gets the PN532 out of power down
+        driver
+            .write(&[
+                0x55, 0x55, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ])
+            .map_err(|err| anyhow::Error::msg(format!("PN532 UART write Error: {}", err)))?;
+
+        Ok(Pn532Uart { driver })
+    }
+
+    fn read_byte(&mut self, deadline: Instant) -> anyhow::Result<u8> {
+        let mut byte = [0u8; 1];
+
+        loop {
+            let ticks = TickType::from(deadline.saturating_duration_since(Instant::now())).0;
+
+            if self.driver.read(&mut byte, ticks).unwrap_or(0) == 1 {
+                return Ok(byte[0]);
+            }
+
+            if Instant::now() >= deadline {
+                bail!("PN532 UART read timed out");
+            }
+        }
+    }
+}
+
+impl Pn532Transport for Pn532Uart {
+    fn write(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.driver
+            .write(bytes)
+            .map(|_| ())
+            .map_err(|err| anyhow::Error::msg(format!("PN532 UART write Error: {}", err)))
+    }
+
+    // UART has no ready flag, so read up to the start code and then as much as LEN says.
+    fn read_frame(&mut self, timeout: Duration) -> anyhow::Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+
+        let mut prev = self.read_byte(deadline)?;
+
+        loop {
+            let byte = self.read_byte(deadline)?;
+
+            if prev == 0x00 && byte == 0xFF {
+                break;
+            }
+
+            prev = byte;
+        }
+
+        let len = self.read_byte(deadline)?;
+        let lcs = self.read_byte(deadline)?;
+
+        let mut frame = vec![0x00, 0x00, 0xFF, len, lcs];
+
+        // ACK has no data and no checksum, only the postamble
+        let remaining = if len == 0 { 1 } else { len as usize + 2 };
+
+        for _ in 0..remaining {
+            frame.push(self.read_byte(deadline)?);
+        }
+
+        Ok(frame)
+    }
+}
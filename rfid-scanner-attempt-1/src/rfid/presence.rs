@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+use super::CardReader;
+use crate::card_uid::CardUid;
+
+// Which card is on a reader from one poll to the next. Only the reader calls in `sense` touch
+// hardware, the rest works from what they saw.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PresenceEvent {
+    Presented(CardUid),
+    // The card left after being held on the reader this long
+    Removed(CardUid, Duration),
+}
+
+struct Held {
+    uid: CardUid,
+    since: Instant,
+    misses: u8,
+}
+
+pub struct Presence {
+    // Polls in a row a card may miss before it counts as removed
+    max_misses: u8,
+    held: Option<Held>,
+}
+
+impl Presence {
+    pub fn new(max_misses: u8) -> Presence {
+        Presence {
+            max_misses,
+            held: None,
+        }
+    }
+
+    pub fn card(&self) -> Option<CardUid> {
+        self.held.as_ref().map(|held| held.uid)
+    }
+
+    // Forgets the card without reporting it removed, for a reader that has gone missing.
+    pub fn clear(&mut self) {
+        self.held = None;
+    }
+
+    // Looks for a card. One that is already known gets halted and woken again, so it answers
+    // even though it was selected last time.
+    pub fn sense<R: CardReader + ?Sized>(&self, reader: &mut R) -> anyhow::Result<Option<CardUid>> {
+        match self.held {
+            Some(_) => reader.halt().and_then(|_| reader.wake()),
+            None => reader.poll(),
+        }
+    }
+
+    // Takes the card seen by the latest poll, `None` for nothing or a read error.
+    pub fn update(&mut self, seen: Option<CardUid>, now: Instant) -> Vec<PresenceEvent> {
+        let mut events = Vec::new();
+
+        if let Some(held) = self.held.as_mut() {
+            if seen == Some(held.uid) {
+                held.misses = 0;
+                return events;
+            }
+
+            held.misses += 1;
+
+            // A different card replaces the old one straight away
+            if seen.is_none() && held.misses < self.max_misses {
+                return events;
+            }
+
+            events.push(PresenceEvent::Removed(
+                held.uid,
+                now.saturating_duration_since(held.since),
+            ));
+
+            self.held = None;
+        }
+
+        if let Some(uid) = seen {
+            self.held = Some(Held {
+                uid,
+                since: now,
+                misses: 0,
+            });
+
+            events.push(PresenceEvent::Presented(uid));
+        }
+
+        events
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+use tokio::time::sleep;
+
+use super::{
+    iso14443,
+    iso14443_4::Transceive,
+    mifare::{KeyType, MifareKey},
+};
+use crate::card_uid::CardUid;
+
+// Everything the polling loop needs from a reader module. Implementations do the chip specific
+// work (register access, frame formats) so the event flow is the same for every backend.
+// `Transceive` carries ISO 14443-4 frames for cards that support it.
+pub trait CardReader: Transceive {
+    // Short name for logs, e.g. "MFRC522"
+    fn name(&self) -> &'static str;
+
+    // Chip/firmware version as reported by the reader
+    fn version(&mut self) -> anyhow::Result<u8>;
+
+    // The chip's own self test, leaving it initialised afterwards.
+    fn self_test(&mut self) -> anyhow::Result<()>;
+
+    // Reset and configure the chip again, keeping the gain and field settings.
+    fn reinit(&mut self) -> anyhow::Result<()>;
+
+    // Receiver gain, 0 (least sensitive) to 7.
+    fn set_antenna_gain(&mut self, gain: u8) -> anyhow::Result<()>;
+
+    fn set_rf_field(&mut self, on: bool) -> anyhow::Result<()>;
+
+    // Look for a card in the field and select it. `Ok(None)` means nothing answered.
+    fn poll(&mut self) -> anyhow::Result<Option<CardUid>>;
+
+    // Like `poll` but also wakes halted cards (WUPA), used to check a card is still there.
+    fn wake(&mut self) -> anyhow::Result<Option<CardUid>>;
+
+    // Waits out one interval of an empty field, returning early if a card may have entered it.
+    // Readers without card detection just wait for the next poll.
+    async fn wait_for_card(&mut self, interval: Duration) -> anyhow::Result<()> {
+        sleep(interval).await;
+
+        Ok(())
+    }
+
+    // SAK of the selected card, `None` when nothing is selected.
+    fn sak(&self) -> Option<u8>;
+
+    // Whether the selected card announced ISO 14443-4 support in its SAK.
+    fn iso14443_4(&self) -> bool {
+        self.sak()
+            .is_some_and(|sak| sak & iso14443::SAK_ISO14443_4 != 0)
+    }
+
+    // Put the selected card to sleep so it stops answering REQA.
+    fn halt(&mut self) -> anyhow::Result<()>;
+
+    // MIFARE Classic: authenticate to the sector holding `block` of the selected card.
+    fn authenticate(&mut self, block: u8, key_type: KeyType, key: &MifareKey)
+        -> anyhow::Result<()>;
+
+    // MIFARE Classic block, or four pages starting at `block` on Ultralight/NTAG.
+    fn read_block(&mut self, block: u8) -> anyhow::Result<[u8; 16]>;
+
+    fn write_block(&mut self, block: u8, data: &[u8; 16]) -> anyhow::Result<()>;
+
+    // Leave the authenticated state so the next card starts clean.
+    fn stop_crypto(&mut self) -> anyhow::Result<()>;
+}
@@ -0,0 +1,143 @@
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike, Weekday};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+use crate::members::{Member, Role};
+
+// Minutes after local midnight, "HH:MM" in JSON. "24:00" is allowed as the end of a day.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    pub fn new(hours: u16, minutes: u16) -> Option<TimeOfDay> {
+        let minute = hours * 60 + minutes;
+
+        (minutes < 60 && minute <= 24 * 60).then_some(TimeOfDay(minute))
+    }
+
+    pub fn of(time: NaiveDateTime) -> TimeOfDay {
+        TimeOfDay((time.hour() * 60 + time.minute()) as u16)
+    }
+}
+
+impl Serialize for TimeOfDay {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:02}:{:02}", self.0 / 60, self.0 % 60))
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+
+        s.split_once(':')
+            .and_then(|(h, m)| TimeOfDay::new(h.parse().ok()?, m.parse().ok()?))
+            .ok_or_else(|| de::Error::custom(format!("Invalid time of day: {:?}", s)))
+    }
+}
+
+// Open from `start` to `end` on each of `days`, or every day when none are given. An end at
+// or before the start carries on past midnight into the next day.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Window {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Weekday>,
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+}
+
+impl Window {
+    fn on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    pub fn is_open(&self, at: NaiveDateTime) -> bool {
+        let day = at.weekday();
+        let minute = TimeOfDay::of(at);
+
+        match self.start < self.end {
+            true => self.on(day) && self.start <= minute && minute < self.end,
+            false => {
+                (self.on(day) && minute >= self.start) || (self.on(day.pred()) && minute < self.end)
+            }
+        }
+    }
+}
+
+// Dates that don't follow the weekly schedules, such as closing over Christmas or an open
+// day. An exception decides by the local date alone, so a window running past midnight into
+// an exception date ends at midnight.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exception {
+    pub from: NaiveDate,
+    pub until: NaiveDate,
+    // Roles it applies to, everyone when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<Role>,
+    // Opening times on those dates instead of the usual ones, none means closed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub windows: Vec<Window>,
+}
+
+impl Exception {
+    fn applies(&self, role: Role, date: NaiveDate) -> bool {
+        (self.from..=self.until).contains(&date)
+            && (self.roles.is_empty() || self.roles.contains(&role))
+    }
+}
+
+// When each member may get in, all in local time:
+//
+//     "weekly": { "evenings": [{ "days": ["Tue", "Thu"], "start": "18:00", "end": "22:00" }] },
+//     "roles": { "member": "evenings" },
+//     "exceptions": [{ "from": "2026-12-24", "until": "2026-12-26", "roles": ["member"] }]
+//
+// A member's own schedule takes priority over their role's. Anyone with neither, keyholders
+// above, has access at any time.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessRules {
+    #[serde(default)]
+    pub weekly: BTreeMap<String, Vec<Window>>,
+    #[serde(default)]
+    pub roles: BTreeMap<Role, String>,
+    #[serde(default)]
+    pub exceptions: Vec<Exception>,
+}
+
+impl AccessRules {
+    fn schedule_for<'a>(&'a self, member: &'a Member) -> Option<&'a String> {
+        member
+            .schedule
+            .as_ref()
+            .or_else(|| self.roles.get(&member.role))
+    }
+
+    // Whether `member` may get in at local time `at`. Without the time only members with no
+    // schedule can.
+    pub fn allows(&self, member: &Member, at: Option<NaiveDateTime>) -> bool {
+        let name = self.schedule_for(member);
+
+        let Some(at) = at else {
+            return name.is_none();
+        };
+
+        let exception = self
+            .exceptions
+            .iter()
+            .find(|exception| exception.applies(member.role, at.date()));
+
+        let windows = match (exception, name) {
+            (Some(exception), _) => &exception.windows,
+            (None, None) => return true,
+            (None, Some(name)) => match self.weekly.get(name) {
+                Some(windows) => windows,
+                None => {
+                    println!("Unknown schedule {:?} for {}", name, member.name);
+                    return false;
+                }
+            },
+        };
+
+        windows.iter().any(|window| window.is_open(at))
+    }
+}
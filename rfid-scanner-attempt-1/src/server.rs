@@ -1,11 +1,21 @@
 use crate::{
     audio,
-    common::{self, SystemMessage},
+    audit::{AuditEntry, AuditLog},
+    card_hash::CardKey,
+    common::{self, AdminAction, SystemMessage},
+    credential::MacKey,
+    hex,
+    list_key::ListKey,
+    rfid::desfire::MasterKey,
     spiffs::Spiffs,
+    uses::UseCounts,
 };
 use embedded_svc::http::Headers;
 use esp_idf_hal::io::{Read, Write};
-use esp_idf_svc::http::{server::EspHttpServer, Method};
+use esp_idf_svc::http::{
+    server::{EspHttpConnection, EspHttpServer, Request},
+    Method,
+};
 use esp_idf_sys::xRingbufferSend;
 use std::{fs, io::Read as _, os::raw::c_void, sync::Arc};
 use tokio::{runtime::Builder, sync::mpsc::Sender};
@@ -18,6 +28,15 @@ const MAX_LEN: usize = 2048 * 1024;
 // Need lots of stack to parse JSON
 const STACK_SIZE: usize = 10240;
 
+// Longest body taken for a key in hex
+const MAX_KEY_LEN: usize = 256;
+
+// Answer to requests that wait for an admin's card
+const CONFIRM_PROMPT: &str = "Hold an admin card on the reader to confirm";
+
+// Audit entries per page, unless the request asks for fewer
+const AUDIT_PAGE: usize = 200;
+
 macro_rules! call_async {
     ($async_code:block) => {
         tokio::runtime::Builder::new_current_thread()
@@ -29,6 +48,30 @@ macro_rules! call_async {
     };
 }
 
+// Body of a request carrying a key as hex, so the key stays out of the URL and the logs
+fn read_key(req: &mut Request<&mut EspHttpConnection>) -> anyhow::Result<Option<Vec<u8>>> {
+    let len = req.content_len().unwrap_or(0) as usize;
+
+    if len > MAX_KEY_LEN {
+        return Ok(None);
+    }
+
+    let mut buf = vec![0; len];
+    req.read_exact(&mut buf)?;
+
+    Ok(std::str::from_utf8(&buf)
+        .ok()
+        .and_then(|body| hex::decode(body.trim())))
+}
+
+// Value of `name` in the query string, as it was sent
+fn query_param<'a>(uri: &'a str, name: &str) -> Option<&'a str> {
+    uri.split_once('?')?.1.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then_some(value)
+    })
+}
+
 pub struct HttpServer {
     tx: Sender<SystemMessage>,
     started: bool,
@@ -60,6 +103,12 @@ impl HttpServer {
         let tx2 = self.tx.clone();
         let tx3 = self.tx.clone();
         let tx4 = self.tx.clone();
+        let tx5 = self.tx.clone();
+        let tx6 = self.tx.clone();
+        let tx7 = self.tx.clone();
+        let tx8 = self.tx.clone();
+        let tx9 = self.tx.clone();
+        let tx10 = self.tx.clone();
 
         let mut server = self.create_server()?;
 
@@ -84,6 +133,95 @@ impl HttpServer {
                 .map(|_| ())
         })?;
 
+        server.fn_handler::<anyhow::Error, _>("/write-credential", Method::Get, move |req| {
+            let member_id = req
+                .uri()
+                .split("?member=")
+                .nth(1)
+                .and_then(|id| id.parse::<u32>().ok());
+
+            let Some(member_id) = member_id else {
+                req.into_status_response(400)?
+                    .write_all("Missing member".as_bytes())?;
+                return Ok(());
+            };
+
+            let action = AdminAction::WriteCredential(member_id);
+
+            call_async!({ tx5.send(SystemMessage::ConfirmAdmin(action)).await });
+
+            req.into_status_response(202)?
+                .write_all(CONFIRM_PROMPT.as_bytes())?;
+
+            Ok(())
+        })?;
+
+        // POST /mac-key with the key in hex, replacing any key cards were written with before
+        server.fn_handler::<anyhow::Error, _>("/mac-key", Method::Post, move |mut req| {
+            let key = read_key(&mut req)?.and_then(|key| MacKey::from_bytes(&key));
+
+            let Some(key) = key else {
+                req.into_status_response(400)?
+                    .write_all("Expected 16 to 64 bytes in hex".as_bytes())?;
+                return Ok(());
+            };
+
+            let action = AdminAction::ProvisionMacKey(key);
+
+            call_async!({ tx6.send(SystemMessage::ConfirmAdmin(action)).await });
+
+            req.into_status_response(202)?
+                .write_all(CONFIRM_PROMPT.as_bytes())?;
+
+            Ok(())
+        })?;
+
+        // POST /desfire-key with the AES-128 master key the card keys are diversified from, in hex
+        server.fn_handler::<anyhow::Error, _>("/desfire-key", Method::Post, move |mut req| {
+            let key = read_key(&mut req)?.and_then(|key| MasterKey::from_bytes(&key));
+
+            let Some(key) = key else {
+                req.into_status_response(400)?
+                    .write_all("Expected 16 bytes in hex".as_bytes())?;
+                return Ok(());
+            };
+
+            let action = AdminAction::ProvisionDesfireKey(key);
+
+            call_async!({ tx7.send(SystemMessage::ConfirmAdmin(action)).await });
+
+            req.into_status_response(202)?
+                .write_all(CONFIRM_PROMPT.as_bytes())?;
+
+            Ok(())
+        })?;
+
+        // /reader?gain=0..7 or /reader?field=on|off
+        server.fn_handler::<anyhow::Error, _>("/reader", Method::Get, move |req| {
+            let query = req.uri().split('?').nth(1).unwrap_or("").to_string();
+
+            let msg = match query.split_once('=') {
+                Some(("gain", gain)) => gain.parse::<u8>().ok().map(SystemMessage::SetAntennaGain),
+                Some(("field", "on")) => Some(SystemMessage::SetRfField(true)),
+                Some(("field", "off")) => Some(SystemMessage::SetRfField(false)),
+                _ => None,
+            };
+
+            let Some(msg) = msg else {
+                req.into_status_response(400)?
+                    .write_all("Expected gain=0..7 or field=on|off".as_bytes())?;
+                return Ok(());
+            };
+
+            call_async!({ tx8.send(msg).await });
+
+            AuditLog::record(AuditEntry::admin(format!("reader {}", query)));
+
+            req.into_ok_response()?.write_all("OK".as_bytes())?;
+
+            Ok(())
+        })?;
+
         server.fn_handler::<anyhow::Error, _>("/update", Method::Post, move |mut req| {
             let len = req.content_len().unwrap_or(0) as usize;
 
@@ -98,6 +236,8 @@ impl HttpServer {
 
             let mut resp = req.into_ok_response()?;
 
+            AuditLog::record(AuditEntry::admin(format!("firmware update, {} bytes", len)));
+
             call_async!({ tx2.send(SystemMessage::OnOtaBuffer(Arc::new(buf))).await });
 
             resp.write_all(format!("Done {}", len).as_bytes())?;
@@ -118,6 +258,18 @@ impl HttpServer {
         server.fn_handler::<anyhow::Error, _>("/write-file", Method::Post, move |mut req| {
             let file_name = req.uri().split("?name=").nth(1).unwrap().to_string();
 
+            if AuditLog::is_log_file(&file_name) {
+                req.into_status_response(403)?
+                    .write_all("The audit log can't be written".as_bytes())?;
+                return Ok(());
+            }
+
+            if UseCounts::is_uses_file(&file_name) {
+                req.into_status_response(403)?
+                    .write_all("Guest pass uses can't be written".as_bytes())?;
+                return Ok(());
+            }
+
             let len = req.content_len().unwrap_or(0) as usize;
 
             if len > MAX_LEN {
@@ -131,6 +283,11 @@ impl HttpServer {
 
             let mut resp = req.into_ok_response()?;
 
+            AuditLog::record(AuditEntry::admin(format!(
+                "write {}, {} bytes",
+                file_name, len
+            )));
+
             Spiffs::write_binary(file_name, buf);
 
             call_async!({
@@ -143,6 +300,98 @@ impl HttpServer {
             Ok(())
         })?;
 
+        // POST /list-key with the public key in hex, unless the firmware has a key built in
+        server.fn_handler::<anyhow::Error, _>("/list-key", Method::Post, move |mut req| {
+            if common::MEMBERS_PUBLIC_KEY.is_some() {
+                req.into_status_response(409)?
+                    .write_all("Member list key is built into the firmware".as_bytes())?;
+                return Ok(());
+            }
+
+            let key = read_key(&mut req)?.and_then(|key| ListKey::from_bytes(&key).ok());
+
+            let Some(key) = key else {
+                req.into_status_response(400)?
+                    .write_all("Expected a 32 byte public key in hex".as_bytes())?;
+                return Ok(());
+            };
+
+            let action = AdminAction::ProvisionListKey(key);
+
+            call_async!({ tx9.send(SystemMessage::ConfirmAdmin(action)).await });
+
+            req.into_status_response(202)?
+                .write_all(CONFIRM_PROMPT.as_bytes())?;
+
+            Ok(())
+        })?;
+
+        // POST /card-key with the secret that card UIDs are hashed with, in hex
+        server.fn_handler::<anyhow::Error, _>("/card-key", Method::Post, move |mut req| {
+            let key = read_key(&mut req)?.and_then(|key| CardKey::from_bytes(&key));
+
+            let Some(key) = key else {
+                req.into_status_response(400)?
+                    .write_all("Expected 16 to 64 bytes in hex".as_bytes())?;
+                return Ok(());
+            };
+
+            let action = AdminAction::ProvisionCardKey(key);
+
+            call_async!({ tx10.send(SystemMessage::ConfirmAdmin(action)).await });
+
+            req.into_status_response(202)?
+                .write_all(CONFIRM_PROMPT.as_bytes())?;
+
+            Ok(())
+        })?;
+
+        // /audit?from=<unix time>&until=<unix time>&limit=<n>&format=csv|jsonl, oldest first.
+        // A page cut short carries X-Next-From, the `from` for the next one. That can have a
+        // count after the time, see `AuditCursor`.
+        server.fn_handler::<anyhow::Error, _>("/audit", Method::Get, |req| {
+            let uri = req.uri().to_string();
+
+            let from = query_param(&uri, "from").and_then(|cursor| cursor.parse().ok());
+            let until = query_param(&uri, "until").and_then(|time| time.parse().ok());
+            let limit = query_param(&uri, "limit")
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(AUDIT_PAGE)
+                .clamp(1, AUDIT_PAGE);
+            let csv = query_param(&uri, "format") == Some("csv");
+
+            let (entries, next) = AuditLog::query(from, until, limit)?;
+            let next = next.map(|next| next.to_string());
+
+            let content_type = match csv {
+                true => "text/csv",
+                false => "application/x-ndjson",
+            };
+
+            let mut headers = vec![("Content-Type", content_type)];
+
+            if let Some(next) = &next {
+                headers.push(("X-Next-From", next.as_str()));
+            }
+
+            let mut resp = req.into_response(200, None, &headers)?;
+
+            if csv {
+                resp.write_all(AuditEntry::CSV_HEADER.as_bytes())?;
+            }
+
+            for entry in entries {
+                let line = match csv {
+                    true => entry.to_csv(),
+                    false => serde_json::to_string(&entry)? + "\n",
+                };
+
+                resp.write_all(line.as_bytes())?;
+            }
+
+            Ok(())
+        })?;
+
         // ffmpeg -i denybeep2.mp3 -ar 16000 -ac 1 -sample_fmt s16 spiffs/denied.wav
 
         server.fn_handler("/play", Method::Get, move |req| {
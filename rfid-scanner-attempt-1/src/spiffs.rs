@@ -2,6 +2,7 @@ use esp_idf_sys::{esp_spiffs_check, esp_vfs_spiffs_conf_t, esp_vfs_spiffs_regist
 use std::{
     ffi::CString,
     fs::{self},
+    time::SystemTime,
 };
 
 pub struct Spiffs {}
@@ -59,4 +60,39 @@ impl Spiffs {
     pub fn write_binary(path: String, contents: Vec<u8>) {
         fs::write(format!("/spiffs/{}", path), contents).unwrap();
     }
+
+    // Modification time and size, enough to notice a file has been replaced
+    pub fn stamp(path: &str) -> Option<(Option<SystemTime>, u64)> {
+        let metadata = fs::metadata(format!("/spiffs/{}", path)).ok()?;
+
+        Some((metadata.modified().ok(), metadata.len()))
+    }
+
+    pub fn open(path: &str) -> anyhow::Result<fs::File> {
+        fs::File::open(format!("/spiffs/{}", path))
+            .map_err(|err| anyhow::Error::msg(format!("open Error: {}", err)))
+    }
+
+    pub fn append(path: &str) -> anyhow::Result<fs::File> {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(format!("/spiffs/{}", path))
+            .map_err(|err| anyhow::Error::msg(format!("append Error: {}", err)))
+    }
+
+    pub fn create(path: &str) -> anyhow::Result<fs::File> {
+        fs::File::create(format!("/spiffs/{}", path))
+            .map_err(|err| anyhow::Error::msg(format!("create Error: {}", err)))
+    }
+
+    pub fn remove(path: &str) -> anyhow::Result<()> {
+        fs::remove_file(format!("/spiffs/{}", path))
+            .map_err(|err| anyhow::Error::msg(format!("remove Error: {}", err)))
+    }
+
+    pub fn rename(from: &str, to: &str) -> anyhow::Result<()> {
+        fs::rename(format!("/spiffs/{}", from), format!("/spiffs/{}", to))
+            .map_err(|err| anyhow::Error::msg(format!("rename Error: {}", err)))
+    }
 }
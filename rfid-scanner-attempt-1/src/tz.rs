@@ -0,0 +1,314 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
+use std::{fmt, str::FromStr, time::SystemTime};
+
+// Any earlier time means SNTP hasn't set the clock yet (2024-01-01)
+const CLOCK_SET_AFTER: i64 = 1_704_067_200;
+
+// Used when a zone has a DST name but no rules, as glibc does
+const DEFAULT_DST_START: Rule = Rule {
+    date: RuleDate::MonthWeekDay {
+        month: 3,
+        week: 2,
+        weekday: 0,
+    },
+    time: 2 * 3600,
+};
+const DEFAULT_DST_END: Rule = Rule {
+    date: RuleDate::MonthWeekDay {
+        month: 11,
+        week: 1,
+        weekday: 0,
+    },
+    time: 2 * 3600,
+};
+
+// Now in UTC, `None` until the clock has been set.
+pub fn utc_now() -> Option<DateTime<Utc>> {
+    let now = DateTime::<Utc>::from(SystemTime::now());
+
+    (now.timestamp() > CLOCK_SET_AFTER).then_some(now)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TzError(pub String);
+
+impl fmt::Display for TzError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid TZ string: {}", self.0)
+    }
+}
+
+impl std::error::Error for TzError {}
+
+// A timezone in the POSIX TZ format ESP-IDF uses, e.g. "GMT0BST,M3.5.0/1,M10.5.0" for the UK.
+// Offsets in the string count west of UTC, here they are stored in seconds east of UTC.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PosixTz {
+    std_offset: i32,
+    dst: Option<Dst>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Dst {
+    offset: i32,
+    start: Rule,
+    end: Rule,
+}
+
+// A transition: a day of the year and the local time it happens at, which can be negative or
+// past midnight.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Rule {
+    date: RuleDate,
+    time: i32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RuleDate {
+    // Jn: day 1 to 365, February 29th is never counted
+    Julian(u16),
+    // n: day 0 to 365, counting February 29th
+    Zero(u16),
+    // Mm.w.d: weekday d (0 is Sunday) of week w (5 is the last) of month m
+    MonthWeekDay { month: u8, week: u8, weekday: u8 },
+}
+
+impl RuleDate {
+    fn day(&self, year: i32) -> NaiveDate {
+        let jan1 = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+
+        match *self {
+            RuleDate::Julian(n) => {
+                let leap_day = jan1.leap_year() && n >= 60;
+
+                jan1 + Duration::days(n as i64 - 1 + leap_day as i64)
+            }
+            RuleDate::Zero(n) => {
+                let last = NaiveDate::from_ymd_opt(year, 12, 31).unwrap();
+
+                (jan1 + Duration::days(n as i64)).min(last)
+            }
+            RuleDate::MonthWeekDay {
+                month,
+                week,
+                weekday,
+            } => {
+                let first = NaiveDate::from_ymd_opt(year, month as u32, 1).unwrap();
+                let first_weekday = first.weekday().num_days_from_sunday() as i64;
+                let mut day = first
+                    + Duration::days((weekday as i64 - first_weekday).rem_euclid(7))
+                    + Duration::days(7 * (week as i64 - 1));
+
+                // Week 5 means the last one, which may be the fourth
+                while day.month() != month as u32 {
+                    day -= Duration::days(7);
+                }
+
+                day
+            }
+        }
+    }
+}
+
+impl Rule {
+    // The moment of the transition in `year`, for a rule given in local time at `offset`.
+    fn utc(&self, year: i32, offset: i32) -> i64 {
+        let midnight = self.date.day(year).and_hms_opt(0, 0, 0).unwrap();
+
+        midnight.and_utc().timestamp() + self.time as i64 - offset as i64
+    }
+}
+
+impl PosixTz {
+    pub fn utc() -> PosixTz {
+        PosixTz {
+            std_offset: 0,
+            dst: None,
+        }
+    }
+
+    // Seconds east of UTC in force at `utc`.
+    pub fn offset_at(&self, utc: DateTime<Utc>) -> i32 {
+        let Some(dst) = &self.dst else {
+            return self.std_offset;
+        };
+
+        let t = utc.timestamp();
+        let year = (utc + Duration::seconds(self.std_offset as i64)).year();
+
+        // The start is given in standard time and the end in daylight time
+        let start = dst.start.utc(year, self.std_offset);
+        let end = dst.end.utc(year, dst.offset);
+
+        // Southern hemisphere zones have daylight time over the new year
+        let in_dst = match start < end {
+            true => start <= t && t < end,
+            false => t >= start || t < end,
+        };
+
+        match in_dst {
+            true => dst.offset,
+            false => self.std_offset,
+        }
+    }
+
+    pub fn to_local(&self, utc: DateTime<Utc>) -> NaiveDateTime {
+        (utc + Duration::seconds(self.offset_at(utc) as i64)).naive_utc()
+    }
+}
+
+impl FromStr for PosixTz {
+    type Err = TzError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { s, pos: 0 };
+        let err = || TzError(s.to_string());
+
+        parser.name().ok_or_else(err)?;
+        let std_offset = -parser.offset(24).ok_or_else(err)?;
+
+        if parser.done() {
+            return Ok(PosixTz {
+                std_offset,
+                dst: None,
+            });
+        }
+
+        parser.name().ok_or_else(err)?;
+
+        let offset = match parser.peek() {
+            None | Some(b',') => std_offset + 3600,
+            _ => -parser.offset(24).ok_or_else(err)?,
+        };
+
+        let (start, end) = match parser.eat(b',') {
+            true => {
+                let start = parser.rule().ok_or_else(err)?;
+
+                if !parser.eat(b',') {
+                    return Err(err());
+                }
+
+                (start, parser.rule().ok_or_else(err)?)
+            }
+            false => (DEFAULT_DST_START, DEFAULT_DST_END),
+        };
+
+        if !parser.done() {
+            return Err(err());
+        }
+
+        Ok(PosixTz {
+            std_offset,
+            dst: Some(Dst { offset, start, end }),
+        })
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.as_bytes().get(self.pos).copied()
+    }
+
+    fn done(&self) -> bool {
+        self.pos == self.s.len()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        let found = self.peek() == Some(b);
+
+        if found {
+            self.pos += 1;
+        }
+
+        found
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &str {
+        let start = self.pos;
+
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+
+        &self.s[start..self.pos]
+    }
+
+    fn number(&mut self) -> Option<i32> {
+        self.take_while(|b| b.is_ascii_digit()).parse().ok()
+    }
+
+    // Three or more letters, or anything but '>' in angle brackets ("<+0330>")
+    fn name(&mut self) -> Option<()> {
+        let name = match self.eat(b'<') {
+            true => {
+                let name = self.take_while(|b| b != b'>').len();
+                self.eat(b'>').then_some(name)?
+            }
+            false => self.take_while(|b| b.is_ascii_alphabetic()).len(),
+        };
+
+        (name >= 3).then_some(())
+    }
+
+    // [+-]hh[:mm[:ss]] in seconds, positive west of UTC for zone offsets
+    fn offset(&mut self, max_hours: i32) -> Option<i32> {
+        let sign = match self.peek() {
+            Some(b'-') => -1,
+            _ => 1,
+        };
+
+        if matches!(self.peek(), Some(b'+' | b'-')) {
+            self.pos += 1;
+        }
+
+        let hours = self.number()?;
+        let minutes = match self.eat(b':') {
+            true => self.number()?,
+            false => 0,
+        };
+        let seconds = match self.eat(b':') {
+            true => self.number()?,
+            false => 0,
+        };
+
+        if hours > max_hours || minutes > 59 || seconds > 59 {
+            return None;
+        }
+
+        Some(sign * (hours * 3600 + minutes * 60 + seconds))
+    }
+
+    fn rule(&mut self) -> Option<Rule> {
+        let date = if self.eat(b'J') {
+            RuleDate::Julian(self.number().filter(|n| (1..=365).contains(n))? as u16)
+        } else if self.eat(b'M') {
+            let month = self.number().filter(|m| (1..=12).contains(m))?;
+            self.eat(b'.').then_some(())?;
+            let week = self.number().filter(|w| (1..=5).contains(w))?;
+            self.eat(b'.').then_some(())?;
+            let weekday = self.number().filter(|d| (0..=6).contains(d))?;
+
+            RuleDate::MonthWeekDay {
+                month: month as u8,
+                week: week as u8,
+                weekday: weekday as u8,
+            }
+        } else {
+            RuleDate::Zero(self.number().filter(|n| (0..=365).contains(n))? as u16)
+        };
+
+        // Transition times may run from -167 to 167 hours (RFC 8536)
+        let time = match self.eat(b'/') {
+            true => self.offset(167)?,
+            false => 2 * 3600,
+        };
+
+        Some(Rule { date, time })
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::NaiveDateTime;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+use crate::{
+    card_hash::CardId,
+    members::{Member, MemberIndex},
+    spiffs::Spiffs,
+};
+
+const USES_FILE: &str = "uses.json";
+const NEW_USES_FILE: &str = "uses.json.new";
+
+// How many times each guest pass has been used, in uses.json. It is kept apart from
+// members.json so a signed or synced list doesn't hand uses back, and written on every use so a
+// restart doesn't either.
+//
+// A pass is its card as the list has it (the hash, with a card key) and its start date, so a
+// new guest given the same name or a pass handed out again with a new `valid_from` starts
+// from nothing.
+pub struct UseCounts {
+    used: BTreeMap<String, u32>,
+}
+
+impl UseCounts {
+    // The new file is only there if power was lost while saving, and is read first as it has
+    // the latest count. One cut short doesn't parse, leaving the old one.
+    pub fn load() -> UseCounts {
+        let used = [NEW_USES_FILE, USES_FILE]
+            .iter()
+            .find_map(|path| {
+                let json = Spiffs::read_string(path.to_string()).ok()?;
+
+                serde_json::from_str(&json).ok()
+            })
+            .unwrap_or_default();
+
+        UseCounts { used }
+    }
+
+    fn pass(card: CardId, member: &Member) -> String {
+        match member.valid_from {
+            Some(from) => format!("{} {}", card, from),
+            None => card.to_string(),
+        }
+    }
+
+    // None for passes that don't run out
+    pub fn remaining(&self, card: CardId, member: &Member) -> Option<u32> {
+        let uses = member.remaining_uses?;
+        let used = self
+            .used
+            .get(&UseCounts::pass(card, member))
+            .copied()
+            .unwrap_or(0);
+
+        Some(uses.saturating_sub(used))
+    }
+
+    // Counts one use, returning how many are left
+    pub fn record(&mut self, card: CardId, member: &Member) -> anyhow::Result<Option<u32>> {
+        if member.remaining_uses.is_none() {
+            return Ok(None);
+        }
+
+        *self.used.entry(UseCounts::pass(card, member)).or_default() += 1;
+        self.save()?;
+
+        Ok(self.remaining(card, member))
+    }
+
+    // Forgets passes that are no longer in the list, or have expired. Without the time only
+    // removed ones can be told apart.
+    pub fn prune(&mut self, index: &MemberIndex, now: Option<NaiveDateTime>) -> anyhow::Result<()> {
+        let live = index
+            .entries()
+            .filter(|(_, member)| member.remaining_uses.is_some())
+            .filter(|(_, member)| now.map_or(true, |now| !member.has_expired(now)))
+            .map(|(entry, member)| UseCounts::pass(entry.card, member))
+            .collect::<BTreeSet<_>>();
+
+        let count = self.used.len();
+
+        self.used.retain(|pass, _| live.contains(pass));
+
+        if self.used.len() == count {
+            return Ok(());
+        }
+
+        println!("Dropped {} guest pass counts", count - self.used.len());
+
+        self.save()
+    }
+
+    // uses.json and the copy it is saved through, which only the firmware may write. Leading
+    // slashes and dots are ignored, as for the audit log.
+    pub fn is_uses_file(name: &str) -> bool {
+        let name = name.trim_start_matches(['/', '.']);
+
+        name == USES_FILE || name == NEW_USES_FILE
+    }
+
+    // SPIFFS can't rename over a file, so the old one goes first
+    fn save(&self) -> anyhow::Result<()> {
+        let mut file = Spiffs::create(NEW_USES_FILE)?;
+        file.write_all(serde_json::to_string(&self.used)?.as_bytes())?;
+        drop(file);
+
+        if Spiffs::stamp(USES_FILE).is_some() {
+            Spiffs::remove(USES_FILE)?;
+        }
+
+        Spiffs::rename(NEW_USES_FILE, USES_FILE)
+    }
+}
@@ -0,0 +1,250 @@
+pub mod frame;
+
+use esp_idf_hal::{
+    delay::Ets,
+    gpio::{Gpio10, Gpio11, Gpio12, Gpio13, Input, InterruptType, Output, Pin, PinDriver, Pull},
+};
+use frame::{WiegandFormat, WiegandFrame};
+use std::{
+    sync::{
+        atomic::{AtomicI64, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::sleep,
+};
+
+use crate::{
+    card_uid::CardUid,
+    common::{self, SystemMessage},
+};
+
+// Line timing: D0/D1 idle high and are pulled low for one bit at a time
+const PULSE_US: u32 = 50;
+const BIT_INTERVAL: Duration = Duration::from_millis(2);
+
+// Silence that ends an incoming frame
+const FRAME_GAP_US: i64 = 25_000;
+const INPUT_POLL: Duration = Duration::from_millis(10);
+
+// Bits seen on the input lines, filled in from the GPIO interrupts.
+#[derive(Default)]
+struct Capture {
+    bits: AtomicU64,
+    count: AtomicU32,
+    last_us: AtomicI64,
+}
+
+impl Capture {
+    fn push(&self, bit: bool) {
+        let _ = self
+            .bits
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((bits << 1) | bit as u64)
+            });
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.last_us.store(
+            unsafe { esp_idf_sys::esp_timer_get_time() },
+            Ordering::Relaxed,
+        );
+    }
+
+    // The bits of a finished frame, MSB first, once the line has been quiet long enough.
+    fn take(&self) -> Option<Vec<bool>> {
+        let count = self.count.load(Ordering::Relaxed) as usize;
+        let quiet =
+            unsafe { esp_idf_sys::esp_timer_get_time() } - self.last_us.load(Ordering::Relaxed);
+
+        if count == 0 || quiet < FRAME_GAP_US {
+            return None;
+        }
+
+        self.count.store(0, Ordering::Relaxed);
+        let bits = self.bits.swap(0, Ordering::Relaxed);
+
+        Some(
+            (0..count.min(64))
+                .rev()
+                .map(|i| (bits >> i) & 1 != 0)
+                .collect(),
+        )
+    }
+}
+
+struct WiegandOutput {
+    format: WiegandFormat,
+    facility: u16,
+    d0: PinDriver<'static, Gpio10, Output>,
+    d1: PinDriver<'static, Gpio11, Output>,
+}
+
+struct WiegandInput {
+    capture: Arc<Capture>,
+    // Kept so the interrupts stay subscribed
+    _d0: PinDriver<'static, Gpio12, Input>,
+    _d1: PinDriver<'static, Gpio13, Input>,
+}
+
+// Wiegand towards the alarm panel (cards read here go out on GPIO 10/11) and from an external
+// reader (GPIO 12/13 feed `OnCard`, and `OnKey` if it has a keypad). Either direction can be left off.
+pub struct WiegandService {
+    tx: Sender<SystemMessage>,
+    output: Option<WiegandOutput>,
+    input: Option<WiegandInput>,
+    cards_tx: Sender<CardUid>,
+    cards_rx: Receiver<CardUid>,
+}
+
+impl WiegandService {
+    pub fn new(
+        tx: Sender<SystemMessage>,
+        output: Option<(WiegandFormat, u16)>,
+        input: bool,
+    ) -> anyhow::Result<WiegandService> {
+        let output = match output {
+            Some((format, facility)) => Some(WiegandService::output(format, facility)?),
+            None => None,
+        };
+
+        let input = match input {
+            true => Some(WiegandService::input()?),
+            false => None,
+        };
+
+        let (cards_tx, cards_rx) = mpsc::channel(4);
+
+        Ok(WiegandService {
+            tx,
+            output,
+            input,
+            cards_tx,
+            cards_rx,
+        })
+    }
+
+    fn output(format: WiegandFormat, facility: u16) -> anyhow::Result<WiegandOutput> {
+        let mut d0 = PinDriver::output(unsafe { Gpio10::new() })?;
+        let mut d1 = PinDriver::output(unsafe { Gpio11::new() })?;
+
+        d0.set_high()?;
+        d1.set_high()?;
+
+        Ok(WiegandOutput {
+            format,
+            facility,
+            d0,
+            d1,
+        })
+    }
+
+    fn input() -> anyhow::Result<WiegandInput> {
+        let capture = Arc::new(Capture::default());
+
+        let mut d0 = PinDriver::input(unsafe { Gpio12::new() })?;
+        let mut d1 = PinDriver::input(unsafe { Gpio13::new() })?;
+
+        d0.set_pull(Pull::Up)?;
+        d1.set_pull(Pull::Up)?;
+        d0.set_interrupt_type(InterruptType::NegEdge)?;
+        d1.set_interrupt_type(InterruptType::NegEdge)?;
+
+        // The HAL disables a pin's interrupt each time it fires, so the handlers re-enable it
+        let (capture0, pin0) = (capture.clone(), d0.pin());
+        let (capture1, pin1) = (capture.clone(), d1.pin());
+
+        unsafe {
+            d0.subscribe(move || {
+                capture0.push(false);
+                esp_idf_sys::gpio_intr_enable(pin0);
+            })?;
+            d1.subscribe(move || {
+                capture1.push(true);
+                esp_idf_sys::gpio_intr_enable(pin1);
+            })?;
+        }
+
+        d0.enable_interrupt()?;
+        d1.enable_interrupt()?;
+
+        Ok(WiegandInput {
+            capture,
+            _d0: d0,
+            _d1: d1,
+        })
+    }
+
+    // Cards sent here are repeated on the output, if there is one.
+    pub fn cards(&self) -> Sender<CardUid> {
+        self.cards_tx.clone()
+    }
+
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        loop {
+            while let Ok(uid) = self.cards_rx.try_recv() {
+                self.send(&uid).await?;
+            }
+
+            if let Some(input) = &self.input {
+                if let Some(bits) = input.capture.take() {
+                    // Keypads on the same lines send each key on its own
+                    if let Some(key) = frame::decode_key(&bits) {
+                        self.tx.send(SystemMessage::OnKey(key)).await?;
+                        continue;
+                    }
+
+                    match WiegandFrame::decode(&bits) {
+                        Ok(frame) => {
+                            println!("Wiegand in: {} {}", frame.facility, frame.card);
+
+                            self.tx
+                                .send(SystemMessage::OnCard(
+                                    frame.to_uid(),
+                                    common::WIEGAND_READER,
+                                ))
+                                .await?;
+                        }
+                        Err(err) => println!("Wiegand in: {err}"),
+                    }
+                }
+            }
+
+            sleep(INPUT_POLL).await;
+        }
+    }
+
+    async fn send(&mut self, uid: &CardUid) -> anyhow::Result<()> {
+        let Some(output) = self.output.as_mut() else {
+            return Ok(());
+        };
+
+        let Some(frame) = WiegandFrame::from_uid(output.format, output.facility, uid) else {
+            println!(
+                "Wiegand facility {} too large for {:?}",
+                output.facility, output.format
+            );
+            return Ok(());
+        };
+
+        for bit in frame.encode() {
+            match bit {
+                false => pulse(&mut output.d0)?,
+                true => pulse(&mut output.d1)?,
+            }
+
+            sleep(BIT_INTERVAL).await;
+        }
+
+        Ok(())
+    }
+}
+
+fn pulse<P: Pin>(line: &mut PinDriver<'static, P, Output>) -> anyhow::Result<()> {
+    line.set_low()?;
+    Ets::delay_us(PULSE_US);
+    line.set_high()?;
+
+    Ok(())
+}
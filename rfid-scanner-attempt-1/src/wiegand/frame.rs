@@ -0,0 +1,168 @@
+use std::fmt;
+
+use crate::card_uid::CardUid;
+
+// Wiegand 26 and 34 bit frames: leading even parity over the first half of the data bits,
+// facility code, card number, trailing odd parity over the second half. Bits go out MSB first.
+// No hardware in here.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WiegandFormat {
+    // 8 bit facility, 16 bit card number
+    W26,
+    // 16 bit facility, 16 bit card number
+    W34,
+}
+
+impl WiegandFormat {
+    pub fn from_len(len: usize) -> Option<WiegandFormat> {
+        match len {
+            26 => Some(WiegandFormat::W26),
+            34 => Some(WiegandFormat::W34),
+            _ => None,
+        }
+    }
+
+    pub fn len(self) -> usize {
+        match self {
+            WiegandFormat::W26 => 26,
+            WiegandFormat::W34 => 34,
+        }
+    }
+
+    fn data_bits(self) -> usize {
+        self.len() - 2
+    }
+
+    fn max_facility(self) -> u16 {
+        match self {
+            WiegandFormat::W26 => 0xFF,
+            WiegandFormat::W34 => 0xFFFF,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WiegandError {
+    Length(usize),
+    Parity,
+}
+
+impl fmt::Display for WiegandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WiegandError::Length(len) => write!(f, "Unsupported Wiegand frame of {} bits", len),
+            WiegandError::Parity => write!(f, "Wiegand parity error"),
+        }
+    }
+}
+
+impl std::error::Error for WiegandError {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WiegandFrame {
+    pub format: WiegandFormat,
+    pub facility: u16,
+    pub card: u16,
+}
+
+impl WiegandFrame {
+    // `None` if the facility code doesn't fit the format.
+    pub fn new(format: WiegandFormat, facility: u16, card: u16) -> Option<WiegandFrame> {
+        if facility > format.max_facility() {
+            return None;
+        }
+
+        Some(WiegandFrame {
+            format,
+            facility,
+            card,
+        })
+    }
+
+    // Only 16 bits of the UID fit: the first two bytes, which are the low 16 bits of its
+    // decimal number.
+    pub fn from_uid(format: WiegandFormat, facility: u16, uid: &CardUid) -> Option<WiegandFrame> {
+        let bytes = uid.as_bytes();
+
+        WiegandFrame::new(format, facility, u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    // Facility and card number as one value, the way most panels display it.
+    pub fn value(&self) -> u32 {
+        ((self.facility as u32) << 16) | self.card as u32
+    }
+
+    // A 4 byte UID whose decimal number is `value()`, so codes.txt can list Wiegand cards too.
+    pub fn to_uid(&self) -> CardUid {
+        CardUid::from_bytes(&self.value().to_le_bytes()).unwrap()
+    }
+
+    pub fn encode(&self) -> Vec<bool> {
+        let n = self.format.data_bits();
+        let data = self.value();
+
+        let data_bits = (0..n)
+            .rev()
+            .map(|i| (data >> i) & 1 != 0)
+            .collect::<Vec<_>>();
+
+        let (first, second) = data_bits.split_at(n / 2);
+
+        let mut bits = Vec::with_capacity(self.format.len());
+        bits.push(ones(first) % 2 == 1);
+        bits.extend_from_slice(&data_bits);
+        bits.push(ones(second) % 2 == 0);
+        bits
+    }
+
+    pub fn decode(bits: &[bool]) -> Result<WiegandFrame, WiegandError> {
+        let Some(format) = WiegandFormat::from_len(bits.len()) else {
+            return Err(WiegandError::Length(bits.len()));
+        };
+
+        let n = format.data_bits();
+        let data_bits = &bits[1..=n];
+        let (first, second) = data_bits.split_at(n / 2);
+
+        // Even parity including the leading bit, odd including the trailing one
+        if (ones(first) + bits[0] as usize) % 2 != 0
+            || (ones(second) + bits[n + 1] as usize) % 2 != 1
+        {
+            return Err(WiegandError::Parity);
+        }
+
+        let data = data_bits
+            .iter()
+            .fold(0u32, |acc, bit| (acc << 1) | *bit as u32);
+
+        Ok(WiegandFrame {
+            format,
+            facility: (data >> 16) as u16,
+            card: data as u16,
+        })
+    }
+}
+
+fn ones(bits: &[bool]) -> usize {
+    bits.iter().filter(|bit| **bit).count()
+}
+
+// A key press from a Wiegand keypad: 4 bits, or 8 with the high nibble the inverse of the low
+// one. 10 is '*' and 11 is '#'.
+pub fn decode_key(bits: &[bool]) -> Option<char> {
+    let value = bits.iter().fold(0u8, |acc, bit| (acc << 1) | *bit as u8);
+
+    let key = match bits.len() {
+        4 => value,
+        8 if value >> 4 == !value & 0x0F => value & 0x0F,
+        _ => return None,
+    };
+
+    match key {
+        0..=9 => char::from_digit(key as u32, 10),
+        10 => Some('*'),
+        11 => Some('#'),
+        _ => None,
+    }
+}